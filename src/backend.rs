@@ -0,0 +1,385 @@
+use crate::error::{Error, Result};
+use crate::track::Track;
+use rodio::{Decoder, Device, Sample, Sink, Source};
+use std::cmp::{max, min};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub trait AudioBackend: Send {
+    // Get ready to play, e.g. by opening the output device.
+    // This is called before the backend is handed over to the player thread,
+    // so that problems can be reported to whoever started playing.
+    fn open(&mut self) -> Result<()>;
+
+    // Stop anything currently playing and start playing the given track.
+    // If the track can't be played, nothing will be playing afterwards.
+    fn play_track(&mut self, track: &Track) -> Result<()>;
+
+    // Stop playing, discarding whatever was playing.
+    fn stop(&mut self);
+
+    // Pause playback, keeping our place in the current track.
+    fn pause(&mut self);
+
+    // Carry on playing from wherever we were paused.
+    fn resume(&mut self);
+
+    // Jump to the given position in the current track, staying paused if we
+    // were paused.
+    fn seek(&mut self, position: Duration);
+
+    // How far through the current track we are.
+    fn position(&self) -> Duration;
+
+    // How long the current track is, if we know.
+    fn duration(&self) -> Option<Duration>;
+
+    // True when there is nothing (left) to play.
+    fn is_empty(&self) -> bool;
+}
+
+fn decode_track(track: &Track) -> Result<Decoder<BufReader<File>>> {
+    let path = PathBuf::from(&track.path);
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(err) => return Err(Error::io(&path, err)),
+    };
+    match rodio::Decoder::new(BufReader::new(file)) {
+        Ok(src) => Ok(src),
+        // Rodio only fails here when it doesn't recognise the format
+        Err(_) => Err(Error::UnsupportedFormat(path)),
+    }
+}
+
+fn get_samples_per_second<S>(source: &S) -> u64
+where
+    S: Source,
+    S::Item: Sample,
+{
+    max(1, source.sample_rate() as u64 * source.channels() as u64)
+}
+
+fn samples_to_duration(samples: u64, samples_per_second: u64) -> Duration {
+    Duration::from_micros(samples * 1_000_000 / samples_per_second)
+}
+
+fn duration_to_samples(duration: Duration, samples_per_second: u64) -> u64 {
+    duration.as_micros() as u64 * samples_per_second / 1_000_000
+}
+
+// Most decoders can't tell us how long a track is without decoding all of it,
+// so that's only done when the length wasn't read with the tags either.
+fn measure_track(track: &Track) -> Result<Duration> {
+    let source = decode_track(track)?;
+    match source.total_duration().or(track.duration) {
+        Some(length) => Ok(length),
+        None => {
+            let samples_per_second = get_samples_per_second(&source);
+            match source.count() as u64 {
+                0 => Err(Error::Decode {
+                    path: PathBuf::from(&track.path),
+                    error: String::from("No audio could be decoded"),
+                }),
+                samples => Ok(samples_to_duration(samples, samples_per_second)),
+            }
+        },
+    }
+}
+
+// Counts the samples passed on from the decoder so that we can tell how far
+// through the track playback has got.
+struct TrackedSource<S> {
+    source: S,
+    samples_played: Arc<AtomicU64>,
+}
+
+impl<S> Iterator for TrackedSource<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<S::Item> {
+        let sample = self.source.next();
+        if sample.is_some() {
+            self.samples_played.fetch_add(1, Ordering::Relaxed);
+        };
+        sample
+    }
+}
+
+impl<S> Source for TrackedSource<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+pub struct RodioBackend {
+    device: Option<Device>,
+    sink: Option<Sink>,
+    track: Option<Track>,
+    length: Option<Duration>,
+    samples_played: Arc<AtomicU64>,
+    samples_per_second: u64,
+}
+
+impl RodioBackend {
+    pub fn new() -> RodioBackend {
+        // The output device is only opened when something is played, so that
+        // creating a queue doesn't require a sound card.
+        RodioBackend{
+            device: None,
+            sink: None,
+            track: None,
+            length: None,
+            samples_played: Arc::new(AtomicU64::new(0)),
+            samples_per_second: 1,
+        }
+    }
+
+    fn start_track_at(&mut self, track: &Track, position: Duration) -> Result<()> {
+        self.open()?;
+
+        // Rodio can't seek, so we decode from the start and throw away
+        // everything before the position we want.
+        let mut source = decode_track(track)?;
+        let samples_per_second = get_samples_per_second(&source);
+        let channels = max(1, source.channels() as u64);
+        // Skip whole frames so that we don't swap the channels around
+        let to_skip = duration_to_samples(position, samples_per_second) / channels * channels;
+        let skipped = source.by_ref().take(to_skip as usize).count() as u64;
+
+        self.samples_played = Arc::new(AtomicU64::new(skipped));
+        self.samples_per_second = samples_per_second;
+
+        let sink = Sink::new(self.device.as_ref().unwrap());
+        sink.append(TrackedSource {
+            source,
+            samples_played: self.samples_played.clone(),
+        });
+        sink.play();
+        self.sink = Some(sink);
+        Ok(())
+    }
+}
+
+impl AudioBackend for RodioBackend {
+    fn open(&mut self) -> Result<()> {
+        if self.device.is_none() {
+            match rodio::default_output_device() {
+                Some(device) => self.device = Some(device),
+                None => return Err(Error::NoOutputDevice),
+            };
+        };
+        Ok(())
+    }
+
+    fn play_track(&mut self, track: &Track) -> Result<()> {
+        self.stop();
+        self.length = Some(measure_track(track)?);
+        self.start_track_at(track, Duration::from_secs(0))?;
+        self.track = Some(track.clone());
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        // Dropping the sink stops it
+        self.sink = None;
+        self.track = None;
+        self.length = None;
+    }
+
+    fn pause(&mut self) {
+        if let Some(sink) = &self.sink {
+            sink.pause();
+        };
+    }
+
+    fn resume(&mut self) {
+        if let Some(sink) = &self.sink {
+            sink.play();
+        };
+    }
+
+    fn seek(&mut self, position: Duration) {
+        let track = match &self.track {
+            Some(track) => track.clone(),
+            None => return,
+        };
+        let paused = match &self.sink {
+            Some(sink) => sink.is_paused(),
+            None => false,
+        };
+        match self.start_track_at(&track, position) {
+            Ok(_) => {
+                if paused {
+                    self.pause();
+                };
+            },
+            // The file has gone away or changed since we started it, so
+            // give up on it.
+            Err(_) => self.stop(),
+        };
+    }
+
+    fn position(&self) -> Duration {
+        match self.sink {
+            Some(_) => samples_to_duration(
+                self.samples_played.load(Ordering::Relaxed),
+                self.samples_per_second,
+            ),
+            None => Duration::from_secs(0),
+        }
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        self.length
+    }
+
+    fn is_empty(&self) -> bool {
+        match &self.sink {
+            Some(sink) => sink.empty(),
+            None => true,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct PlayedTrack {
+    pub track: Track,
+    pub started: Instant,
+}
+
+#[derive(Clone)]
+struct NullPlayback {
+    length: Duration,
+    // How much was played before the most recent resume
+    played: Duration,
+    // None while paused
+    resumed: Option<Instant>,
+}
+
+impl NullPlayback {
+    fn position(&self) -> Duration {
+        match self.resumed {
+            Some(resumed) => self.played + resumed.elapsed(),
+            None => self.played,
+        }
+    }
+}
+
+// Decodes tracks but discards the samples, taking as long to "play" each
+// track as it would take to play it for real.
+// Everything played is recorded, and clones share the same record, so a clone
+// can be kept to inspect what a queue played after handing it the original.
+#[derive(Clone)]
+pub struct NullBackend {
+    played: Arc<Mutex<Vec<PlayedTrack>>>,
+    current: Option<NullPlayback>,
+}
+
+impl NullBackend {
+    pub fn new() -> NullBackend {
+        NullBackend{
+            played: Arc::new(Mutex::new(Vec::new())),
+            current: None,
+        }
+    }
+
+    pub fn get_played(&self) -> Vec<PlayedTrack> {
+        self.played.lock().unwrap().clone()
+    }
+}
+
+impl AudioBackend for NullBackend {
+    fn open(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn play_track(&mut self, track: &Track) -> Result<()> {
+        self.current = None;
+        let length = measure_track(track)?;
+
+        let started = Instant::now();
+        self.played.lock().unwrap().push(PlayedTrack {
+            track: track.clone(),
+            started,
+        });
+        self.current = Some(NullPlayback {
+            length,
+            played: Duration::from_secs(0),
+            resumed: Some(started),
+        });
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.current = None;
+    }
+
+    fn pause(&mut self) {
+        if let Some(playback) = self.current.as_mut() {
+            playback.played = playback.position();
+            playback.resumed = None;
+        };
+    }
+
+    fn resume(&mut self) {
+        if let Some(playback) = self.current.as_mut() {
+            if playback.resumed.is_none() {
+                playback.resumed = Some(Instant::now());
+            };
+        };
+    }
+
+    fn seek(&mut self, position: Duration) {
+        if let Some(playback) = self.current.as_mut() {
+            playback.played = min(position, playback.length);
+            if playback.resumed.is_some() {
+                playback.resumed = Some(Instant::now());
+            };
+        };
+    }
+
+    fn position(&self) -> Duration {
+        match &self.current {
+            Some(playback) => min(playback.position(), playback.length),
+            None => Duration::from_secs(0),
+        }
+    }
+
+    fn duration(&self) -> Option<Duration> {
+        match &self.current {
+            Some(playback) => Some(playback.length),
+            None => None,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match &self.current {
+            Some(playback) => playback.position() >= playback.length,
+            None => true,
+        }
+    }
+}
@@ -0,0 +1,228 @@
+// Biases steer which track dynamic mode picks next, like Amarok's dynamic
+// playlists: "half the tracks from the 90s", "no artists played in the last
+// hour", "more of the highly rated tracks". A playlist's biases are applied
+// in the order they were added, each adjusting the weights the last left,
+// so a bias that rules tracks out is best added last.
+use crate::track::Track;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+// A track that could be picked next, and the name of the source it's from
+#[derive(Clone, Copy)]
+pub struct Candidate<'a> {
+    pub track: &'a Track,
+    pub source: &'a str,
+}
+
+// A track handed out by a playlist, or played from somewhere else
+#[derive(Clone)]
+pub struct Play {
+    pub track: Track,
+    pub at: SystemTime,
+}
+
+// What biases know about besides the candidates
+pub struct BiasContext<'a> {
+    // Oldest first
+    pub plays: &'a [Play],
+    pub now: SystemTime,
+}
+
+pub trait Bias: Send + Sync {
+    // Adjusts how likely each candidate is to be picked, where weights[i]
+    // goes with candidates[i]. Weights are relative to each other, and a
+    // weight of 0 means the candidate won't be picked.
+    fn apply(&self, candidates: &[Candidate], weights: &mut [f64], context: &BiasContext);
+}
+
+// Runs the biases over the weights in turn. Biases are preferences, so one
+// that would rule out everything still left, like a recency bias once every
+// artist has been played, is skipped and the rest still apply.
+pub(crate) fn apply_biases(biases: &[Arc<dyn Bias>], candidates: &[Candidate], weights: &mut [f64], context: &BiasContext) {
+    for bias in biases {
+        let before = weights.to_vec();
+        bias.apply(candidates, weights, context);
+        if ! usable(weights) {
+            weights.copy_from_slice(&before);
+        };
+    }
+}
+
+// Whether there's anything the weights can pick
+pub(crate) fn usable(weights: &[f64]) -> bool {
+    let total: f64 = weights.iter().sum();
+    total.is_finite() && total > 0.0
+}
+
+// Makes the matching candidates get the proportion of the weight between
+// them, and the rest the remainder, keeping how they compare among
+// themselves. If all or none of them match there's nothing to steer.
+fn apply_proportion<F>(candidates: &[Candidate], weights: &mut [f64], proportion: f64, matches: F)
+    where F: Fn(&Candidate) -> bool
+{
+    let proportion = proportion.clamp(0.0, 1.0);
+    let matching: Vec<bool> = candidates.iter().map(matches).collect();
+    let total = |wanted: bool| -> f64 {
+        matching.iter().zip(weights.iter())
+            .filter(|(matched, _)| **matched == wanted)
+            .map(|(_, weight)| weight)
+            .sum()
+    };
+    let (matching_total, other_total) = (total(true), total(false));
+    if matching_total <= 0.0 || other_total <= 0.0 {
+        return;
+    };
+
+    for (matched, weight) in matching.iter().zip(weights.iter_mut()) {
+        if *matched {
+            *weight *= proportion / matching_total;
+        } else {
+            *weight *= (1.0 - proportion) / other_total;
+        };
+    }
+}
+
+// A proportion of the picks, between 0 and 1, go to tracks matching a test
+// on their details
+pub struct MetadataBias {
+    matches: Box<dyn Fn(&Track) -> bool + Send + Sync>,
+    proportion: f64,
+}
+
+impl MetadataBias {
+    pub fn new<F>(matches: F, proportion: f64) -> MetadataBias
+        where F: Fn(&Track) -> bool + Send + Sync + 'static
+    {
+        MetadataBias {
+            matches: Box::new(matches),
+            proportion,
+        }
+    }
+
+    // Years are read from the start of the year field, which can be a full date
+    pub fn year_between(first: u32, last: u32, proportion: f64) -> MetadataBias {
+        MetadataBias::new(move |track| {
+            let year: String = track.year.trim().chars().take_while(|c| c.is_ascii_digit()).collect();
+            match year.parse::<u32>() {
+                Ok(year) => year >= first && year <= last,
+                Err(_) => false,
+            }
+        }, proportion)
+    }
+}
+
+impl Bias for MetadataBias {
+    fn apply(&self, candidates: &[Candidate], weights: &mut [f64], _context: &BiasContext) {
+        apply_proportion(candidates, weights, self.proportion, |candidate| (self.matches)(candidate.track));
+    }
+}
+
+// A proportion of the picks, between 0 and 1, go to tracks from the source
+// with the name, whatever the source weights say
+pub struct SourceBias {
+    source: String,
+    proportion: f64,
+}
+
+impl SourceBias {
+    pub fn new(source: &str, proportion: f64) -> SourceBias {
+        SourceBias {
+            source: source.to_string(),
+            proportion,
+        }
+    }
+}
+
+impl Bias for SourceBias {
+    fn apply(&self, candidates: &[Candidate], weights: &mut [f64], _context: &BiasContext) {
+        apply_proportion(candidates, weights, self.proportion, |candidate| candidate.source == self.source);
+    }
+}
+
+// Ratings given out of 5 stars
+const MAX_RATING: u8 = 5;
+
+// Tracks are picked in proportion to their rating plus one, so a 5 star
+// track comes up six times as often as a 0 star one. Tracks without a
+// rating count as being in the middle.
+pub struct RatingBias {
+    // By track path
+    ratings: HashMap<String, u8>,
+}
+
+impl RatingBias {
+    pub fn new(ratings: HashMap<String, u8>) -> RatingBias {
+        RatingBias {
+            ratings,
+        }
+    }
+}
+
+impl Bias for RatingBias {
+    fn apply(&self, candidates: &[Candidate], weights: &mut [f64], _context: &BiasContext) {
+        for (candidate, weight) in candidates.iter().zip(weights.iter_mut()) {
+            let rating = match self.ratings.get(&candidate.track.path) {
+                Some(rating) => f64::from((*rating).min(MAX_RATING)),
+                None => f64::from(MAX_RATING) / 2.0,
+            };
+            *weight *= rating + 1.0;
+        }
+    }
+}
+
+// What has to match for a play to count against a candidate
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecentBy {
+    Track,
+    Artist,
+    Album,
+}
+
+// Rules out tracks, or tracks by artists or from albums, played within the
+// time before now
+pub struct RecencyBias {
+    by: RecentBy,
+    within: Duration,
+}
+
+impl RecencyBias {
+    pub fn new(by: RecentBy, within: Duration) -> RecencyBias {
+        RecencyBias {
+            by,
+            within,
+        }
+    }
+
+    // Tracks with nothing to go on aren't all the same artist or album
+    fn key(&self, track: &Track) -> Option<String> {
+        let key = match self.by {
+            RecentBy::Track => return Some(track.path.clone()),
+            RecentBy::Artist => &track.artist,
+            RecentBy::Album => &track.album,
+        };
+        let key = key.trim().to_lowercase();
+        if key.is_empty() { None } else { Some(key) }
+    }
+}
+
+impl Bias for RecencyBias {
+    fn apply(&self, candidates: &[Candidate], weights: &mut [f64], context: &BiasContext) {
+        let recent: Vec<String> = context.plays.iter()
+            .filter(|play| match context.now.duration_since(play.at) {
+                Ok(ago) => ago <= self.within,
+                // Played in the future, as far as the clock is concerned
+                Err(_) => true,
+            })
+            .filter_map(|play| self.key(&play.track))
+            .collect();
+
+        for (candidate, weight) in candidates.iter().zip(weights.iter_mut()) {
+            if let Some(key) = self.key(candidate.track) {
+                if recent.contains(&key) {
+                    *weight = 0.0;
+                };
+            };
+        }
+    }
+}
@@ -1,6 +1,12 @@
 // Finding out what is valid in id3v2 tags turned out to be aggravating.
 // It might be possible for these to be used, but they will not display well
-// in most cases, so they'll probably be acceptable.
+// in most cases, so they'll probably be acceptable. When they do turn up in
+// something being saved they are escaped.
 pub const END_OF_FIELD: char = '\u{1f}';
 pub const END_OF_RECORD: char = '\u{1e}';
 pub const END_OF_HEADER: char = '\u{1d}';
+// Save files start with this, followed by the format version
+pub const START_OF_VERSION: char = '\u{1c}';
+// Put before any delimiter (or itself) that turns up in a field, so it is
+// read back as part of the field
+pub const ESCAPE: char = '\u{1b}';
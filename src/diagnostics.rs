@@ -0,0 +1,144 @@
+use crate::error::Error;
+use crate::save_format::{Record, KIND_KEY};
+use std::collections::BTreeMap;
+use std::fmt;
+#[cfg(feature = "export")]
+use serde::{Deserialize, Serialize};
+
+// Marks a header record holding a diagnostic in a saved library
+pub(crate) const DIAGNOSTIC_RECORD: &str = "diag";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "export", derive(Serialize, Deserialize), serde(rename_all = "snake_case"))]
+pub enum RequiredTag {
+    Title,
+    Artist,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "export", derive(Serialize, Deserialize), serde(rename_all = "snake_case"))]
+pub enum SkipReason {
+    // The file couldn't be opened or read at all
+    Unreadable(String),
+    // The file was read, but its tags couldn't be made sense of
+    BadTags(String),
+    MissingTag(RequiredTag),
+}
+
+// A file which looked like audio, but which couldn't be added to the library
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "export", derive(Serialize, Deserialize))]
+pub struct ScanDiagnostic {
+    pub path: String,
+    pub reason: SkipReason,
+}
+
+// Everything which needed attention in a scan, at most one entry per file
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "export", derive(Serialize, Deserialize), serde(transparent))]
+pub struct ScanDiagnostics {
+    diagnostics: BTreeMap<String, ScanDiagnostic>,
+}
+
+impl SkipReason {
+    pub(crate) fn from_error(error: Error) -> SkipReason {
+        match error {
+            Error::Io { error, .. } => SkipReason::Unreadable(error.to_string()),
+            Error::Tag { error, .. } => SkipReason::BadTags(error),
+            other => SkipReason::BadTags(other.to_string()),
+        }
+    }
+}
+
+impl ScanDiagnostic {
+    // Saved with the path, the reason it was skipped and any detail
+    pub(crate) fn to_record(&self) -> Vec<(&'static str, String)> {
+        let (kind, detail) = match &self.reason {
+            SkipReason::Unreadable(error) => ("unreadable", error.clone()),
+            SkipReason::BadTags(error) => ("bad_tags", error.clone()),
+            SkipReason::MissingTag(RequiredTag::Title) => ("missing_tag", String::from("title")),
+            SkipReason::MissingTag(RequiredTag::Artist) => ("missing_tag", String::from("artist")),
+        };
+        vec!(
+            (KIND_KEY, String::from(DIAGNOSTIC_RECORD)),
+            ("path", self.path.clone()),
+            ("reason", String::from(kind)),
+            ("detail", detail),
+        )
+    }
+
+    // Gives None if the record isn't a valid diagnostic
+    pub(crate) fn from_record(record: &Record) -> Option<ScanDiagnostic> {
+        if record.kind() != DIAGNOSTIC_RECORD {
+            return None;
+        };
+        let reason = match (record.value("reason"), record.value("detail")) {
+            ("unreadable", error) => SkipReason::Unreadable(error.to_string()),
+            ("bad_tags", error) => SkipReason::BadTags(error.to_string()),
+            ("missing_tag", "title") => SkipReason::MissingTag(RequiredTag::Title),
+            ("missing_tag", "artist") => SkipReason::MissingTag(RequiredTag::Artist),
+            _ => return None,
+        };
+        Some(ScanDiagnostic {
+            path: record.value("path").to_string(),
+            reason,
+        })
+    }
+}
+
+impl fmt::Display for ScanDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.reason {
+            SkipReason::Unreadable(error) => write!(f, "Could not read {}: {}", self.path, error),
+            SkipReason::BadTags(error) => write!(f, "Could not read tags from {}: {}", self.path, error),
+            SkipReason::MissingTag(RequiredTag::Title) => write!(f, "Could not get track name (title tag) for {}", self.path),
+            SkipReason::MissingTag(RequiredTag::Artist) => write!(f, "Could not get artist (artist tag) for {}", self.path),
+        }
+    }
+}
+
+impl ScanDiagnostics {
+    pub fn new() -> ScanDiagnostics {
+        ScanDiagnostics::default()
+    }
+
+    pub fn get(&self, path: &str) -> Option<ScanDiagnostic> {
+        self.diagnostics.get(path).cloned()
+    }
+
+    // In path order
+    pub fn get_all(&self) -> Vec<ScanDiagnostic> {
+        self.diagnostics.values().cloned().collect()
+    }
+
+    pub fn get_missing_tag(&self, tag: RequiredTag) -> Vec<ScanDiagnostic> {
+        self.diagnostics.values()
+            .filter(|diagnostic| diagnostic.reason == SkipReason::MissingTag(tag))
+            .cloned()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.diagnostics.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub(crate) fn insert(&mut self, diagnostic: ScanDiagnostic) {
+        self.diagnostics.insert(diagnostic.path.clone(), diagnostic);
+    }
+
+    pub(crate) fn remove(&mut self, path: &str) {
+        self.diagnostics.remove(path);
+    }
+
+    pub(crate) fn retain<F: FnMut(&String) -> bool>(&mut self, mut keep: F) {
+        self.diagnostics.retain(|path, _| keep(path));
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.diagnostics.clear();
+    }
+}
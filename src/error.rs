@@ -0,0 +1,95 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    // Offset is in bytes from the start of the file
+    CorruptSaveFile {
+        path: PathBuf,
+        offset: usize,
+    },
+    // Saved by a newer version of the library than this one
+    UnsupportedSaveVersion {
+        path: PathBuf,
+        version: u32,
+    },
+    UnsupportedFormat(PathBuf),
+    Decode {
+        path: PathBuf,
+        error: String,
+    },
+    Tag {
+        path: PathBuf,
+        error: String,
+    },
+    NoOutputDevice,
+    // A queue was asked to play without a playlist to play from
+    NoPlaylist,
+    Watch {
+        path: PathBuf,
+        error: String,
+    },
+    InvalidPathPattern {
+        pattern: String,
+        error: String,
+    },
+    // A playlist file from elsewhere that doesn't make sense
+    InvalidPlaylist {
+        path: PathBuf,
+        error: String,
+    },
+    Export {
+        format: String,
+        error: String,
+    },
+    Import {
+        format: String,
+        error: String,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub(crate) fn io(path: &Path, error: io::Error) -> Error {
+        Error::Io {
+            path: path.to_path_buf(),
+            error,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, error } => write!(f, "Could not access {}: {}", path.display(), error),
+            Error::CorruptSaveFile { path, offset } => write!(f, "Save file {} is corrupt at byte {}", path.display(), offset),
+            Error::UnsupportedSaveVersion { path, version } => write!(f, "Save file {} uses format version {}, which is newer than this version understands", path.display(), version),
+            Error::UnsupportedFormat(path) => write!(f, "Format of {} is not supported", path.display()),
+            Error::Decode { path, error } => write!(f, "Could not decode {}: {}", path.display(), error),
+            Error::Tag { path, error } => write!(f, "Could not read tags from {}: {}", path.display(), error),
+            Error::NoOutputDevice => write!(f, "No audio output device is available"),
+            Error::NoPlaylist => write!(f, "There is no playlist to play"),
+            Error::Watch { path, error } => write!(f, "Could not watch {} for changes: {}", path.display(), error),
+            Error::InvalidPathPattern { pattern, error } => write!(f, "Path pattern {} is not valid: {}", pattern, error),
+            Error::InvalidPlaylist { path, error } => write!(f, "Playlist {} could not be read: {}", path.display(), error),
+            Error::Export { format, error } => write!(f, "Could not export to {}: {}", format, error),
+            Error::Import { format, error } => write!(f, "Could not import from {}: {}", format, error),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
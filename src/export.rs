@@ -0,0 +1,68 @@
+// Libraries, playlists and tracks as JSON or TOML, for other tools to read
+// and write. These aren't replacements for saving: file stats aren't
+// exported, so an imported library reads every file again on its first
+// rescan, and the upcoming tracks of dynamic playlists are picked afresh.
+use crate::error::{Error, Result};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::time::Duration;
+
+const JSON: &str = "JSON";
+const TOML: &str = "TOML";
+
+pub fn export_json<T: Serialize>(value: &T) -> Result<String> {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => Ok(json),
+        Err(err) => Err(export_error(JSON, err)),
+    }
+}
+
+pub fn import_json<T: DeserializeOwned>(json: &str) -> Result<T> {
+    match serde_json::from_str(json) {
+        Ok(value) => Ok(value),
+        Err(err) => Err(import_error(JSON, err)),
+    }
+}
+
+pub fn export_toml<T: Serialize>(value: &T) -> Result<String> {
+    // TOML needs plain values before tables, which going through a Value
+    // sorts out whatever order the fields are in.
+    let value = match toml::Value::try_from(value) {
+        Ok(value) => value,
+        Err(err) => return Err(export_error(TOML, err)),
+    };
+    match toml::to_string_pretty(&value) {
+        Ok(toml) => Ok(toml),
+        Err(err) => Err(export_error(TOML, err)),
+    }
+}
+
+pub fn import_toml<T: DeserializeOwned>(toml: &str) -> Result<T> {
+    match toml::from_str(toml) {
+        Ok(value) => Ok(value),
+        Err(err) => Err(import_error(TOML, err)),
+    }
+}
+
+fn export_error<E: std::fmt::Display>(format: &str, err: E) -> Error {
+    Error::Export {
+        format: format.to_string(),
+        error: err.to_string(),
+    }
+}
+
+fn import_error<E: std::fmt::Display>(format: &str, err: E) -> Error {
+    Error::Import {
+        format: format.to_string(),
+        error: err.to_string(),
+    }
+}
+
+// Durations are in whole milliseconds, as they are when saved
+pub(crate) fn serialize_duration_ms<S: Serializer>(duration: &Option<Duration>, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    duration.map(|duration| duration.as_millis() as u64).serialize(serializer)
+}
+
+pub(crate) fn deserialize_duration_ms<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<Duration>, D::Error> {
+    Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_millis))
+}
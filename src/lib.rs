@@ -1,13 +1,34 @@
+pub mod backend;
+pub mod bias;
+pub mod diagnostics;
+pub mod error;
+#[cfg(feature = "export")]
+pub mod export;
 pub mod music_library;
+pub mod path_pattern;
 pub mod playlist;
 pub mod track;
 pub mod queue;
+#[cfg(feature = "watch")]
+pub mod watcher;
 
 mod delimiters;
+mod playlist_formats;
+mod save_format;
 mod shared;
+mod tags;
 
-pub use crate::music_library::MusicLibrary;
-pub use crate::playlist::Playlist;
+pub use crate::backend::{AudioBackend, NullBackend, RodioBackend};
+pub use crate::bias::{Bias, BiasContext, Candidate, MetadataBias, Play, RatingBias, RecencyBias, RecentBy, SourceBias};
+pub use crate::diagnostics::{RequiredTag, ScanDiagnostic, ScanDiagnostics, SkipReason};
+pub use crate::error::{Error, Result};
+#[cfg(feature = "export")]
+pub use crate::export::{export_json, export_toml, import_json, import_toml};
+pub use crate::music_library::{MusicLibrary, ScanProgress, ScanReport};
+pub use crate::path_pattern::PathPattern;
+pub use crate::playlist::{PlayMode, Playlist, SourceWeight};
 pub use crate::shared::Saveable;
-pub use crate::track::Track;
-pub use crate::queue::Queue;
+pub use crate::track::{Track, TrackField};
+pub use crate::queue::{HistoryEntry, Queue, QueueEvent};
+#[cfg(feature = "watch")]
+pub use crate::watcher::{LibraryEvent, LibraryWatcher};
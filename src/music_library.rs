@@ -1,19 +1,108 @@
-use id3::Tag;
+use std::cmp::{max, min};
+use std::collections::{BTreeMap, HashSet};
 use std::ffi::OsStr;
-use std::fs::read_to_string;
+use std::fs::metadata;
+use std::panic::resume_unwind;
 use std::path::{Path, PathBuf};
-use crate::delimiters::{END_OF_FIELD, END_OF_HEADER};
-use crate::shared::{DynamicSource, Saveable};
-use crate::track::Track;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::UNIX_EPOCH;
+use crate::delimiters::{END_OF_HEADER, END_OF_RECORD};
+use crate::diagnostics::{RequiredTag, ScanDiagnostic, ScanDiagnostics, SkipReason, DIAGNOSTIC_RECORD};
+use crate::error::{Error, Result};
+use crate::path_pattern::PathPattern;
+use crate::save_format::{dump_record, KIND_KEY};
+use crate::shared::{load_saved, DynamicSource, Saveable};
+use crate::tags::{detect_format, read_tags, AudioFormat};
+use crate::track::{Track, TrackField};
+#[cfg(feature = "export")]
+use serde::{Deserialize, Serialize};
 
 const EXTENSION: &str = "lib";
 
+// Marks a header record holding the size and modification time of a file
+const FILE_STAT_RECORD: &str = "stat";
+// Marks a header record holding a path pattern
+const PATH_PATTERN_RECORD: &str = "pattern";
+
+// Enough to tell whether a file has changed since it was last read
+#[derive(Clone, PartialEq)]
+struct FileStat {
+    // Nanoseconds since the unix epoch
+    modified: u128,
+    size: u64,
+}
+
+impl FileStat {
+    fn read(path: &Path) -> Result<FileStat> {
+        let details = match metadata(path) {
+            Ok(details) => details,
+            Err(err) => return Err(Error::io(path, err)),
+        };
+        let modified = match details.modified() {
+            Ok(modified) => modified,
+            Err(err) => return Err(Error::io(path, err)),
+        };
+        Ok(FileStat {
+            // Anything from before the epoch is treated as being at it
+            modified: modified.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos(),
+            size: details.len(),
+        })
+    }
+}
+
+// How many files are read at once while scanning, unless told otherwise
+const DEFAULT_SCAN_THREADS: usize = 4;
+
+// Passed to the progress callback while scanning. Files are all seen before
+// any are processed, so files_seen is the total once processing starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanProgress {
+    pub files_seen: usize,
+    pub files_processed: usize,
+    pub current_path: String,
+}
+
+// The track read from a file if it's audio, and the stats of the file, which
+// are kept either way so unchanged files aren't read again. Files that
+// couldn't be read have stats too, unless they couldn't even be looked at.
+type FileDetails = (Option<Track>, FileStat);
+type FileResult = std::result::Result<FileDetails, (SkipReason, Option<FileStat>)>;
+
+// What changed in the library during a rescan. All entries are file paths.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ScanReport {
+    pub added: Vec<String>,
+    pub updated: Vec<String>,
+    pub removed: Vec<String>,
+    pub failed: Vec<String>,
+}
 
 #[derive(Clone)]
+#[cfg_attr(feature = "export", derive(Serialize, Deserialize))]
 pub struct MusicLibrary {
     name: String,
     path: String,
+    #[cfg_attr(feature = "export", serde(default))]
     tracks: Vec<Track>,
+    // Only meaningful on the machine the files were read on
+    #[cfg_attr(feature = "export", serde(skip))]
+    file_stats: BTreeMap<String, FileStat>,
+    #[cfg_attr(feature = "export", serde(default))]
+    diagnostics: ScanDiagnostics,
+    #[cfg_attr(feature = "export", serde(default))]
+    path_patterns: Vec<PathPattern>,
+    #[cfg_attr(feature = "export", serde(skip, default = "default_scan_threads"))]
+    scan_threads: usize,
+    // The directory the library was loaded from, if it was
+    #[cfg_attr(feature = "export", serde(skip))]
+    storage_path: Option<String>,
+}
+
+#[cfg(feature = "export")]
+fn default_scan_threads() -> usize {
+    DEFAULT_SCAN_THREADS
 }
 
 impl MusicLibrary {
@@ -22,96 +111,428 @@ impl MusicLibrary {
             name,
             path,
             tracks: Vec::new(),
+            file_stats: BTreeMap::new(),
+            diagnostics: ScanDiagnostics::new(),
+            path_patterns: Vec::new(),
+            scan_threads: DEFAULT_SCAN_THREADS,
+            storage_path: None,
         }
     }
 
-    pub fn load(saved_library_path: String, saved_library_name: String) -> MusicLibrary {
+    pub fn load(saved_library_path: String, saved_library_name: String) -> Result<MusicLibrary> {
         let mut library_path = PathBuf::from(&saved_library_path);
         library_path.push(OsStr::new(&format!("{}.{}", &saved_library_name, &EXTENSION)));
 
-        let saved_data = match read_to_string(&library_path) {
-            Ok(data) => data,
-            Err(err) => panic!("Could not load library from {}: {:#?}", library_path.display(), err),
-        };
+        let mut library = load_saved(&library_path, MusicLibrary::from_saved)?;
+        library.storage_path = Some(saved_library_path);
+        Ok(library)
+    }
+
+    fn from_saved(saved_data: &str, library_path: &Path) -> Result<MusicLibrary> {
+        let header_records = MusicLibrary::process_save_header_records(saved_data, library_path)?;
+        let header_details = &header_records[0];
 
-        let header_details = MusicLibrary::process_save_header(&saved_data);
+        // Libraries saved before file stats were kept won't have any, so
+        // everything in them will be read again on the next rescan.
+        let mut file_stats = BTreeMap::new();
+        let mut diagnostics = ScanDiagnostics::new();
+        let mut path_patterns = Vec::new();
+        for record in &header_records[1..] {
+            let corrupt = || Error::CorruptSaveFile {
+                path: library_path.to_path_buf(),
+                offset: record.start,
+            };
+            if record.kind() == FILE_STAT_RECORD {
+                let stat = match (record.value("modified").parse(), record.value("size").parse()) {
+                    (Ok(modified), Ok(size)) => FileStat { modified, size },
+                    _ => return Err(corrupt()),
+                };
+                file_stats.insert(record.value("path").to_string(), stat);
+            } else if record.kind() == DIAGNOSTIC_RECORD {
+                match ScanDiagnostic::from_record(record) {
+                    Some(diagnostic) => diagnostics.insert(diagnostic),
+                    None => return Err(corrupt()),
+                };
+            } else if record.kind() == PATH_PATTERN_RECORD {
+                match PathPattern::new(record.value("pattern")) {
+                    Ok(pattern) => path_patterns.push(pattern),
+                    Err(_) => return Err(corrupt()),
+                };
+            };
+            // Anything else was added by a newer version, and can be skipped
+        };
 
-        let tracks = MusicLibrary::load_tracks(&saved_data);
+        let tracks = MusicLibrary::load_tracks(saved_data, library_path)?;
 
-        MusicLibrary{
-            name: header_details[0].to_string(),
-            path: header_details[1].to_string(),
+        Ok(MusicLibrary{
+            name: header_details.value("name").to_string(),
+            path: header_details.value("path").to_string(),
             tracks: tracks,
-        }
+            file_stats,
+            diagnostics,
+            path_patterns,
+            scan_threads: DEFAULT_SCAN_THREADS,
+            storage_path: None,
+        })
     }
 
     pub fn get_path(&self) -> &str {
         &self.path
     }
 
-    pub fn scan(&mut self) {
+    pub(crate) fn get_storage_path(&self) -> Option<&str> {
+        self.storage_path.as_deref()
+    }
+
+    // How many files to read at once while scanning. Reading is mostly
+    // waiting on the disk, so this can usefully be more than the CPU count,
+    // particularly for libraries on network shares.
+    pub fn set_scan_threads(&mut self, threads: usize) {
+        self.scan_threads = max(threads, 1);
+    }
+
+    // Patterns to work out missing track details from file paths (relative
+    // to the library), tried in order until one fits. Only used for details
+    // which aren't in the tags, and only applied on the next scan.
+    pub fn set_path_patterns(&mut self, patterns: Vec<PathPattern>) {
+        self.path_patterns = patterns;
+    }
+
+    pub fn get_path_patterns(&self) -> Vec<PathPattern> {
+        self.path_patterns.clone()
+    }
+
+    // Read every track in the library from scratch. Files which couldn't be
+    // added are returned, and are kept with the library until they're fixed.
+    pub fn scan(&mut self) -> Result<ScanDiagnostics> {
+        self.scan_with_progress(|_| ())
+    }
+
+    pub fn scan_with_progress<F: FnMut(&ScanProgress)>(&mut self, mut progress: F) -> Result<ScanDiagnostics> {
+        self.tracks.clear();
+        self.file_stats.clear();
+        self.diagnostics.clear();
+
+        let mut files_seen = 0;
+        let mut files = MusicLibrary::find_files(Path::new(&self.path), &mut |path| {
+            files_seen += 1;
+            progress(&ScanProgress {
+                files_seen,
+                files_processed: 0,
+                current_path: path.to_string_lossy().to_string(),
+            });
+        })?;
+        // Files are read in any order, but the tracks are always kept in path
+        // order so that saving the same library twice gives the same file.
+        files.sort();
+
+        let results = self.read_files(&files, &mut progress);
+        for (file, result) in files.iter().zip(results) {
+            match result {
+                Ok((Some(track), stat)) => {
+                    self.file_stats.insert(track.path.clone(), stat);
+                    self.tracks.push(track);
+                },
+                // Not something we can play
+                Ok((None, stat)) => {
+                    self.file_stats.insert(file.to_string_lossy().to_string(), stat);
+                },
+                // One bad file shouldn't stop the rest being scanned
+                Err((reason, stat)) => {
+                    if let Some(stat) = stat {
+                        self.file_stats.insert(file.to_string_lossy().to_string(), stat);
+                    };
+                    self.diagnostics.insert(ScanDiagnostic {
+                        path: file.to_string_lossy().to_string(),
+                        reason,
+                    });
+                },
+            };
+        }
+        Ok(self.diagnostics.clone())
+    }
+
+    // Files which couldn't be added in the last scan or rescan
+    pub fn get_diagnostics(&self) -> ScanDiagnostics {
+        self.diagnostics.clone()
+    }
+
+    // Only read files which are new or have changed since the last scan, and
+    // forget about any which have gone.
+    pub fn rescan(&mut self) -> Result<ScanReport> {
+        let library_path = PathBuf::from(&self.path);
+        self.rescan_path(&library_path)
+    }
+
+    // As rescan, but only looking at one file or directory in the library.
+    // The path doesn't need to exist any more, in which case any tracks that
+    // were under it are removed.
+    pub fn rescan_path(&mut self, path: &Path) -> Result<ScanReport> {
+        let mut report = ScanReport::default();
+        let mut found = HashSet::new();
+
+        let files = if path.is_file() {
+            vec!(path.to_path_buf())
+        } else if path.is_dir() || path == Path::new(&self.path) {
+            // A missing library is an error, rather than a reason to empty it
+            MusicLibrary::find_files(path, &mut |_| ())?
+        } else {
+            Vec::new()
+        };
+
+        let mut changed = Vec::new();
+        for file in files {
+            let file_string = file.to_string_lossy().to_string();
+            found.insert(file_string.clone());
+
+            let stat = match FileStat::read(&file) {
+                Ok(stat) => stat,
+                Err(err) => {
+                    if self.remove_file(&file_string) {
+                        report.removed.push(file_string.clone());
+                    };
+                    self.diagnostics.insert(ScanDiagnostic {
+                        path: file_string,
+                        reason: SkipReason::from_error(err),
+                    });
+                    continue;
+                },
+            };
+            if self.file_stats.get(&file_string) != Some(&stat) {
+                changed.push(file);
+            };
+        }
+        changed.sort();
+
+        let results = self.read_files(&changed, &mut |_| ());
+        for (file, result) in changed.iter().zip(results) {
+            let file_string = file.to_string_lossy().to_string();
+            let known = self.remove_file(&file_string);
+            match result {
+                Ok((Some(track), stat)) => {
+                    self.file_stats.insert(file_string.clone(), stat);
+                    self.tracks.push(track);
+                    if known {
+                        report.updated.push(file_string);
+                    } else {
+                        report.added.push(file_string);
+                    };
+                },
+                // Not something we can play (any more)
+                Ok((None, stat)) => {
+                    self.file_stats.insert(file_string.clone(), stat);
+                    if known {
+                        report.removed.push(file_string);
+                    };
+                },
+                // Only tried again once the file changes
+                Err((reason, stat)) => {
+                    if let Some(stat) = stat {
+                        self.file_stats.insert(file_string.clone(), stat);
+                    };
+                    self.diagnostics.insert(ScanDiagnostic {
+                        path: file_string.clone(),
+                        reason,
+                    });
+                    report.failed.push(file_string);
+                },
+            };
+        }
+
+        let is_missing = |track_path: &String| {
+            Path::new(track_path).starts_with(path) && ! found.contains(track_path)
+        };
+        let missing: Vec<String> = self.tracks.iter()
+            .map(|track| track.path.clone())
+            .filter(|track_path| is_missing(track_path))
+            .collect();
+        for track_path in missing {
+            self.remove_file(&track_path);
+            report.removed.push(track_path);
+        }
+        // Including files which weren't audio, or couldn't be added
+        self.file_stats.retain(|stat_path, _| ! is_missing(stat_path));
+        self.diagnostics.retain(|diagnostic_path| ! is_missing(diagnostic_path));
+
+        // Changed tracks were read again and added at the end, but they're
+        // kept in path order like a full scan, so saves don't depend on
+        // what was changed when
+        self.tracks.sort_by(|a, b| Path::new(&a.path).cmp(Path::new(&b.path)));
+
+        Ok(report)
+    }
+
+    fn find_files(path: &Path, on_file: &mut dyn FnMut(&Path)) -> Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
         let mut scan_paths = Vec::new();
-        scan_paths.push(Path::new(&self.path).to_path_buf());
+        scan_paths.push(path.to_path_buf());
         while scan_paths.len() > 0 {
             let current_path = scan_paths.pop().unwrap();
-            for entry in current_path.read_dir().expect("Could not read {}.") {
-                let entry = entry.unwrap().path();
+            let entries = match current_path.read_dir() {
+                Ok(entries) => entries,
+                Err(err) => return Err(Error::io(&current_path, err)),
+            };
+            for entry in entries {
+                let entry = match entry {
+                    Ok(entry) => entry.path(),
+                    Err(err) => return Err(Error::io(&current_path, err)),
+                };
                 if entry.is_file() {
-                    let extension = match entry.extension() {
-                        Some(osstr) => osstr,
-                        None => OsStr::new(""),
-                    };
-                    if extension == OsStr::new("mp3") { 
-                        self.add_track_details(&entry);
-                    }
+                    on_file(&entry);
+                    files.push(entry);
                 } else if entry.is_dir() {
                     scan_paths.push(entry.to_path_buf());
                 }
             }
         }
+        Ok(files)
     }
 
-    fn add_track_details(&mut self, path: &Path) {
-        let tags = match Tag::read_from_path(path) {
-            Ok(res) => res,
-            Err(_) => {
-                println!("Unable to read id3v2 tags for {}", path.display());
-                return;
-            },
+    // Keep the library up to date with its directory from now on
+    #[cfg(feature = "watch")]
+    pub fn watch(self) -> Result<crate::watcher::LibraryWatcher> {
+        crate::watcher::LibraryWatcher::new(self)
+    }
+
+    pub fn get_track(&self, path: &str) -> Option<Track> {
+        self.tracks.iter().find(|track| track.path == path).cloned()
+    }
+
+    // Read the files on a pool of threads, giving back the results in the same
+    // order as the files were given.
+    fn read_files(&self, files: &[PathBuf], progress: &mut dyn FnMut(&ScanProgress)) -> Vec<FileResult> {
+        let work_list: Vec<(usize, PathBuf)> = files.iter().cloned().enumerate().collect();
+        let work = Arc::new(Mutex::new(work_list.into_iter()));
+        let library_path = Arc::new(PathBuf::from(&self.path));
+        let path_patterns = Arc::new(self.path_patterns.clone());
+        let (sender, receiver) = mpsc::channel();
+
+        let mut workers = Vec::new();
+        for _ in 0..min(self.scan_threads, files.len()) {
+            let work = work.clone();
+            let library_path = library_path.clone();
+            let path_patterns = path_patterns.clone();
+            let sender = sender.clone();
+            workers.push(thread::spawn(move || loop {
+                let next = work.lock().unwrap().next();
+                match next {
+                    Some((index, path)) => {
+                        let result = MusicLibrary::read_file(&path, &library_path, &path_patterns);
+                        if sender.send((index, result)).is_err() {
+                            break;
+                        };
+                    },
+                    None => break,
+                };
+            }));
+        }
+        // Only the workers can send now, so receiving ends when they finish
+        drop(sender);
+
+        let mut results: Vec<Option<FileResult>> = files.iter().map(|_| None).collect();
+        for (processed, (index, result)) in receiver.into_iter().enumerate() {
+            progress(&ScanProgress {
+                files_seen: files.len(),
+                files_processed: processed + 1,
+                current_path: files[index].to_string_lossy().to_string(),
+            });
+            results[index] = Some(result);
+        }
+
+        for worker in workers {
+            if let Err(panic) = worker.join() {
+                resume_unwind(panic);
+            };
+        }
+
+        // Every worker finished, so every file has a result
+        results.into_iter().map(|result| result.unwrap()).collect()
+    }
+
+    // Gives no track if the file isn't audio
+    fn read_file(path: &Path, library_path: &Path, path_patterns: &[PathPattern]) -> FileResult {
+        // Take the stats first, so a change made while reading is seen next time
+        let stat = FileStat::read(path).map_err(|err| (SkipReason::from_error(err), None))?;
+        let format = match detect_format(path) {
+            Ok(Some(format)) => format,
+            Ok(None) => return Ok((None, stat)),
+            Err(err) => return Err((SkipReason::Unreadable(err.to_string()), Some(stat))),
+        };
+        match MusicLibrary::read_track_details(path, format, library_path, path_patterns) {
+            Ok(track) => Ok((Some(track), stat)),
+            Err(reason) => Err((reason, Some(stat))),
+        }
+    }
+
+    // Returns true if there was a track for the path
+    fn remove_file(&mut self, path: &str) -> bool {
+        self.file_stats.remove(path);
+        self.diagnostics.remove(path);
+        let track_count = self.tracks.len();
+        self.tracks.retain(|track| track.path != path);
+        self.tracks.len() != track_count
+    }
+
+    fn read_track_details(path: &Path, format: AudioFormat, library_path: &Path, path_patterns: &[PathPattern]) -> std::result::Result<Track, SkipReason> {
+        // Tracks keep their path as text, which it has to be to be kept
+        let path_string = match path.to_str() {
+            Some(path_string) => path_string.to_string(),
+            None => return Err(SkipReason::Unreadable(String::from("the path is not valid UTF-8"))),
+        };
+        let mut tags = read_tags(path, format).map_err(SkipReason::from_error)?;
+
+        let mut inferred = Vec::new();
+        if tags.title.is_none() || tags.artist.is_none() || tags.album.is_none() || tags.track_number.is_none() {
+            let relative_path = path.strip_prefix(library_path).unwrap_or(path);
+            if let Some(from_path) = path_patterns.iter().find_map(|pattern| pattern.infer(relative_path)) {
+                let mut fill = |tag: &mut Option<String>, value: Option<String>, field: TrackField| {
+                    if tag.is_none() && value.is_some() {
+                        *tag = value;
+                        inferred.push(field);
+                    };
+                };
+                fill(&mut tags.title, from_path.title, TrackField::TrackName);
+                fill(&mut tags.artist, from_path.artist, TrackField::Artist);
+                fill(&mut tags.album, from_path.album, TrackField::Album);
+                fill(&mut tags.track_number, from_path.track_number, TrackField::TrackNumber);
+            };
         };
 
-        let track_name: String = match tags.get("TIT2") {
-            Some(res) => res.to_string(),
-            None => {
-                println!("Could not get track name (id3v2 TIT2 tag) for {}", path.display());
-                return;
-            },
+        let track_name: String = match tags.title {
+            Some(res) => res,
+            None => return Err(SkipReason::MissingTag(RequiredTag::Title)),
         };
-        let artist: String = match tags.get("TPE1") {
-            Some(res) => res.to_string(),
-            None => {
-                println!("Could not get artist (id3v2 TPE1 tag) for {}", path.display());
-                return;
-            },
+        let artist: String = match tags.artist {
+            Some(res) => res,
+            None => return Err(SkipReason::MissingTag(RequiredTag::Artist)),
         };
-        let album: String = match tags.get("TALB") {
-            Some(res) => res.to_string(),
+        let album: String = match tags.album {
+            Some(res) => res,
             None => String::from(""),  // Album is not required
         };
-        let track_number: String = match tags.get("TRCK") {
-            Some(res) => res.to_string(),
+        let track_number: String = match tags.track_number {
+            Some(res) => res,
             None => String::from(""),  // Track number is not required
         };
 
-        self.tracks.push(
-            Track {
-                track_name,
-                artist,
-                album,
-                track_number,
-                path: String::from(path.to_str().unwrap()),
-            }
-        );
+        Ok(Track {
+            track_name,
+            artist,
+            album,
+            track_number,
+            path: path_string,
+            inferred,
+            duration: tags.duration,
+            year: tags.year.unwrap_or_default(),
+            genre: tags.genre.unwrap_or_default(),
+            disc_number: tags.disc_number.unwrap_or_default(),
+            disc_total: tags.disc_total.unwrap_or_default(),
+            album_artist: tags.album_artist.unwrap_or_default(),
+            composer: tags.composer.unwrap_or_default(),
+            bitrate: tags.bitrate,
+            sample_rate: tags.sample_rate,
+            channels: tags.channels,
+        })
     }
 
     pub fn get_tracks_by_title(&self) -> Vec<Track> {
@@ -125,6 +546,12 @@ impl MusicLibrary {
         tracks.sort_by(|a, b| a.order_by_artist_and_album(b));
         tracks.clone()
     }
+
+    pub fn get_tracks_by_album_artist_and_album(&self) -> Vec<Track> {
+        let tracks = &mut self.tracks.clone();
+        tracks.sort_by(|a, b| a.order_by_album_artist_and_album(b));
+        tracks.clone()
+    }
 }
 
 impl Saveable for MusicLibrary {
@@ -144,17 +571,50 @@ impl Saveable for MusicLibrary {
         let mut header = String::new();
 
         // Generate header
-        header.push_str(&self.name);
-        header.push(END_OF_FIELD);
-        header.push_str(&self.path);
+        header.push_str(&dump_record(&[("name", self.name.clone()), ("path", self.path.clone())]));
+        // Only the stats of tracks and files that couldn't be read are saved.
+        // Other files are quick to look at once after loading, and can
+        // include the save itself if it's in the library.
+        let mut saved_paths: HashSet<String> = self.tracks.iter().map(|track| track.path.clone()).collect();
+        saved_paths.extend(self.diagnostics.get_all().into_iter().map(|diagnostic| diagnostic.path));
+        for (path, stat) in self.file_stats.iter().filter(|(path, _)| saved_paths.contains(path.as_str())) {
+            header.push(END_OF_RECORD);
+            header.push_str(&dump_record(&[
+                (KIND_KEY, String::from(FILE_STAT_RECORD)),
+                ("path", path.clone()),
+                ("modified", stat.modified.to_string()),
+                ("size", stat.size.to_string()),
+            ]));
+        }
+        for pattern in &self.path_patterns {
+            header.push(END_OF_RECORD);
+            header.push_str(&dump_record(&[
+                (KIND_KEY, String::from(PATH_PATTERN_RECORD)),
+                ("pattern", pattern.get_pattern().to_string()),
+            ]));
+        }
+        for diagnostic in self.diagnostics.get_all() {
+            header.push(END_OF_RECORD);
+            header.push_str(&dump_record(&diagnostic.to_record()));
+        }
         header.push(END_OF_HEADER);
 
         header
     }
+
+    fn get_legacy_header_keys(kind: Option<&str>) -> &'static [&'static str] {
+        match kind {
+            None => &["name", "path"],
+            Some(FILE_STAT_RECORD) => &[KIND_KEY, "path", "modified", "size"],
+            Some(PATH_PATTERN_RECORD) => &[KIND_KEY, "pattern"],
+            Some(DIAGNOSTIC_RECORD) => &[KIND_KEY, "path", "reason", "detail"],
+            Some(_) => &[KIND_KEY],
+        }
+    }
 }
 
 impl DynamicSource for MusicLibrary {
-    fn get_tracks(&self) -> Vec<Track> {
-        self.tracks.clone()
+    fn get_tracks(&self) -> &[Track] {
+        &self.tracks
     }
 }
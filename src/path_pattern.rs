@@ -0,0 +1,185 @@
+// Working out track details from where a file is, for files without tags.
+// Patterns look like "{artist}/{album}/{track_number} - {title}.{ext}", and
+// are matched against the end of a file's path, so the pattern above would
+// match "/music/Artist/Album/01 - Title.mp3".
+use crate::error::{Error, Result};
+use crate::tags::TagDetails;
+#[cfg(feature = "export")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "export")]
+use std::convert::TryFrom;
+use std::iter::once;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Placeholder {
+    Title,
+    Artist,
+    Album,
+    TrackNumber,
+    // Matched, but otherwise ignored
+    Extension,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Literal(String),
+    Field(Placeholder),
+}
+
+// Exported as just the pattern text
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "export", derive(Serialize, Deserialize), serde(try_from = "String", into = "String"))]
+pub struct PathPattern {
+    pattern: String,
+    // One list of tokens per path component
+    components: Vec<Vec<Token>>,
+}
+
+impl PathPattern {
+    pub fn new(pattern: &str) -> Result<PathPattern> {
+        let invalid = |error: &str| Error::InvalidPathPattern {
+            pattern: pattern.to_string(),
+            error: error.to_string(),
+        };
+
+        let mut components = Vec::new();
+        for component in pattern.split('/') {
+            let mut tokens = Vec::new();
+            let mut literal = String::new();
+            let mut chars = component.chars();
+            while let Some(c) = chars.next() {
+                if c == '}' {
+                    return Err(invalid("found } without a matching {"));
+                };
+                if c != '{' {
+                    literal.push(c);
+                    continue;
+                };
+
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(invalid("found { without a matching }")),
+                    };
+                }
+                let placeholder = match name.as_str() {
+                    "title" => Placeholder::Title,
+                    "artist" => Placeholder::Artist,
+                    "album" => Placeholder::Album,
+                    "track_number" => Placeholder::TrackNumber,
+                    "ext" => Placeholder::Extension,
+                    _ => return Err(invalid(&format!("unknown placeholder {{{}}}", name))),
+                };
+                if let Some(Token::Field(_)) = tokens.last() {
+                    if literal.is_empty() {
+                        // There'd be no telling where one ended and the next began
+                        return Err(invalid("placeholders must be separated by some text"));
+                    };
+                };
+                if ! literal.is_empty() {
+                    tokens.push(Token::Literal(literal));
+                    literal = String::new();
+                };
+                tokens.push(Token::Field(placeholder));
+            }
+            if ! literal.is_empty() {
+                tokens.push(Token::Literal(literal));
+            };
+            if tokens.is_empty() {
+                return Err(invalid("path components can't be empty"));
+            };
+            components.push(tokens);
+        }
+
+        Ok(PathPattern {
+            pattern: pattern.to_string(),
+            components,
+        })
+    }
+
+    pub fn get_pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    // Gives None if the path doesn't fit the pattern
+    pub(crate) fn infer(&self, path: &Path) -> Option<TagDetails> {
+        let path_components: Vec<String> = path.iter()
+            .map(|component| component.to_string_lossy().to_string())
+            .collect();
+        if path_components.len() < self.components.len() {
+            return None;
+        };
+        let path_components = &path_components[path_components.len() - self.components.len()..];
+
+        let mut details = TagDetails::default();
+        for (tokens, text) in self.components.iter().zip(path_components) {
+            for (placeholder, value) in match_tokens(tokens, text)? {
+                let value = value.trim().to_string();
+                let field = match placeholder {
+                    Placeholder::Title => &mut details.title,
+                    Placeholder::Artist => &mut details.artist,
+                    Placeholder::Album => &mut details.album,
+                    Placeholder::TrackNumber => &mut details.track_number,
+                    Placeholder::Extension => continue,
+                };
+                // The same placeholder turning up twice has to agree with itself
+                match field {
+                    Some(existing) if *existing != value => return None,
+                    _ => *field = Some(value),
+                };
+            }
+        }
+        Some(details)
+    }
+}
+
+#[cfg(feature = "export")]
+impl TryFrom<String> for PathPattern {
+    type Error = Error;
+
+    fn try_from(pattern: String) -> Result<PathPattern> {
+        PathPattern::new(&pattern)
+    }
+}
+
+#[cfg(feature = "export")]
+impl From<PathPattern> for String {
+    fn from(pattern: PathPattern) -> String {
+        pattern.pattern
+    }
+}
+
+// Placeholders take as little as they can, so in "{track_number} - {title}"
+// the track number stops at the first " - ", but will take more if that's
+// what it takes for the rest to match.
+fn match_tokens(tokens: &[Token], text: &str) -> Option<Vec<(Placeholder, String)>> {
+    match tokens.first() {
+        None => if text.is_empty() {
+            Some(Vec::new())
+        } else {
+            None
+        },
+        Some(Token::Literal(literal)) => if text.starts_with(literal.as_str()) {
+            match_tokens(&tokens[1..], &text[literal.len()..])
+        } else {
+            None
+        },
+        Some(Token::Field(placeholder)) => {
+            let ends = text.char_indices().skip(1).map(|(end, _)| end).chain(once(text.len()));
+            for end in ends.filter(|end| *end > 0) {
+                let value = &text[..end];
+                if *placeholder == Placeholder::Extension && value.contains('.') {
+                    break;
+                };
+                if let Some(mut matched) = match_tokens(&tokens[1..], &text[end..]) {
+                    matched.insert(0, (*placeholder, value.to_string()));
+                    return Some(matched);
+                };
+            }
+            None
+        },
+    }
+}
@@ -1,24 +1,181 @@
-use crate::delimiters::{END_OF_FIELD, END_OF_HEADER};
+use crate::bias::{apply_biases, usable, Bias, BiasContext, Candidate, Play};
+use crate::delimiters::{END_OF_HEADER, END_OF_RECORD};
+use crate::error::{Error, Result};
 use crate::music_library::MusicLibrary;
+use crate::playlist_formats::{m3u, playlist_name, pls, xspf};
+use crate::save_format::{dump_record, Record, KIND_KEY};
 use crate::track::Track;
-use crate::shared::{DynamicSource, Saveable};
+use crate::shared::{load_saved, write_saved, DynamicSource, Saveable};
+use rand::seq::SliceRandom;
 use rand::Rng;
+#[cfg(feature = "export")]
+use serde::{Deserialize, Serialize};
 use std::cmp::min;
+use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
-use std::fs::read_to_string;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
 
 const EXTENSION: &str = "playlist";
 
+// Mark header records referring to the saved playlists and libraries which
+// dynamic mode picks tracks from
+const PLAYLIST_SOURCE_RECORD: &str = "playlist_source";
+const LIBRARY_SOURCE_RECORD: &str = "library_source";
+// Marks a header record holding a track in the window of recent picks
+const WINDOW_RECORD: &str = "window";
+
+// How often a dynamic source is picked from, compared with the others
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "export", derive(Serialize, Deserialize))]
+pub enum SourceWeight {
+    // As often as its number of tracks, so each track is as likely as any
+    // other from a source weighted the same way
+    #[default]
+    Tracks,
+    // As often as a source with this many tracks
+    Fixed(usize),
+    // This percentage of all picks, with what's left shared between the
+    // other sources by their weights
+    Share(u8),
+}
+
+impl SourceWeight {
+    // Saved as nothing, a number, or a percentage
+    fn dump(&self) -> String {
+        match self {
+            SourceWeight::Tracks => String::new(),
+            SourceWeight::Fixed(fixed) => fixed.to_string(),
+            SourceWeight::Share(percent) => format!("{}%", percent),
+        }
+    }
 
+    fn parse(weight: &str) -> Option<SourceWeight> {
+        if weight.is_empty() {
+            Some(SourceWeight::Tracks)
+        } else if let Some(percent) = weight.strip_suffix('%') {
+            percent.parse().ok().map(SourceWeight::Share)
+        } else {
+            weight.parse().ok().map(SourceWeight::Fixed)
+        }
+    }
+}
+
+// How a playlist goes through its tracks
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "export", derive(Serialize, Deserialize))]
+pub enum PlayMode {
+    // In order, then on to dynamic mode once they run out
+    #[default]
+    Sequential,
+    // In order, back to the first after the last
+    RepeatAll,
+    // The same track over and over
+    RepeatOne,
+    // Every track once in a random order, then again in a new one
+    Shuffle,
+    // Albums in a random order, each played through in the order the
+    // playlist has them. Tracks without an album are shuffled on their own.
+    AlbumShuffle,
+}
+
+impl PlayMode {
+    // Saved as nothing for sequential, as older playlists were
+    fn dump(&self) -> &'static str {
+        match self {
+            PlayMode::Sequential => "",
+            PlayMode::RepeatAll => "repeat_all",
+            PlayMode::RepeatOne => "repeat_one",
+            PlayMode::Shuffle => "shuffle",
+            PlayMode::AlbumShuffle => "album_shuffle",
+        }
+    }
+
+    fn parse(mode: &str) -> Option<PlayMode> {
+        match mode {
+            "" => Some(PlayMode::Sequential),
+            "repeat_all" => Some(PlayMode::RepeatAll),
+            "repeat_one" => Some(PlayMode::RepeatOne),
+            "shuffle" => Some(PlayMode::Shuffle),
+            "album_shuffle" => Some(PlayMode::AlbumShuffle),
+            _ => None,
+        }
+    }
+
+    fn shuffles(&self) -> bool {
+        *self == PlayMode::Shuffle || *self == PlayMode::AlbumShuffle
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "export", derive(Serialize, Deserialize))]
+struct WeightedSource<T> {
+    source: T,
+    #[cfg_attr(feature = "export", serde(default))]
+    weight: SourceWeight,
+}
+
+// Plays are only kept for biases, which care about the last few hours at most
+const MAX_PLAYS: usize = 500;
+
+// Where a dynamic source was saved, to be loaded along with the playlist. An
+// empty storage path means it was saved alongside the playlist.
 #[derive(Clone)]
+struct SourceReference {
+    kind: String,
+    name: String,
+    storage_path: String,
+    weight: SourceWeight,
+}
+
+// A source which couldn't be loaded with the playlist. It's still saved with
+// it, so it's loaded again once whatever was wrong is put right.
+#[derive(Clone)]
+struct UnloadedSource {
+    reference: SourceReference,
+    error: Arc<Error>,
+}
+
+
+#[derive(Clone)]
+#[cfg_attr(feature = "export", derive(Serialize, Deserialize))]
 pub struct Playlist {
     name: String,
+    #[cfg_attr(feature = "export", serde(default))]
     tracks: Vec<Track>,
+    // Picked again from the dynamic sources rather than exported
+    #[cfg_attr(feature = "export", serde(skip))]
     window: Vec<Track>,
-    dynamic_playlist_sources: Vec<Playlist>,
-    dynamic_library_sources: Vec<MusicLibrary>,
+    #[cfg_attr(feature = "export", serde(default))]
+    dynamic_playlist_sources: Vec<WeightedSource<Playlist>>,
+    #[cfg_attr(feature = "export", serde(default))]
+    dynamic_library_sources: Vec<WeightedSource<MusicLibrary>>,
+    // The place in the play order, which is the tracks' own order unless
+    // shuffling
     pos: Option<usize>,
+    #[cfg_attr(feature = "export", serde(default))]
+    mode: PlayMode,
+    // Where each place in the shuffled play order is in the tracks. Empty
+    // when not shuffling.
+    #[cfg_attr(feature = "export", serde(default))]
+    order: Vec<usize>,
+    // The directory the playlist was loaded from, if it was
+    #[cfg_attr(feature = "export", serde(skip))]
+    storage_path: Option<String>,
+    #[cfg_attr(feature = "export", serde(skip))]
+    biases: Vec<Arc<dyn Bias>>,
+    // How many tracks dynamic mode tries to leave between ones by the same
+    // artist or from the same album
+    #[cfg_attr(feature = "export", serde(default))]
+    artist_separation: usize,
+    #[cfg_attr(feature = "export", serde(default))]
+    album_separation: usize,
+    // Oldest first
+    #[cfg_attr(feature = "export", serde(skip))]
+    plays: Vec<Play>,
+    #[cfg_attr(feature = "export", serde(skip))]
+    unloaded_sources: Vec<UnloadedSource>,
 }
 
 impl Playlist {
@@ -30,52 +187,312 @@ impl Playlist {
             dynamic_playlist_sources: Vec::new(),
             dynamic_library_sources: Vec::new(),
             pos: None,
+            mode: PlayMode::Sequential,
+            order: Vec::new(),
+            storage_path: None,
+            biases: Vec::new(),
+            artist_separation: 0,
+            album_separation: 0,
+            plays: Vec::new(),
+            unloaded_sources: Vec::new(),
         }
     }
 
-    pub fn load(saved_playlist_path: String, saved_playlist_name: String) -> Playlist {
+    // Dynamic sources are loaded too, from wherever they were saved. Any
+    // which can't be are left out rather than the playlist not loading, and
+    // get_source_errors says why.
+    pub fn load(saved_playlist_path: String, saved_playlist_name: String) -> Result<Playlist> {
+        Playlist::load_with_sources(saved_playlist_path, saved_playlist_name, &mut Vec::new())
+    }
+
+    // Playlists can be sources of each other, so any already being loaded
+    // further up are loaded again without their sources rather than forever.
+    // Only the tracks of a source are used, so nothing is lost.
+    fn load_with_sources(saved_playlist_path: String, saved_playlist_name: String, loading: &mut Vec<PathBuf>) -> Result<Playlist> {
         let mut playlist_path = PathBuf::from(&saved_playlist_path);
         playlist_path.push(OsStr::new(&format!("{}.{}", &saved_playlist_name, &EXTENSION)));
 
-        let saved_data = match read_to_string(&playlist_path) {
-            Ok(data) => data,
-            Err(err) => panic!("Could not load playlist from {}: {:#?}", playlist_path.display(), err),
+        let (mut playlist, sources) = load_saved(&playlist_path, Playlist::from_saved)?;
+        playlist.storage_path = Some(saved_playlist_path.clone());
+        if loading.contains(&playlist_path) {
+            return Ok(playlist);
         };
 
-        let header_details = Playlist::process_save_header(&saved_data);
+        loading.push(playlist_path);
+        for mut source in sources {
+            if source.storage_path.is_empty() {
+                source.storage_path = saved_playlist_path.clone();
+            };
+            let storage_path = source.storage_path.clone();
+            let loaded = if source.kind == LIBRARY_SOURCE_RECORD {
+                MusicLibrary::load(storage_path, source.name.clone())
+                    .map(|library| playlist.add_dynamic_library_source(library, Some(source.weight)))
+            } else {
+                Playlist::load_with_sources(storage_path, source.name.clone(), loading)
+                    .map(|source_playlist| playlist.add_dynamic_playlist_source(source_playlist, Some(source.weight)))
+            };
+            if let Err(error) = loaded {
+                playlist.unloaded_sources.push(UnloadedSource {
+                    reference: source,
+                    error: Arc::new(error),
+                });
+            };
+        }
+        loading.pop();
+
+        Ok(playlist)
+    }
+
+    fn from_saved(saved_data: &str, playlist_path: &Path) -> Result<(Playlist, Vec<SourceReference>)> {
+        let header_records = Playlist::process_save_header_records(saved_data, playlist_path)?;
+        let header_details = &header_records[0];
+
+        let mut sources = Vec::new();
+        let mut window = Vec::new();
+        for record in &header_records[1..] {
+            if record.kind() == PLAYLIST_SOURCE_RECORD || record.kind() == LIBRARY_SOURCE_RECORD {
+                let weight = match SourceWeight::parse(record.value("weight")) {
+                    Some(weight) => weight,
+                    None => return Err(Error::CorruptSaveFile {
+                        path: playlist_path.to_path_buf(),
+                        offset: record.offset("weight"),
+                    }),
+                };
+                sources.push(SourceReference {
+                    kind: record.kind().to_string(),
+                    name: record.value("name").to_string(),
+                    storage_path: record.value("path").to_string(),
+                    weight,
+                });
+            } else if record.kind() == WINDOW_RECORD {
+                window.push(window_track(record, playlist_path)?);
+            };
+            // Anything else was added by a newer version, and can be skipped
+        }
 
-        let tracks = Playlist::load_tracks(&saved_data);
+        let tracks = Playlist::load_tracks(saved_data, playlist_path)?;
 
         let pos:Option<usize>;
-        if header_details[1].len() == 0 {
+        if header_details.value("pos").len() == 0 {
             pos = None;
         } else {
-            pos = match header_details[1].parse::<usize>() {
+            pos = match header_details.value("pos").parse::<usize>() {
                 Ok(pos) => Some(pos),
-                Err(err) => panic!("Could not parse playlist position in {}: {:#?}", playlist_path.display(), err),
+                Err(_) => return Err(Error::CorruptSaveFile {
+                    path: playlist_path.to_path_buf(),
+                    offset: header_details.offset("pos"),
+                }),
             }
         };
 
-        Playlist{
-            name: header_details[0].to_string(),
+        let corrupt = |key| Error::CorruptSaveFile {
+            path: playlist_path.to_path_buf(),
+            offset: header_details.offset(key),
+        };
+        let mode = PlayMode::parse(header_details.value("mode")).ok_or_else(|| corrupt("mode"))?;
+        let order = match header_details.value("order") {
+            "" => Vec::new(),
+            order => {
+                let order: Vec<usize> = order.split(',')
+                    .map(|index| index.parse::<usize>())
+                    .collect::<std::result::Result<_, _>>()
+                    .map_err(|_| corrupt("order"))?;
+                // Every track once
+                let mut sorted = order.clone();
+                sorted.sort_unstable();
+                if ! sorted.into_iter().eq(0..tracks.len()) {
+                    return Err(corrupt("order"));
+                };
+                order
+            },
+        };
+
+        let separation = |key| match header_details.value(key) {
+            "" => Ok(0),
+            separation => separation.parse::<usize>().map_err(|_| corrupt(key)),
+        };
+
+        let mut playlist = Playlist{
+            name: header_details.value("name").to_string(),
             pos: pos,
+            mode,
+            order,
             tracks: tracks,
-            window: Vec::new(),
+            window,
             dynamic_playlist_sources: Vec::new(),
             dynamic_library_sources: Vec::new(),
-        }
+            storage_path: None,
+            biases: Vec::new(),
+            artist_separation: separation("artist_separation")?,
+            album_separation: separation("album_separation")?,
+            plays: Vec::new(),
+            unloaded_sources: Vec::new(),
+        };
+        if ! playlist.mode.shuffles() {
+            playlist.order.clear();
+        } else if playlist.order.is_empty() {
+            playlist.order = playlist.shuffled_order(None, None);
+        };
+        Ok((playlist, sources))
     }
 
     pub fn reset_position(&mut self) {
         self.pos = None;
     }
 
+    // Where in the play order the last track given was, if anywhere
+    pub fn get_position(&self) -> Option<usize> {
+        self.pos
+    }
+
+    // Tracks added while shuffling come at the end of the current order
     pub fn add_track(&mut self, track: Track) {
         &self.tracks.push(track);
+        if self.mode.shuffles() {
+            self.order.push(self.tracks.len() - 1);
+        };
     }
 
     pub fn remove_track(&mut self, index: usize) {
         &self.tracks.remove(index);
+        // Where the track was in the play order
+        let removed = if self.mode.shuffles() {
+            let removed = self.order.iter().position(|track_index| *track_index == index);
+            if let Some(removed) = removed {
+                self.order.remove(removed);
+                for track_index in self.order.iter_mut().filter(|track_index| **track_index > index) {
+                    *track_index -= 1;
+                }
+            };
+            removed
+        } else {
+            Some(index)
+        };
+
+        // Keep the place of the track playing. If that's the one removed, the
+        // track after it comes next, or is the one repeated.
+        self.pos = match (self.pos, removed) {
+            (Some(pos), Some(removed)) if removed < pos => Some(pos - 1),
+            (Some(pos), Some(removed)) if removed == pos && self.mode != PlayMode::RepeatOne => pos.checked_sub(1),
+            (pos, _) => pos,
+        };
+    }
+
+    // Changing mode carries on from the track playing, if there is one, so
+    // a new shuffled order starts with it
+    pub fn set_play_mode(&mut self, mode: PlayMode) {
+        let current = self.pos
+            .and_then(|pos| self.track_index(pos))
+            .filter(|index| *index < self.tracks.len());
+        self.mode = mode;
+        if mode.shuffles() {
+            self.order = self.shuffled_order(current, None);
+            self.pos = current.and_then(|index| self.order.iter().position(|track_index| *track_index == index));
+        } else {
+            self.order.clear();
+            if current.is_some() {
+                self.pos = current;
+            };
+        };
+    }
+
+    pub fn get_play_mode(&self) -> PlayMode {
+        self.mode
+    }
+
+    // Where the track at a place in the play order is in the tracks
+    fn track_index(&self, pos: usize) -> Option<usize> {
+        if self.order.is_empty() {
+            Some(pos)
+        } else {
+            self.order.get(pos).copied()
+        }
+    }
+
+    // A new random order of all the tracks, starting with the album or track
+    // of first if given. Otherwise it doesn't start with not_first, so a
+    // track isn't played twice in a row when the order wraps round.
+    fn shuffled_order(&self, first: Option<usize>, not_first: Option<usize>) -> Vec<usize> {
+        // Shuffled as a whole, so tracks in a group stay together in order
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        if self.mode == PlayMode::AlbumShuffle {
+            let mut albums: HashMap<(String, String), usize> = HashMap::new();
+            for (index, track) in self.tracks.iter().enumerate() {
+                match album_key(track) {
+                    Some(album) => match albums.get(&album) {
+                        Some(group) => groups[*group].push(index),
+                        None => {
+                            albums.insert(album, groups.len());
+                            groups.push(vec!(index));
+                        },
+                    },
+                    None => groups.push(vec!(index)),
+                };
+            }
+        } else {
+            groups = (0..self.tracks.len()).map(|index| vec!(index)).collect();
+        };
+
+        let mut rng = rand::thread_rng();
+        groups.shuffle(&mut rng);
+        let group_of = |index: usize, groups: &[Vec<usize>]| groups.iter().position(|group| group.contains(&index));
+        if let Some(group) = first.and_then(|index| group_of(index, &groups)) {
+            let group = groups.remove(group);
+            groups.insert(0, group);
+        } else if not_first.and_then(|index| group_of(index, &groups)) == Some(0) && groups.len() > 1 {
+            let other = rng.gen_range(1, groups.len());
+            groups.swap(0, other);
+        };
+        groups.concat()
+    }
+
+    // Walks the tracks in order, then picks from the dynamic sources
+    fn next_in_order(&mut self) -> Option<Track> {
+        match self.pos {
+            Some(pos) => self.pos = Some(min(pos + 1, self.tracks.len())),
+            None => self.pos = Some(0),
+        };
+
+        if self.pos.unwrap() < self.tracks.len() {
+            match self.tracks.get(self.pos.unwrap()) {
+                Some(track) => Some(track.clone()),
+                None => None,
+            }
+        } else {
+            // None if there's nothing to pick from
+            match self.get_random_next_track() {
+                Some(track) => {
+                    self.add_to_window(track.clone());
+                    self.add_track(track.clone());
+                    Some(track)
+                },
+                None => None,
+            }
+        }
+    }
+
+    // Modes other than sequential go round the tracks the playlist has,
+    // rather than picking more
+    fn next_repeating(&mut self) -> Option<Track> {
+        if self.tracks.is_empty() {
+            return None;
+        };
+        let pos = match self.pos {
+            None => 0,
+            Some(pos) if self.mode == PlayMode::RepeatOne && pos < self.tracks.len() => pos,
+            // The track has since been removed
+            Some(_) if self.mode == PlayMode::RepeatOne => 0,
+            Some(pos) if pos + 1 < self.tracks.len() => pos + 1,
+            Some(pos) => {
+                if self.mode.shuffles() {
+                    let last = self.track_index(pos);
+                    self.order = self.shuffled_order(None, last);
+                };
+                0
+            },
+        };
+        self.pos = Some(pos);
+        self.track_index(pos).and_then(|index| self.tracks.get(index)).cloned()
     }
 
     pub fn prev(&mut self) -> Option<Track> {
@@ -87,7 +504,7 @@ impl Playlist {
                     result = None;
                 } else {
                     self.pos = Some(pos - 1);
-                    match self.tracks.get(self.pos.unwrap()) {
+                    match self.track_index(pos - 1).and_then(|index| self.tracks.get(index)) {
                         Some(track) => result = Some(track.clone()),
                         None => (),
                     };
@@ -100,28 +517,38 @@ impl Playlist {
 
     fn add_to_window(&mut self, track: Track) {
         self.window.push(track.clone());
-        if self.window.len() > self.get_window_size() {
+        // Sources can have changed since the window was filled
+        let window_size = self.get_window_size();
+        while self.window.len() > window_size {
             self.window.remove(0);
-        };
+        }
     }
 
+    // Just under half of the tracks which are likely to come up, so the
+    // order isn't the same each time round. A source picked from more than
+    // its tracks would suggest fills the window faster, so it's sized for
+    // whichever source fills it first.
     fn get_window_size(&self) -> usize {
-        let mut window_size = 0;
-        for source in self.dynamic_playlist_sources.clone() {
-            window_size += source.get_weight()
-        };
-        for source in self.dynamic_library_sources.clone() {
-            window_size += source.get_weight()
-        };
+        let mut window_size = None;
+        let mut distinct_tracks = HashSet::new();
+        for (_, source, share) in self.get_source_shares() {
+            if share > 0.0 {
+                let size = source.get_weight() as f64 / (2.0 * share);
+                window_size = Some(window_size.map_or(size, |smallest: f64| smallest.min(size)));
+                distinct_tracks.extend(source.get_tracks().iter().map(|track| track.path.as_str()));
+            };
+        }
+        // Sources sharing tracks mustn't leave the window covering all of them
+        let window_size = window_size.map(|size| size.min(distinct_tracks.len() as f64 / 2.0));
 
-        // Effectively this is rounding up the division
-        window_size += 1;
-        window_size = window_size / 2;
+        // Rounding up, allowing for rounding errors so sources weighted by
+        // their tracks give exactly half of them
+        let mut window_size = window_size.map_or(0, |size| (size - 1e-9).ceil() as usize);
 
         // Make the default window size just less than half of a smaller source set
         // Otherwise, with e.g. 3 tracks we will always play them in an order that is
         // determined randomly one.
-        window_size -= 1;
+        window_size = window_size.saturating_sub(1);
 
         // Arbitrary max window size
         if window_size > 30 {
@@ -131,45 +558,191 @@ impl Playlist {
         window_size
     }
 
+    fn get_sources(&self) -> Vec<(&str, &dyn DynamicSource, SourceWeight)> {
+        let playlists = self.dynamic_playlist_sources.iter()
+            .map(|weighted| (weighted.source.get_name(), &weighted.source as &dyn DynamicSource, weighted.weight));
+        let libraries = self.dynamic_library_sources.iter()
+            .map(|weighted| (weighted.source.get_name(), &weighted.source as &dyn DynamicSource, weighted.weight));
+        playlists.chain(libraries).collect()
+    }
+
+    // What part of all picks each source should get, adding up to 1 unless
+    // there's nothing to pick from. Sources without tracks get nothing
+    // whatever their weight.
+    fn get_source_shares(&self) -> Vec<(&str, &dyn DynamicSource, f64)> {
+        let sources: Vec<(&str, &dyn DynamicSource, SourceWeight)> = self.get_sources().into_iter()
+            .filter(|(_, source, _)| source.get_weight() > 0)
+            .collect();
+
+        let mut shared = 0.0;
+        let mut unshared = 0.0;
+        for (_, source, weight) in &sources {
+            match weight {
+                SourceWeight::Tracks => unshared += source.get_weight() as f64,
+                SourceWeight::Fixed(fixed) => unshared += *fixed as f64,
+                SourceWeight::Share(percent) => shared += f64::from(min(*percent, 100)) / 100.0,
+            };
+        }
+        // Shares adding up to more than everything are scaled down, and if
+        // there are no other sources to take what's left they're scaled up
+        let scale = if shared > 1.0 || (shared > 0.0 && unshared == 0.0) { 1.0 / shared } else { 1.0 };
+        let left = 1.0 - shared * scale;
+
+        sources.into_iter().map(|(name, source, weight)| {
+            let share = match weight {
+                SourceWeight::Share(percent) => f64::from(min(percent, 100)) / 100.0 * scale,
+                _ if unshared == 0.0 => 0.0,
+                SourceWeight::Tracks => left * source.get_weight() as f64 / unshared,
+                SourceWeight::Fixed(fixed) => left * fixed as f64 / unshared,
+            };
+            (name, source, share)
+        }).collect()
+    }
+
+    // Each track outside the window starts with an even part of its
+    // source's share, which the biases then adjust. Sources with nothing
+    // outside the window are left out, so the window doesn't change how often
+    // the others come up.
     fn get_random_next_track(&self) -> Option<Track> {
-        let mut next_track = Vec::new();
-        let mut dyn_weight = 0;
-        for source in self.dynamic_playlist_sources.clone() {
-            dyn_weight += source.get_weight()
+        let mut candidates = self.get_candidates(true);
+        if candidates.is_empty() {
+            // Everything is in the window, and a repeat is better than nothing
+            candidates = self.get_candidates(false);
+        };
+        let candidates = self.separate(candidates);
+        let unbiased: Vec<f64> = candidates.iter().map(|(_, weight)| *weight).collect();
+        let candidates: Vec<Candidate> = candidates.into_iter().map(|(candidate, _)| candidate).collect();
+
+        let mut weights = unbiased.clone();
+        let context = BiasContext {
+            plays: &self.plays,
+            now: SystemTime::now(),
         };
-        for source in self.dynamic_library_sources.clone() {
-            dyn_weight += source.get_weight()
+        apply_biases(&self.biases, &candidates, &mut weights, &context);
+        // Biases that rule out everything have already been skipped, but one
+        // could still leave weights that can't be picked from
+        if ! usable(&weights) {
+            weights = unbiased;
         };
-        if dyn_weight > 0 {
-            let mut dyn_source_num = rand::thread_rng().gen_range(1, dyn_weight);
-            for source in self.dynamic_playlist_sources.clone() {
-                let source_weight = source.get_weight();
-                if dyn_source_num <= source_weight {
-                    next_track.push(source.get_random_track());
-                    dyn_weight = 0;
+        if ! usable(&weights) {
+            return None;
+        };
+
+        let total: f64 = weights.iter().sum();
+        let mut pick = rand::thread_rng().gen_range(0.0, total);
+        let mut chosen = None;
+        for (candidate, weight) in candidates.iter().zip(&weights) {
+            if *weight > 0.0 {
+                chosen = Some(candidate.track);
+                if pick < *weight {
                     break;
-                } else {
-                    dyn_source_num -= source_weight;
                 };
             };
-            if dyn_weight > 0 {
-                for source in self.dynamic_library_sources.clone() {
-                    let source_weight = source.get_weight();
-                    if dyn_source_num <= source_weight {
-                        next_track.push(source.get_random_track());
-                        break;
-                    } else {
-                        dyn_source_num -= source_weight;
-                    };
-                };
+            pick -= weight;
+        }
+        // Rounding can leave a little over for the last track
+        chosen.cloned()
+    }
+
+    // Every track that can be picked, with its part of its sources' shares.
+    // Tracks in more than one source are only candidates once, from the
+    // first source they're in, and are as likely as all of those sources
+    // would make them between them.
+    fn get_candidates(&self, skip_window: bool) -> Vec<(Candidate<'_>, f64)> {
+        let mut candidates: Vec<(Candidate, f64)> = Vec::new();
+        let mut by_path: HashMap<&str, usize> = HashMap::new();
+        for (name, source, share) in self.get_source_shares() {
+            let tracks: Vec<&Track> = source.get_tracks().iter()
+                .filter(|track| ! skip_window || ! self.in_window(track))
+                .collect();
+            if tracks.is_empty() || share <= 0.0 {
+                continue;
             };
-            match next_track.get(0) {
-                Some(track) => Some(track.clone()),
-                None => None,
+
+            let weight = share / tracks.len() as f64;
+            for track in tracks {
+                match by_path.get(track.path.as_str()) {
+                    Some(index) => candidates[*index].1 += weight,
+                    None => {
+                        by_path.insert(track.path.as_str(), candidates.len());
+                        candidates.push((Candidate { track, source: name }, weight));
+                    },
+                };
             }
-        } else {
-            None
         }
+        candidates
+    }
+
+    // Tracks are the same if they're the same file, as sources can have
+    // different copies of a track's details
+    fn in_window(&self, track: &Track) -> bool {
+        self.window.iter().any(|recent| recent.path == track.path)
+    }
+
+    // Leaves out candidates by the same artist or from the same album as the
+    // tracks just before. If that leaves nothing, as there aren't enough
+    // artists or albums to go round, the gaps are narrowed until something
+    // fits.
+    fn separate<'a>(&self, candidates: Vec<(Candidate<'a>, f64)>) -> Vec<(Candidate<'a>, f64)> {
+        let previous = &self.tracks[..min(self.pos.unwrap_or(0), self.tracks.len())];
+        let mut artist_gap = self.artist_separation;
+        let mut album_gap = self.album_separation;
+        while artist_gap > 0 || album_gap > 0 {
+            let recent_artists: Vec<String> = previous.iter().rev().take(artist_gap).filter_map(artist_key).collect();
+            let recent_albums: Vec<(String, String)> = previous.iter().rev().take(album_gap).filter_map(album_key).collect();
+            let separated: Vec<(Candidate, f64)> = candidates.iter()
+                .filter(|(candidate, _)| artist_key(candidate.track).is_none_or(|artist| ! recent_artists.contains(&artist)))
+                .filter(|(candidate, _)| album_key(candidate.track).is_none_or(|album| ! recent_albums.contains(&album)))
+                .copied()
+                .collect();
+            if ! separated.is_empty() {
+                return separated;
+            };
+
+            artist_gap = artist_gap.saturating_sub(1);
+            album_gap = album_gap.saturating_sub(1);
+        }
+        candidates
+    }
+
+    // Dynamic mode tries to leave at least this many tracks between two by
+    // the same artist. 0, the default, doesn't keep them apart at all.
+    pub fn set_artist_separation(&mut self, tracks: usize) {
+        self.artist_separation = tracks;
+    }
+
+    pub fn get_artist_separation(&self) -> usize {
+        self.artist_separation
+    }
+
+    // As for artists, but for tracks from the same album
+    pub fn set_album_separation(&mut self, tracks: usize) {
+        self.album_separation = tracks;
+    }
+
+    pub fn get_album_separation(&self) -> usize {
+        self.album_separation
+    }
+
+    // Biases steer dynamic mode's picks, in the order they're added
+    pub fn add_bias<B: Bias + 'static>(&mut self, bias: B) {
+        self.biases.push(Arc::new(bias));
+    }
+
+    pub fn clear_biases(&mut self) {
+        self.biases.clear();
+    }
+
+    // Tracks handed out by the playlist are recorded as played already, so
+    // this is for plays from elsewhere, which biases should know about too
+    pub fn record_play(&mut self, track: Track, at: SystemTime) {
+        self.plays.push(Play {
+            track,
+            at,
+        });
+        if self.plays.len() > MAX_PLAYS {
+            self.plays.remove(0);
+        };
     }
 
     pub fn get(&self, pos: usize) -> Option<Track> {
@@ -179,24 +752,130 @@ impl Playlist {
         }
     }
 
-    pub fn add_dynamic_playlist_source(&mut self, source: Playlist) {
-        self.dynamic_playlist_sources.push(source.clone());
+    // Without a weight, sources are picked from as often as their number of
+    // tracks would suggest
+    pub fn add_dynamic_playlist_source(&mut self, source: Playlist, weight: Option<SourceWeight>) {
+        self.dynamic_playlist_sources.push(WeightedSource {
+            source,
+            weight: weight.unwrap_or_default(),
+        });
+    }
+
+    pub fn add_dynamic_library_source(&mut self, source: MusicLibrary, weight: Option<SourceWeight>) {
+        self.dynamic_library_sources.push(WeightedSource {
+            source,
+            weight: weight.unwrap_or_default(),
+        });
+    }
+
+    // Sets the weight of every source with the name, playlist or library.
+    // Returns false if there were none.
+    pub fn set_source_weight(&mut self, name: &str, weight: SourceWeight) -> bool {
+        let mut found = false;
+        for existing in self.dynamic_playlist_sources.iter_mut().filter(|existing| existing.source.get_name() == name) {
+            existing.weight = weight;
+            found = true;
+        }
+        for existing in self.dynamic_library_sources.iter_mut().filter(|existing| existing.source.get_name() == name) {
+            existing.weight = weight;
+            found = true;
+        }
+        found
+    }
+
+    pub fn get_source_weight(&self, name: &str) -> Option<SourceWeight> {
+        let playlists = self.dynamic_playlist_sources.iter()
+            .map(|existing| (existing.source.get_name(), existing.weight));
+        let libraries = self.dynamic_library_sources.iter()
+            .map(|existing| (existing.source.get_name(), existing.weight));
+        playlists.chain(libraries)
+            .find(|(existing, _)| *existing == name)
+            .map(|(_, weight)| weight)
     }
 
-    pub fn add_dynamic_library_source(&mut self, source: MusicLibrary) {
-        self.dynamic_library_sources.push(source.clone());
+    // Swap in a newer copy of a library source, e.g. after it has been
+    // rescanned. Returns false if there was no source with the same name.
+    pub fn update_dynamic_library_source(&mut self, source: MusicLibrary) -> bool {
+        let existing = self.dynamic_library_sources.iter_mut()
+            .find(|existing| existing.source.get_name() == source.get_name());
+        match existing {
+            Some(existing) => {
+                existing.source = source;
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Why each of the sources saved with the playlist that couldn't be
+    // loaded wasn't
+    pub fn get_source_errors(&self) -> Vec<&Error> {
+        self.unloaded_sources.iter().map(|unloaded| unloaded.error.as_ref()).collect()
     }
 
     pub fn get_dynamic_playlist_sources(&self) -> Vec<Playlist> {
-        self.dynamic_playlist_sources.clone()
+        self.dynamic_playlist_sources.iter().map(|weighted| weighted.source.clone()).collect()
     }
 
     pub fn get_dynamic_library_sources(&self) -> Vec<MusicLibrary> {
-        self.dynamic_library_sources.clone()
+        self.dynamic_library_sources.iter().map(|weighted| weighted.source.clone()).collect()
+    }
+
+    // Reads an M3U or M3U8 playlist from another player, named after its
+    // file. Entries are matched to tracks in the library, and any that
+    // aren't in it are kept with what the playlist said about them.
+    pub fn import_m3u(path: &Path, library: &MusicLibrary) -> Result<Playlist> {
+        let mut playlist = Playlist::new(playlist_name(path));
+        playlist.tracks = m3u::read(path, library)?;
+        Ok(playlist)
+    }
+
+    // Writes extended M3U. Paths are relative to relative_to if given,
+    // usually the directory the playlist is going in, otherwise as they are.
+    pub fn export_m3u(&self, path: &Path, relative_to: Option<&Path>) -> Result<()> {
+        m3u::write(path, &self.tracks, relative_to)
+    }
+
+    // Named after the playlist's title, or the file if it doesn't have one
+    pub fn import_xspf(path: &Path, library: &MusicLibrary) -> Result<Playlist> {
+        let (name, tracks) = xspf::read(path, library)?;
+        let mut playlist = Playlist::new(name.unwrap_or_else(|| playlist_name(path)));
+        playlist.tracks = tracks;
+        Ok(playlist)
+    }
+
+    pub fn export_xspf(&self, path: &Path, relative_to: Option<&Path>) -> Result<()> {
+        xspf::write(path, &self.name, &self.tracks, relative_to)
+    }
+
+    pub fn import_pls(path: &Path, library: &MusicLibrary) -> Result<Playlist> {
+        let mut playlist = Playlist::new(playlist_name(path));
+        playlist.tracks = pls::read(path, library)?;
+        Ok(playlist)
+    }
+
+    pub fn export_pls(&self, path: &Path, relative_to: Option<&Path>) -> Result<()> {
+        pls::write(path, &self.tracks, relative_to)
     }
 }
 
 impl Saveable for Playlist {
+    // Sources which weren't loaded from anywhere are saved alongside the
+    // playlist, so they can be loaded with it
+    fn save(&self, data_storage_path: String) -> Result<()> {
+        for weighted in &self.dynamic_playlist_sources {
+            if weighted.source.storage_path.is_none() {
+                weighted.source.save(data_storage_path.clone())?;
+            };
+        }
+        for weighted in &self.dynamic_library_sources {
+            if weighted.source.get_storage_path().is_none() {
+                weighted.source.save(data_storage_path.clone())?;
+            };
+        }
+        write_saved(self, data_storage_path)
+    }
+
     fn get_extension(&self) -> &str {
         EXTENSION
     }
@@ -218,18 +897,79 @@ impl Saveable for Playlist {
         };
 
         // Generate header
-        header.push_str(&self.name);
-        header.push(END_OF_FIELD);
-        header.push_str(&pos_string);
+        let separation = |tracks: usize| if tracks == 0 { String::new() } else { tracks.to_string() };
+        header.push_str(&dump_record(&[
+            ("name", self.name.clone()),
+            ("pos", pos_string),
+            ("mode", String::from(self.mode.dump())),
+            ("order", self.order.iter().map(|index| index.to_string()).collect::<Vec<String>>().join(",")),
+            ("artist_separation", separation(self.artist_separation)),
+            ("album_separation", separation(self.album_separation)),
+        ]));
+        // Sources which weren't loaded from anywhere are saved alongside the
+        // playlist
+        let playlist_sources = self.dynamic_playlist_sources.iter()
+            .map(|weighted| (PLAYLIST_SOURCE_RECORD, weighted.source.get_name(), weighted.source.storage_path.as_deref(), weighted.weight));
+        let library_sources = self.dynamic_library_sources.iter()
+            .map(|weighted| (LIBRARY_SOURCE_RECORD, weighted.source.get_name(), weighted.source.get_storage_path(), weighted.weight));
+        let unloaded_sources = self.unloaded_sources.iter()
+            .map(|unloaded| &unloaded.reference)
+            .map(|reference| (reference.kind.as_str(), reference.name.as_str(), Some(reference.storage_path.as_str()), reference.weight));
+        for (kind, name, storage_path, weight) in playlist_sources.chain(library_sources).chain(unloaded_sources) {
+            header.push(END_OF_RECORD);
+            header.push_str(&dump_record(&[
+                (KIND_KEY, String::from(kind)),
+                ("name", name.to_string()),
+                ("path", storage_path.unwrap_or_default().to_string()),
+                ("weight", weight.dump()),
+            ]));
+        }
+        for track in &self.window {
+            let mut fields = vec!((KIND_KEY, String::from(WINDOW_RECORD)));
+            fields.append(&mut track.to_record());
+            header.push(END_OF_RECORD);
+            header.push_str(&dump_record(&fields));
+        }
         header.push(END_OF_HEADER);
 
         header
     }
+
+    fn get_legacy_header_keys(kind: Option<&str>) -> &'static [&'static str] {
+        match kind {
+            None => &["name", "pos"],
+            // Only the first record was ever saved positionally
+            Some(_) => &[KIND_KEY],
+        }
+    }
+}
+
+// Tracks with nothing to go on aren't all by the same artist
+fn artist_key(track: &Track) -> Option<String> {
+    let artist = track.artist.trim().to_lowercase();
+    if artist.is_empty() { None } else { Some(artist) }
+}
+
+// Albums are told apart by their artist too, as plenty share a name
+fn album_key(track: &Track) -> Option<(String, String)> {
+    let album = track.album.trim().to_lowercase();
+    let artist = if track.album_artist.trim().is_empty() { &track.artist } else { &track.album_artist };
+    if album.is_empty() { None } else { Some((album, artist.trim().to_lowercase())) }
+}
+
+fn window_track(record: &Record, playlist_path: &Path) -> Result<Track> {
+    match Track::from_record(record) {
+        Err(Error::CorruptSaveFile { offset, .. }) => Err(Error::CorruptSaveFile {
+            path: playlist_path.to_path_buf(),
+            offset,
+        }),
+        result => result,
+    }
 }
 
 impl DynamicSource for Playlist {
-    fn get_tracks(&self) -> Vec<Track> {
-        self.tracks.clone()
+    fn get_tracks(&self) -> &[Track] {
+        &self.tracks
     }
 }
 
@@ -237,40 +977,15 @@ impl Iterator for Playlist {
     type Item = Track;
 
     fn next(&mut self) -> Option<Track> {
-        match self.pos {
-            Some(pos) => self.pos = Some(min(pos + 1, self.tracks.len())),
-            None => self.pos = Some(0),
+        let next_track = if self.mode == PlayMode::Sequential {
+            self.next_in_order()
+        } else {
+            self.next_repeating()
         };
 
-        if self.pos.unwrap() < self.tracks.len() {
-            match self.tracks.get(self.pos.unwrap()) {
-                Some(track) => Some(track.clone()),
-                None => None,
-            }
-        } else {
-            let mut next_track:Option<Track> = None;
-            let window = self.window.clone();
-            while next_track == None {
-                next_track = self.get_random_next_track();
-                match &next_track {
-                    Some(track) => {
-                        if window.contains(&track) {
-                            next_track = None;
-                        };
-                    },
-                    // If we receive None then for some reason we can't get a
-                    // next track, so return it.
-                    None => break,
-                };
-            };
-            match next_track {
-                Some(track) => {
-                    self.add_to_window(track.clone());
-                    self.add_track(track.clone());
-                    Some(track)
-                },
-                None => None,
-            }
-        }
+        if let Some(track) = &next_track {
+            self.record_play(track.clone(), SystemTime::now());
+        };
+        next_track
     }
 }
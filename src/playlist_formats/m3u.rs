@@ -0,0 +1,80 @@
+// M3U is a list of locations, one per line. Extended M3U starts with
+// #EXTM3U, and puts a line before each location like:
+// #EXTINF:<seconds, or -1 if not known>,<artist> - <title>
+// Other lines starting with # are comments, or extensions we don't use.
+use super::{display, playlist_dir, read_text, relative_location, split_display, Entry, TrackMatcher};
+use crate::error::{Error, Result};
+use crate::music_library::MusicLibrary;
+use crate::track::Track;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+const HEADER: &str = "#EXTM3U";
+const INFO: &str = "#EXTINF:";
+
+pub fn read(path: &Path, library: &MusicLibrary) -> Result<Vec<Track>> {
+    let text = read_text(path)?;
+    let matcher = TrackMatcher::new(library);
+    let dir = playlist_dir(path);
+
+    let mut tracks = Vec::new();
+    let mut info = None;
+    for line in text.lines().map(|line| line.trim()) {
+        if line.is_empty() || line == HEADER {
+            continue;
+        };
+        if let Some(line_info) = line.strip_prefix(INFO) {
+            info = Some(parse_info(line_info));
+            continue;
+        };
+        if line.starts_with('#') {
+            continue;
+        };
+
+        // Info only applies to the location straight after it
+        let mut entry = info.take().unwrap_or_default();
+        entry.location = line.to_string();
+        tracks.push(matcher.resolve(entry, &dir));
+    }
+    Ok(tracks)
+}
+
+// <seconds>,<artist> - <title>, though plenty of players leave out the
+// artist, and some add attributes before the comma
+fn parse_info(info: &str) -> Entry {
+    let mut parts = info.splitn(2, ',');
+    let duration = parts.next()
+        .and_then(|seconds| seconds.split_whitespace().next())
+        .and_then(|seconds| seconds.parse::<f64>().ok())
+        .filter(|seconds| *seconds > 0.0)
+        .map(|seconds| Duration::from_millis((seconds * 1000.0) as u64));
+    let (artist, title) = split_display(parts.next().unwrap_or(""));
+    Entry {
+        title,
+        artist,
+        duration,
+        ..Entry::default()
+    }
+}
+
+// Always written as UTF-8, which is what .m3u8 means, and what most players
+// assume for .m3u these days too
+pub fn write(path: &Path, tracks: &[Track], relative_to: Option<&Path>) -> Result<()> {
+    let mut text = String::from(HEADER);
+    text.push('\n');
+    for track in tracks {
+        let seconds = match track.duration {
+            Some(duration) => duration.as_secs().to_string(),
+            None => String::from("-1"),
+        };
+        text.push_str(&format!("{}{},{}\n", INFO, seconds, display(track)));
+        text.push_str(&relative_location(&track.path, relative_to));
+        text.push('\n');
+    }
+
+    match fs::write(path, text) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(Error::io(path, err)),
+    }
+}
@@ -0,0 +1,230 @@
+// Playlist files written by other players. Their entries are matched up
+// with tracks in a library where possible, so they pick up everything we
+// know about them, and otherwise kept as tracks with just what the file said.
+pub mod m3u;
+pub mod pls;
+pub mod xspf;
+
+use crate::error::{Error, Result};
+use crate::music_library::MusicLibrary;
+use crate::shared::Saveable;
+use crate::track::Track;
+use std::collections::HashMap;
+use std::fs::read;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+
+// What a playlist file says about one of its entries
+#[derive(Default)]
+pub(crate) struct Entry {
+    pub location: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+pub(crate) struct TrackMatcher {
+    by_path: HashMap<String, Track>,
+    // Lower cased (artist, title)
+    by_details: HashMap<(String, String), Track>,
+}
+
+impl TrackMatcher {
+    pub fn new(library: &MusicLibrary) -> TrackMatcher {
+        let mut by_path = HashMap::new();
+        let mut by_details = HashMap::new();
+        for track in library.get_tracks() {
+            by_details.entry(details_key(&track.artist, &track.track_name)).or_insert_with(|| track.clone());
+            by_path.insert(track.path.clone(), track);
+        }
+        TrackMatcher {
+            by_path,
+            by_details,
+        }
+    }
+
+    // Matches by location first, then by artist and title, as the same
+    // music is often in a different place on another machine. Entries that
+    // don't match anything are kept with what the playlist said about them.
+    pub fn resolve(&self, entry: Entry, playlist_dir: &Path) -> Track {
+        let path = resolve_location(&entry.location, playlist_dir);
+        if let Some(track) = self.by_path.get(&path) {
+            return track.clone();
+        };
+        if let Some(track) = self.find_by_details(&entry) {
+            return track;
+        };
+
+        Track {
+            track_name: entry.title.unwrap_or_default(),
+            artist: entry.artist.unwrap_or_default(),
+            album: entry.album.unwrap_or_default(),
+            path,
+            duration: entry.duration,
+            ..Track::default()
+        }
+    }
+
+    // Entries without a location can only be found this way
+    pub fn find_by_details(&self, entry: &Entry) -> Option<Track> {
+        match (&entry.artist, &entry.title) {
+            (Some(artist), Some(title)) => self.by_details.get(&details_key(artist, title)).cloned(),
+            _ => None,
+        }
+    }
+}
+
+fn details_key(artist: &str, title: &str) -> (String, String) {
+    (artist.trim().to_lowercase(), title.trim().to_lowercase())
+}
+
+// Formats with a single title for each entry usually mean "<artist> - <title>",
+// but plenty of players only give the title. Gives the artist and title.
+pub(crate) fn split_display(display: &str) -> (Option<String>, Option<String>) {
+    let display = display.trim();
+    let (artist, title) = match display.find(" - ") {
+        Some(split) => (Some(display[..split].trim().to_string()), display[split + 3..].trim().to_string()),
+        None => (None, display.to_string()),
+    };
+    (artist, if title.is_empty() { None } else { Some(title) })
+}
+
+pub(crate) fn display(track: &Track) -> String {
+    if track.artist.is_empty() {
+        track.track_name.clone()
+    } else {
+        format!("{} - {}", track.artist, track.track_name)
+    }
+}
+
+// Locations can be absolute or relative to the playlist, or file URLs.
+// Anything else, such as a stream URL, is left as it is.
+pub(crate) fn resolve_location(location: &str, playlist_dir: &Path) -> String {
+    let location = location.trim();
+    let location = match location.strip_prefix("file://") {
+        // Usually an empty host, as in file:///music/track.mp3
+        Some(url_path) => percent_decode(url_path.trim_start_matches("localhost")),
+        None if is_url(location) => return location.to_string(),
+        None => location.to_string(),
+    };
+    // Playlists from Windows use backslashes, which are never found in
+    // paths elsewhere in practice
+    let location = if cfg!(windows) { location } else { location.replace('\\', "/") };
+
+    let path = Path::new(&location);
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        playlist_dir.join(path)
+    };
+    normalise(&path).to_string_lossy().to_string()
+}
+
+pub(crate) fn is_url(location: &str) -> bool {
+    match location.find("://") {
+        Some(scheme_end) => location[..scheme_end].chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.'),
+        None => false,
+    }
+}
+
+// Removes "." and ".." without touching the filesystem, so paths match the
+// ones in the library whether or not the files are there.
+fn normalise(path: &Path) -> PathBuf {
+    let mut normalised = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                normalised.pop();
+            },
+            other => normalised.push(other.as_os_str()),
+        };
+    }
+    normalised
+}
+
+// How a track's path is written to a playlist file: relative to a directory
+// if one is given, otherwise as it is.
+pub(crate) fn relative_location(path: &str, relative_to: Option<&Path>) -> String {
+    let relative_to = match relative_to {
+        Some(relative_to) => normalise(relative_to),
+        None => return path.to_string(),
+    };
+    let path = normalise(Path::new(path));
+    if ! path.is_absolute() || ! relative_to.is_absolute() {
+        return path.to_string_lossy().to_string();
+    };
+
+    let path_components: Vec<Component> = path.components().collect();
+    let base_components: Vec<Component> = relative_to.components().collect();
+    let common = path_components.iter().zip(&base_components).take_while(|(a, b)| a == b).count();
+    if common == 0 {
+        // Different drives, so there's no way to get from one to the other
+        return path.to_string_lossy().to_string();
+    };
+
+    let mut relative = PathBuf::new();
+    for _ in common..base_components.len() {
+        relative.push("..");
+    }
+    for component in &path_components[common..] {
+        relative.push(component.as_os_str());
+    }
+    relative.to_string_lossy().to_string()
+}
+
+pub(crate) fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let hex = |byte: u8| (byte as char).to_digit(16).map(|digit| digit as u8);
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len() {
+        if bytes[pos] == b'%' && pos + 2 < bytes.len() {
+            if let (Some(high), Some(low)) = (hex(bytes[pos + 1]), hex(bytes[pos + 2])) {
+                decoded.push(high << 4 | low);
+                pos += 3;
+                continue;
+            };
+        };
+        decoded.push(bytes[pos]);
+        pos += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+// Leaves alone what's allowed in a URL path
+pub(crate) fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        };
+    }
+    encoded
+}
+
+// Playlists are usually UTF-8, but older ones are often in Latin-1, which
+// we fall back to.
+pub(crate) fn read_text(path: &Path) -> Result<String> {
+    let data = match read(path) {
+        Ok(data) => data,
+        Err(err) => return Err(Error::io(path, err)),
+    };
+    let text = match String::from_utf8(data) {
+        Ok(text) => text,
+        Err(err) => err.into_bytes().iter().map(|byte| *byte as char).collect(),
+    };
+    Ok(text.trim_start_matches('\u{feff}').to_string())
+}
+
+// Playlists are named after their file
+pub(crate) fn playlist_name(path: &Path) -> String {
+    path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+pub(crate) fn playlist_dir(path: &Path) -> PathBuf {
+    path.parent().map(|dir| dir.to_path_buf()).unwrap_or_default()
+}
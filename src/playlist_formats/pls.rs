@@ -0,0 +1,93 @@
+// PLS is an ini file, numbering the details of each entry:
+// [playlist]
+// File1=<location>
+// Title1=<artist> - <title>
+// Length1=<seconds, or -1 if not known>
+// NumberOfEntries=<count>
+// Version=2
+use super::{display, playlist_dir, read_text, relative_location, split_display, Entry, TrackMatcher};
+use crate::error::{Error, Result};
+use crate::music_library::MusicLibrary;
+use crate::track::Track;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+const SECTION: &str = "[playlist]";
+
+pub fn read(path: &Path, library: &MusicLibrary) -> Result<Vec<Track>> {
+    let text = read_text(path)?;
+    let matcher = TrackMatcher::new(library);
+    let dir = playlist_dir(path);
+
+    // Entries can be in any order, and numbering can have gaps
+    let mut entries: BTreeMap<u32, Entry> = BTreeMap::new();
+    let mut found_section = false;
+    for line in text.lines().map(|line| line.trim()) {
+        if line.eq_ignore_ascii_case(SECTION) {
+            found_section = true;
+            continue;
+        };
+        let split = match line.find('=') {
+            Some(split) => split,
+            // Blank lines and comments
+            None => continue,
+        };
+        let (key, value) = (line[..split].trim().to_lowercase(), line[split + 1..].trim());
+
+        let (field, number) = match key.find(|c: char| c.is_ascii_digit()) {
+            Some(digits) => (&key[..digits], key[digits..].parse::<u32>().ok()),
+            None => continue,
+        };
+        let entry = match number {
+            Some(number) => entries.entry(number).or_default(),
+            None => continue,
+        };
+        match field {
+            "file" => entry.location = value.to_string(),
+            "title" => {
+                let (artist, title) = split_display(value);
+                entry.artist = artist;
+                entry.title = title;
+            },
+            "length" => entry.duration = value.parse::<i64>().ok()
+                .filter(|seconds| *seconds > 0)
+                .map(|seconds| Duration::from_secs(seconds as u64)),
+            _ => {},
+        };
+    }
+
+    if ! found_section {
+        return Err(Error::InvalidPlaylist {
+            path: path.to_path_buf(),
+            error: String::from("there is no [playlist] section"),
+        });
+    };
+    Ok(entries.into_values()
+        // Details without a file are no use to anyone
+        .filter(|entry| ! entry.location.is_empty())
+        .map(|entry| matcher.resolve(entry, &dir))
+        .collect())
+}
+
+pub fn write(path: &Path, tracks: &[Track], relative_to: Option<&Path>) -> Result<()> {
+    let mut text = String::from(SECTION);
+    text.push('\n');
+    for (index, track) in tracks.iter().enumerate() {
+        let number = index + 1;
+        let seconds = match track.duration {
+            Some(duration) => duration.as_secs().to_string(),
+            None => String::from("-1"),
+        };
+        text.push_str(&format!("File{}={}\n", number, relative_location(&track.path, relative_to)));
+        text.push_str(&format!("Title{}={}\n", number, display(track)));
+        text.push_str(&format!("Length{}={}\n", number, seconds));
+    }
+    text.push_str(&format!("NumberOfEntries={}\nVersion=2\n", tracks.len()));
+
+    match fs::write(path, text) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(Error::io(path, err)),
+    }
+}
@@ -0,0 +1,281 @@
+// XSPF is XML, with an element for each detail of an entry:
+// <playlist version="1" xmlns="http://xspf.org/ns/0/">
+//   <title>...</title>
+//   <trackList>
+//     <track>
+//       <location>file:///music/track.mp3</location>
+//       <title>...</title> <creator>...</creator> <album>...</album>
+//       <duration>milliseconds</duration>
+//     </track>
+//   </trackList>
+// </playlist>
+// Only what's needed for that is read, so a small reader is enough rather
+// than a full XML parser.
+use super::{is_url, percent_decode, percent_encode, playlist_dir, read_text, relative_location, Entry, TrackMatcher};
+use crate::error::{Error, Result};
+use crate::music_library::MusicLibrary;
+use crate::track::Track;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+const NAMESPACE: &str = "http://xspf.org/ns/0/";
+
+enum Node {
+    Start(String),
+    End(String),
+    Text(String),
+}
+
+// Gives the playlist's title if it has one, and its tracks
+pub fn read(path: &Path, library: &MusicLibrary) -> Result<(Option<String>, Vec<Track>)> {
+    let invalid = |error: String| Error::InvalidPlaylist {
+        path: path.to_path_buf(),
+        error,
+    };
+    let nodes = parse_nodes(&read_text(path)?).map_err(invalid)?;
+    let matcher = TrackMatcher::new(library);
+    let dir = playlist_dir(path);
+
+    let mut name = None;
+    let mut tracks = Vec::new();
+    let mut entry: Option<Entry> = None;
+    let mut open: Vec<String> = Vec::new();
+    let mut text = String::new();
+    for node in nodes {
+        match node {
+            Node::Start(element) => {
+                if open.is_empty() && element != "playlist" {
+                    return Err(invalid(format!("expected a playlist, found {}", element)));
+                };
+                if element == "track" && open.last().map(|parent| parent == "trackList").unwrap_or(false) {
+                    entry = Some(Entry::default());
+                };
+                open.push(element);
+                text.clear();
+            },
+            Node::Text(node_text) => text.push_str(&node_text),
+            Node::End(element) => {
+                let value = text.trim().to_string();
+                text.clear();
+                {
+                    let open: Vec<&str> = open.iter().map(|element| element.as_str()).collect();
+                    match (open.as_slice(), &mut entry) {
+                        (["playlist", "title"], _) if ! value.is_empty() => name = Some(value),
+                        (["playlist", "trackList", "track", field], Some(entry)) => set_field(entry, field, value),
+                        _ => {},
+                    };
+                }
+
+                match open.pop() {
+                    Some(ref opened) if *opened == element => {},
+                    _ => return Err(invalid(format!("{} was closed without being opened", element))),
+                };
+                if element == "track" {
+                    if let Some(entry) = entry.take() {
+                        // Without a location there's nothing to play, unless
+                        // the library has it
+                        if entry.location.is_empty() {
+                            tracks.extend(matcher.find_by_details(&entry));
+                        } else {
+                            tracks.push(matcher.resolve(entry, &dir));
+                        };
+                    };
+                };
+            },
+        };
+    }
+
+    match open.last() {
+        Some(element) => Err(invalid(format!("{} is not closed", element))),
+        None => Ok((name, tracks)),
+    }
+}
+
+fn set_field(entry: &mut Entry, field: &str, value: String) {
+    if value.is_empty() {
+        return;
+    };
+    match field {
+        // Tracks can give more than one location to try, but the first is
+        // where it's meant to be
+        "location" if entry.location.is_empty() => {
+            // Locations are URIs, so relative ones are percent encoded too
+            entry.location = if is_url(&value) { value } else { percent_decode(&value) };
+        },
+        "title" => entry.title = Some(value),
+        "creator" => entry.artist = Some(value),
+        "album" => entry.album = Some(value),
+        "duration" => entry.duration = value.parse::<u64>().ok()
+            .filter(|ms| *ms > 0)
+            .map(Duration::from_millis),
+        _ => {},
+    };
+}
+
+// Splits the XML into elements opening and closing, and the text between
+// them. Namespace prefixes are dropped, and comments, processing
+// instructions and doctypes skipped.
+fn parse_nodes(text: &str) -> std::result::Result<Vec<Node>, String> {
+    let mut nodes = Vec::new();
+    let mut rest = text;
+    while ! rest.is_empty() {
+        if ! rest.starts_with('<') {
+            let end = rest.find('<').unwrap_or(rest.len());
+            nodes.push(Node::Text(unescape(&rest[..end])));
+            rest = &rest[end..];
+            continue;
+        };
+
+        let skip_to = |end: &str| match rest.find(end) {
+            Some(pos) => Ok(pos + end.len()),
+            None => Err(format!("{} is not closed", start_of(rest))),
+        };
+        if rest.starts_with("<!--") {
+            rest = &rest[skip_to("-->")?..];
+        } else if rest.starts_with("<![CDATA[") {
+            let end = skip_to("]]>")?;
+            nodes.push(Node::Text(rest["<![CDATA[".len()..end - "]]>".len()].to_string()));
+            rest = &rest[end..];
+        } else if rest.starts_with("<?") {
+            rest = &rest[skip_to("?>")?..];
+        } else if rest.starts_with("<!") {
+            rest = &rest[skip_to(">")?..];
+        } else {
+            let end = find_tag_end(rest).ok_or_else(|| format!("{} is not closed", start_of(rest)))?;
+            let tag = &rest[1..end];
+            rest = &rest[end + 1..];
+
+            if let Some(closing) = tag.strip_prefix('/') {
+                nodes.push(Node::End(local_name(closing.trim())));
+                continue;
+            };
+            let self_closing = tag.ends_with('/');
+            let tag = tag.trim_end_matches('/');
+            let name = local_name(tag.split_whitespace().next().unwrap_or(""));
+            if name.is_empty() {
+                return Err(String::from("an element has no name"));
+            };
+            nodes.push(Node::Start(name.clone()));
+            if self_closing {
+                nodes.push(Node::End(name));
+            };
+        };
+    }
+    Ok(nodes)
+}
+
+// Enough of what's left to show where the problem is
+fn start_of(text: &str) -> String {
+    text.chars().take(10).collect()
+}
+
+// Where a tag ends, ignoring any > in its attributes' values
+fn find_tag_end(text: &str) -> Option<usize> {
+    let mut quote = None;
+    for (pos, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '>') => return Some(pos),
+            _ => {},
+        };
+    }
+    None
+}
+
+fn local_name(name: &str) -> String {
+    match name.rfind(':') {
+        Some(prefix_end) => name[prefix_end + 1..].to_string(),
+        None => name.to_string(),
+    }
+}
+
+// Entities that aren't understood are left as they are
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ if entity.starts_with("#x") => u32::from_str_radix(&entity[2..], 16).ok().and_then(std::char::from_u32),
+            _ if entity.starts_with('#') => entity[1..].parse::<u32>().ok().and_then(std::char::from_u32),
+            _ => None,
+        };
+        match c {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[end + 1..];
+            },
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            },
+        };
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Locations are written as file URLs, or relative URIs if relative_to is
+// given, with anything that isn't allowed in a URI percent encoded
+fn location(path: &str, relative_to: Option<&Path>) -> String {
+    if is_url(path) {
+        return path.to_string();
+    };
+    let location = relative_location(path, relative_to);
+    if ! Path::new(&location).is_absolute() {
+        return percent_encode(&location);
+    };
+    // Windows paths don't start with /, but file URLs always do
+    let slash = if location.starts_with('/') { "" } else { "/" };
+    format!("file://{}{}", slash, percent_encode(&location.replace('\\', "/")))
+}
+
+pub fn write(path: &Path, name: &str, tracks: &[Track], relative_to: Option<&Path>) -> Result<()> {
+    let mut text = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    text.push_str(&format!("<playlist version=\"1\" xmlns=\"{}\">\n", NAMESPACE));
+    if ! name.is_empty() {
+        text.push_str(&format!("  <title>{}</title>\n", escape(name)));
+    };
+    text.push_str("  <trackList>\n");
+    for track in tracks {
+        text.push_str("    <track>\n");
+        let duration = track.duration.map(|duration| duration.as_millis().to_string()).unwrap_or_default();
+        let fields = vec!(
+            ("location", location(&track.path, relative_to)),
+            ("title", track.track_name.clone()),
+            ("creator", track.artist.clone()),
+            ("album", track.album.clone()),
+            ("duration", duration),
+        );
+        for (element, value) in fields.into_iter().filter(|(_, value)| ! value.is_empty()) {
+            text.push_str(&format!("      <{0}>{1}</{0}>\n", element, escape(&value)));
+        }
+        text.push_str("    </track>\n");
+    }
+    text.push_str("  </trackList>\n</playlist>\n");
+
+    match fs::write(path, text) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(Error::io(path, err)),
+    }
+}
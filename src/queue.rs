@@ -1,47 +1,98 @@
+use crate::backend::{AudioBackend, RodioBackend};
+use crate::error::{Error, Result};
+use crate::music_library::MusicLibrary;
 use crate::playlist::Playlist;
 use crate::track::Track;
-use std::fs::File;
-use std::io::BufReader;
-use rodio::Sink;
 use std::sync::mpsc;
 use std::sync::{Arc,Mutex};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[derive(PartialEq)]
 pub enum QueueActivity {
     Stopped,
     Playing,
+    Paused,
 }
 
 #[derive(PartialEq)]
 pub enum QueueAction {
     SkipForward,
     SkipBack,
+    Pause,
+    Resume,
+    RestartTrack,
+    Seek(Duration),
+    SeekRelative(i64),
 }
 
+#[derive(Clone, PartialEq)]
+pub enum QueueEvent {
+    TrackStarted(Track),
+    TrackFinished,
+    Paused,
+    Resumed,
+    Stopped,
+    PlaylistExhausted,
+    DecodeError {
+        track: Track,
+        error: String,
+    },
+    Progress {
+        position: Duration,
+        duration: Option<Duration>,
+    },
+}
+
+// How often to send progress events while playing
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub track: Track,
+    // True if the track couldn't be played and was skipped
+    pub failed: bool,
+}
+
+// How many tracks in a row can fail to play before we give up
+const DEFAULT_MAX_CONSECUTIVE_FAILURES: usize = 5;
+
 struct QueueState {
     pub current_track: Option<Track>,
     pub action: QueueActivity,
+    pub max_consecutive_failures: usize,
+    pub position: Duration,
+    pub duration: Option<Duration>,
 }
 
 pub struct Queue {
     playlist: Arc<Mutex<Option<Playlist>>>,
-    history: Arc<Mutex<Vec<Track>>>,
+    history: Arc<Mutex<Vec<HistoryEntry>>>,
     state: Arc<Mutex<QueueState>>,
     player_controller: Option<mpsc::Sender<QueueAction>>,
+    backend: Option<Box<dyn AudioBackend>>,
+    subscribers: Arc<Mutex<Vec<mpsc::Sender<QueueEvent>>>>,
 }
 
 impl Queue {
     pub fn new() -> Queue {
+        Queue::with_backend(RodioBackend::new())
+    }
+
+    pub fn with_backend<B: AudioBackend + 'static>(backend: B) -> Queue {
         Queue{
             playlist: Arc::new(Mutex::new(None)),
             history: Arc::new(Mutex::new(Vec::new())),
             state: Arc::new(Mutex::new(QueueState {
                 current_track: None,
                 action: QueueActivity::Stopped,
+                max_consecutive_failures: DEFAULT_MAX_CONSECUTIVE_FAILURES,
+                position: Duration::from_secs(0),
+                duration: None,
             })),
             player_controller: None,
+            backend: Some(Box::new(backend)),
+            subscribers: Arc::new(Mutex::new(Vec::new())),
         }
     }
 
@@ -53,110 +104,331 @@ impl Queue {
         self.playlist.lock().unwrap().clone()
     }
 
-    fn get_controller(&mut self) -> &mpsc::Sender<QueueAction> {
-        match self.player_controller {
-            Some(_) => (),
-            None => self.create_player(),
+    // Pass on a newer copy of a library the playlist picks tracks from, even
+    // while playing. Returns false if the playlist doesn't use the library.
+    pub fn update_dynamic_library_source(&self, source: MusicLibrary) -> bool {
+        match self.playlist.lock().unwrap().as_mut() {
+            Some(playlist) => playlist.update_dynamic_library_source(source),
+            None => false,
+        }
+    }
+
+    fn send_action(&mut self, action: QueueAction) {
+        // Until there is a player nothing can be playing, so there is nothing
+        // to act on.
+        if let Some(controller) = &self.player_controller {
+            let _ = controller.send(action);
         };
-        self.player_controller.as_ref().unwrap()
     }
 
-    fn create_player(&mut self) {
+    fn create_player(&mut self) -> Result<()> {
         match self.player_controller {
-            Some(_) => return,
+            Some(_) => return Ok(()),
             None => (),
         };
-        let playlist = self.playlist.clone();
-        let history = self.history.clone();
-        let state = self.state.clone();
+        self.backend.as_mut().unwrap().open()?;
         let (sender, receiver) = mpsc::channel();
-        thread::spawn(move || {
-            let device = rodio::default_output_device().unwrap();
-            let mut sink = Sink::new(&device);
-            loop {
-                let received = receiver.try_recv();
-                match received {
-                    Ok(msg) => {
-                        if msg == QueueAction::SkipForward {
-                            sink = Sink::new(&device);
-                        } else if msg == QueueAction::SkipBack {
-                            sink = Sink::new(&device);
-                            let prev = history.lock().unwrap().last().unwrap().clone();
-                            state.lock().unwrap().current_track = Some(prev.clone());
-                            history.lock().unwrap().push(prev.clone());
-                            let file = File::open(prev.path).unwrap();
-                            let source = match rodio::Decoder::new(BufReader::new(file)) {
-                                Ok(src) => src,
-                                // TODO: This should be logging, not panicking.
-                                //Err(err) => panic!("Could not play file: {}: {:#?}", &track.path, err),
-                                Err(_) => panic!("Sad time"),
-                            };
-                            sink.append(source);
-                            sink.play();
-                        };
-                    },
-                    Err(_) => (),
-                };
-
-                if state.lock().unwrap().action == QueueActivity::Stopped {
-                    thread::sleep(Duration::from_millis(50));
-                    continue;
-                };
-
-                if sink.empty() {
-
-                    let next_track = playlist.lock().unwrap().as_mut().unwrap().next();
-                    match next_track {
-                        Some(track) => {
-                            state.lock().unwrap().current_track = Some(track.clone());
-                            history.lock().unwrap().push(track.clone());
-                            let file = File::open(track.path).unwrap();
-                            let source = match rodio::Decoder::new(BufReader::new(file)) {
-                                Ok(src) => src,
-                                // TODO: This should be logging, not panicking.
-                                //Err(err) => panic!("Could not play file: {}: {:#?}", &track.path, err),
-                                Err(_) => panic!("Sad time"),
-                            };
-                            sink.append(source);
-                            sink.play();
-                        },
-                        None => {
-                            state.lock().unwrap().current_track = None;
-                            state.lock().unwrap().action = QueueActivity::Stopped;
-                            thread::sleep(Duration::from_millis(50));
-                        },
-                    };
-                } else {
-                    // There is a track playing, wait
-                    thread::sleep(Duration::from_millis(50));
-                }
-            };
-        });
+        let player = Player {
+            playlist: self.playlist.clone(),
+            history: self.history.clone(),
+            state: self.state.clone(),
+            subscribers: self.subscribers.clone(),
+            backend: self.backend.take().unwrap(),
+            receiver,
+            consecutive_failures: 0,
+        };
+        thread::spawn(move || player.run());
         self.player_controller = Some(sender);
+        Ok(())
     }
 
-    pub fn play(&mut self) {
-        self.create_player();
+    pub fn play(&mut self) -> Result<()> {
+        if self.is_paused() {
+            // The player needs to resume the paused track
+            self.resume();
+            return Ok(());
+        };
+        if self.playlist.lock().unwrap().is_none() {
+            return Err(Error::NoPlaylist);
+        };
+        // Playing before the player starts, so it starts a track before
+        // anything else it's asked to do
         self.state.lock().unwrap().action = QueueActivity::Playing;
+        if let Err(err) = self.create_player() {
+            self.state.lock().unwrap().action = QueueActivity::Stopped;
+            return Err(err);
+        };
+        Ok(())
     }
 
     pub fn skip_forward(&mut self) {
-        self.get_controller().send(QueueAction::SkipForward);
+        self.send_action(QueueAction::SkipForward);
     }
 
     pub fn skip_back(&mut self) {
-        self.get_controller().send(QueueAction::SkipBack);
+        self.send_action(QueueAction::SkipBack);
+    }
+
+    pub fn pause(&mut self) {
+        self.send_action(QueueAction::Pause);
+    }
+
+    pub fn resume(&mut self) {
+        self.send_action(QueueAction::Resume);
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.is_paused() {
+            self.resume();
+        } else {
+            self.pause();
+        };
+    }
+
+    pub fn restart_track(&mut self) {
+        self.send_action(QueueAction::RestartTrack);
+    }
+
+    pub fn seek(&mut self, position: Duration) {
+        self.send_action(QueueAction::Seek(position));
+    }
+
+    // Seek forwards (or backwards, for negative values) from the current
+    // position by the given number of milliseconds.
+    pub fn seek_relative(&mut self, offset_ms: i64) {
+        self.send_action(QueueAction::SeekRelative(offset_ms));
+    }
+
+    pub fn position(&self) -> Duration {
+        self.state.lock().unwrap().position
+    }
+
+    pub fn duration_of_current(&self) -> Option<Duration> {
+        self.state.lock().unwrap().duration
+    }
+
+    pub fn is_paused(&self) -> bool {
+        return self.state.lock().unwrap().action == QueueActivity::Paused;
     }
 
     pub fn is_playing(&self) -> bool {
         return self.state.lock().unwrap().action == QueueActivity::Playing;
     }
 
+    // Events are sent from the player as they happen, so a frontend doesn't
+    // need to keep polling the queue.
+    pub fn subscribe(&self) -> mpsc::Receiver<QueueEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
     pub fn get_history(&self) -> Vec<Track> {
+        self.history.lock().unwrap().iter().map(|entry| entry.track.clone()).collect()
+    }
+
+    // As get_history, but also showing which tracks failed to play
+    pub fn get_history_entries(&self) -> Vec<HistoryEntry> {
         self.history.lock().unwrap().clone()
     }
 
     pub fn set_history(&self, new_history: Vec<Track>) {
-        *self.history.lock().unwrap().as_mut() = new_history;
+        *self.history.lock().unwrap() = new_history.into_iter()
+            .map(|track| HistoryEntry {
+                track,
+                failed: false,
+            })
+            .collect();
+    }
+
+    // Tracks that can't be played are skipped, but if this many fail in a row
+    // then playback will stop.
+    pub fn set_max_consecutive_failures(&self, max_failures: usize) {
+        self.state.lock().unwrap().max_consecutive_failures = max_failures;
+    }
+}
+
+// Everything the player thread works with
+struct Player {
+    playlist: Arc<Mutex<Option<Playlist>>>,
+    history: Arc<Mutex<Vec<HistoryEntry>>>,
+    state: Arc<Mutex<QueueState>>,
+    subscribers: Arc<Mutex<Vec<mpsc::Sender<QueueEvent>>>>,
+    backend: Box<dyn AudioBackend>,
+    receiver: mpsc::Receiver<QueueAction>,
+    consecutive_failures: usize,
+}
+
+impl Player {
+    fn notify(&self, event: QueueEvent) {
+        // Forget about anyone who has stopped listening
+        self.subscribers.lock().unwrap().retain(|subscriber| {
+            subscriber.send(event.clone()).is_ok()
+        });
+    }
+
+    fn is_paused(&self) -> bool {
+        self.state.lock().unwrap().action == QueueActivity::Paused
+    }
+
+    fn stop(&mut self) {
+        self.backend.stop();
+        let mut state = self.state.lock().unwrap();
+        state.current_track = None;
+        state.action = QueueActivity::Stopped;
+        drop(state);
+        self.notify(QueueEvent::Stopped);
+    }
+
+    // Play a track from the start, without touching the history.
+    // Returns false if the track couldn't be played, in which case nothing
+    // will be playing and the next track will be picked up as usual.
+    fn play_track(&mut self, track: Track) -> bool {
+        match self.backend.play_track(&track) {
+            Ok(_) => {
+                self.consecutive_failures = 0;
+                if self.is_paused() {
+                    self.backend.pause();
+                };
+                self.notify(QueueEvent::TrackStarted(track));
+                true
+            },
+            Err(error) => {
+                // Reported even if nobody is listening for events
+                eprintln!("Skipping {}: {}", track.track_name, error);
+                self.consecutive_failures += 1;
+                self.state.lock().unwrap().current_track = None;
+                self.notify(QueueEvent::DecodeError {
+                    track,
+                    error: error.to_string(),
+                });
+                let max_failures = self.state.lock().unwrap().max_consecutive_failures;
+                if self.consecutive_failures >= max_failures {
+                    self.consecutive_failures = 0;
+                    self.stop();
+                };
+                false
+            },
+        }
+    }
+
+    fn start_track(&mut self, track: Track) {
+        self.state.lock().unwrap().current_track = Some(track.clone());
+        self.history.lock().unwrap().push(HistoryEntry {
+            track: track.clone(),
+            failed: false,
+        });
+        if ! self.play_track(track) {
+            if let Some(entry) = self.history.lock().unwrap().last_mut() {
+                entry.failed = true;
+            };
+        };
+    }
+
+    fn handle_action(&mut self, action: QueueAction) {
+        match action {
+            QueueAction::SkipForward => {
+                self.backend.stop();
+            },
+            QueueAction::SkipBack => {
+                let prev_track = match self.playlist.lock().unwrap().as_mut() {
+                    // Stepping back from the first track would start it again
+                    // once it finished, so it's just restarted instead
+                    Some(playlist) if playlist.get_position() == Some(0) => None,
+                    Some(playlist) => playlist.prev(),
+                    None => None,
+                };
+                match prev_track {
+                    Some(prev) => self.start_track(prev),
+                    None => self.backend.seek(Duration::from_secs(0)),
+                };
+            },
+            QueueAction::Pause => {
+                let mut state = self.state.lock().unwrap();
+                if state.action == QueueActivity::Playing {
+                    self.backend.pause();
+                    state.action = QueueActivity::Paused;
+                    drop(state);
+                    self.notify(QueueEvent::Paused);
+                };
+            },
+            QueueAction::Resume => {
+                let mut state = self.state.lock().unwrap();
+                if state.action == QueueActivity::Paused {
+                    self.backend.resume();
+                    state.action = QueueActivity::Playing;
+                    drop(state);
+                    self.notify(QueueEvent::Resumed);
+                };
+            },
+            QueueAction::RestartTrack => {
+                let current_track = self.state.lock().unwrap().current_track.clone();
+                if let Some(track) = current_track {
+                    if ! self.play_track(track) {
+                        if let Some(entry) = self.history.lock().unwrap().last_mut() {
+                            entry.failed = true;
+                        };
+                    };
+                };
+            },
+            QueueAction::Seek(position) => {
+                self.backend.seek(position);
+            },
+            QueueAction::SeekRelative(offset_ms) => {
+                let current = self.backend.position();
+                let offset = Duration::from_millis(offset_ms.unsigned_abs());
+                let position = if offset_ms < 0 {
+                    current.checked_sub(offset).unwrap_or(Duration::from_secs(0))
+                } else {
+                    current + offset
+                };
+                self.backend.seek(position);
+            },
+        };
+    }
+
+    fn run(mut self) {
+        let mut last_progress = Instant::now();
+        loop {
+            let position = self.backend.position();
+            let duration = self.backend.duration();
+            let action_is_playing = {
+                let mut state = self.state.lock().unwrap();
+                state.position = position;
+                state.duration = duration;
+                state.action == QueueActivity::Playing
+            };
+
+            // The next track is started before acting on anything, so actions
+            // sent straight after playing apply to the track they were meant for
+            if action_is_playing && self.backend.is_empty() {
+                if self.state.lock().unwrap().current_track.is_some() {
+                    self.notify(QueueEvent::TrackFinished);
+                };
+
+                let next_track = self.playlist.lock().unwrap().as_mut().map(|playlist| playlist.next());
+                match next_track {
+                    Some(Some(track)) => self.start_track(track),
+                    Some(None) => {
+                        self.notify(QueueEvent::PlaylistExhausted);
+                        self.stop();
+                    },
+                    // The playlist was taken away, so there's nothing to play
+                    None => self.stop(),
+                };
+            } else if action_is_playing && last_progress.elapsed() >= PROGRESS_INTERVAL {
+                self.notify(QueueEvent::Progress {
+                    position,
+                    duration,
+                });
+                last_progress = Instant::now();
+            };
+
+            match self.receiver.try_recv() {
+                Ok(action) => self.handle_action(action),
+                // Nothing to do until the track finishes or we're asked to
+                Err(_) => thread::sleep(Duration::from_millis(50)),
+            };
+        };
     }
 }
@@ -0,0 +1,183 @@
+// Save files are records of fields, split up by the characters in
+// delimiters. Since version 2 each field is saved as <key>=<value>, so
+// fields can be added or left out without breaking anything, and files
+// start with the format version:
+// <START_OF_VERSION><version><END_OF_RECORD>
+// <header records><END_OF_HEADER>
+// <track records>
+// Version 1 files have no version and positional fields. They are upgraded
+// as they are read by giving each field the key for its position, and are
+// saved in the current version the next time they are saved. Since version 3
+// delimiters in values are escaped, before which they broke the file.
+use crate::delimiters::{END_OF_FIELD, END_OF_HEADER, END_OF_RECORD, ESCAPE, START_OF_VERSION};
+
+pub const FORMAT_VERSION: u32 = 3;
+const UNVERSIONED: u32 = 1;
+const FIRST_ESCAPED_VERSION: u32 = 3;
+
+// Header records other than the first say what they hold with this key
+pub const KIND_KEY: &str = "kind";
+
+pub struct Field {
+    pub key: String,
+    pub value: String,
+    // Where the value starts in the file, for errors
+    pub start: usize,
+}
+
+pub struct Record {
+    pub fields: Vec<Field>,
+    pub start: usize,
+}
+
+impl Record {
+    pub fn get(&self, key: &str) -> Option<&Field> {
+        self.fields.iter().find(|field| field.key == key)
+    }
+
+    // Missing fields are empty
+    pub fn value(&self, key: &str) -> &str {
+        self.get(key).map(|field| field.value.as_str()).unwrap_or("")
+    }
+
+    // Where a field is, or where the record starts if it isn't there
+    pub fn offset(&self, key: &str) -> usize {
+        self.get(key).map(|field| field.start).unwrap_or(self.start)
+    }
+
+    pub fn kind(&self) -> &str {
+        self.value(KIND_KEY)
+    }
+}
+
+// Fields exactly as they were saved: the text and where it starts
+type RawRecord = Vec<(String, usize)>;
+
+// Errors are all offsets into the data, as the caller knows which file it
+// came from.
+type Offset = usize;
+
+// Gives the version and where the header starts.
+pub fn read_version(data: &str) -> Result<(u32, usize), Offset> {
+    if ! data.starts_with(START_OF_VERSION) {
+        return Ok((UNVERSIONED, 0));
+    };
+    let version_start = START_OF_VERSION.len_utf8();
+    let version_end = match data.find(END_OF_RECORD) {
+        Some(end) => end,
+        None => return Err(data.len()),
+    };
+    match data[version_start..version_end].parse() {
+        Ok(version) if version > UNVERSIONED => Ok((version, version_end + END_OF_RECORD.len_utf8())),
+        _ => Err(version_start),
+    }
+}
+
+// Splits data up into records, starting from start. When reading the
+// header this stops at its end, giving where the tracks start, otherwise it
+// carries on to the end of the data.
+pub fn split_records(data: &str, start: usize, header: bool, version: u32) -> Result<(Vec<RawRecord>, usize), Offset> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = (String::new(), start);
+    let mut escaped = false;
+
+    for (offset, c) in data[start..].char_indices() {
+        let next_start = start + offset + c.len_utf8();
+        if escaped {
+            field.0.push(c);
+            escaped = false;
+        } else if c == ESCAPE && version >= FIRST_ESCAPED_VERSION {
+            escaped = true;
+        } else if c == END_OF_FIELD {
+            fields.push(field);
+            field = (String::new(), next_start);
+        } else if c == END_OF_RECORD {
+            fields.push(field);
+            field = (String::new(), next_start);
+            records.push(fields);
+            fields = Vec::new();
+        } else if c == END_OF_HEADER {
+            if ! header {
+                // There's only one header
+                return Err(start + offset);
+            };
+            fields.push(field);
+            records.push(fields);
+            return Ok((records, next_start));
+        } else {
+            field.0.push(c);
+        }
+    }
+
+    if header || escaped || ! fields.is_empty() || ! field.0.is_empty() {
+        // The header never ended, or the last record was cut short
+        return Err(data.len());
+    };
+    Ok((records, data.len()))
+}
+
+// Gives fields their keys, either from the data itself or, for version 1,
+// from their position.
+pub fn to_record(raw: RawRecord, version: u32, legacy_keys: &[&str]) -> Result<Record, Offset> {
+    let start = raw.first().map(|(_, start)| *start).unwrap_or(0);
+    let mut fields = Vec::new();
+
+    for (position, (text, field_start)) in raw.into_iter().enumerate() {
+        if version == UNVERSIONED {
+            match legacy_keys.get(position) {
+                Some(key) => fields.push(Field {
+                    key: key.to_string(),
+                    value: text,
+                    start: field_start,
+                }),
+                // Found too many fields in record
+                None => return Err(field_start),
+            };
+            continue;
+        };
+
+        if text.is_empty() {
+            // Only a record with no fields at all has one of these
+            continue;
+        };
+        let split = match text.find('=') {
+            Some(split) => split,
+            None => return Err(field_start),
+        };
+        fields.push(Field {
+            key: text[..split].to_string(),
+            value: text[split + 1..].to_string(),
+            start: field_start + split + 1,
+        });
+    }
+
+    Ok(Record {
+        fields,
+        start,
+    })
+}
+
+// Fields with nothing in them are left out, as they're read back as empty.
+pub fn dump_record(fields: &[(&str, String)]) -> String {
+    let fields: Vec<String> = fields.iter()
+        .filter(|(_, value)| ! value.is_empty())
+        .map(|(key, value)| format!("{}={}", key, escape(value)))
+        .collect();
+    fields.join(&END_OF_FIELD.to_string())
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if c == END_OF_FIELD || c == END_OF_RECORD || c == END_OF_HEADER || c == ESCAPE {
+            escaped.push(ESCAPE);
+        };
+        escaped.push(c);
+    }
+    escaped
+}
+
+pub fn dump_version() -> String {
+    format!("{}{}{}", START_OF_VERSION, FORMAT_VERSION, END_OF_RECORD)
+}
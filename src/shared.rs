@@ -1,108 +1,235 @@
-use crate::delimiters::{END_OF_FIELD, END_OF_HEADER, END_OF_RECORD};
-use crate::track::Track;
-use rand::Rng;
-use std::ffi::OsStr;
-use std::fs::File;
-use std::io::Write;
-use std::path::PathBuf;
+use crate::error::{Error, Result};
+use crate::save_format::{dump_version, read_version, split_records, to_record, Record, FORMAT_VERSION};
+use crate::track::{Track, LEGACY_TRACK_KEYS};
+use std::ffi::{OsStr, OsString};
+use std::fs::{copy, read, remove_file, rename, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Saves are written here first, and the previous save is kept in the backup
+const TEMP_SUFFIX: &str = "tmp";
+const BACKUP_SUFFIX: &str = "bak";
+
+// Tells apart the temporary files of saves made at the same time
+static TEMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
 pub trait DynamicSource {
-    fn get_random_track(&self) -> Track {
-        let tracks = &self.get_tracks();
-
-        let track_num = rand::thread_rng().gen_range(0, tracks.len());
-
-        tracks[track_num].clone()
-    }
-
+    // How often the source is picked from unless it's given a weight
     fn get_weight(&self) -> usize {
         self.get_tracks().len()
     }
 
-    fn get_tracks(&self) -> Vec<Track>;
+    fn get_tracks(&self) -> &[Track];
 }
 
 pub trait Saveable {
-    fn save(&self, data_storage_path: String) {
-        let mut data_path = PathBuf::from(data_storage_path);
-        data_path.push(OsStr::new(&format!("{}.{}", &self.get_name(), &self.get_extension())));
+    fn save(&self, data_storage_path: String) -> Result<()> {
+        write_saved(self, data_storage_path)
+    }
 
-        let mut data = String::new();
+    // The first header record, holding the details every save file has
+    fn process_save_header(data: &str, data_path: &Path) -> Result<Record> {
+        let mut records = Self::process_save_header_records(data, data_path)?;
+        Ok(records.remove(0))
+    }
 
-        data.push_str(&self.get_header());
+    // The header is made up of one or more records, the first of which holds
+    // the details common to every save file. Any further records are extra
+    // details specific to the type being saved, with their kind saying what.
+    fn process_save_header_records(data: &str, data_path: &Path) -> Result<Vec<Record>> {
+        let (records, _) = read_header(data, data_path, Self::get_legacy_header_keys)?;
+        Ok(records)
+    }
 
-        // Add tracks
-        for track in &self.get_tracks() {
-            data.push_str(&track.dump());
+    fn load_tracks(data: &str, data_path: &Path) -> Result<Vec<Track>> {
+        let (_, (version, tracks_start)) = read_header(data, data_path, Self::get_legacy_header_keys)?;
+        let corrupt = |offset| Error::CorruptSaveFile {
+            path: data_path.to_path_buf(),
+            offset,
         };
 
-        let mut data_file = match File::create(&data_path) {
-            Ok(file) => file,
-            Err(err) => panic!("Could not create {}: {:#?}", data_path.display(), err),
-        };
-        match data_file.write_all(data.as_bytes()) {
-            Ok(_) => {},
-            Err(err) => panic!("Could not write to {}: {:#?}", data_path.display(), err),
-        };
-        match data_file.sync_all() {
-            Ok(_) => {},
-            Err(err) => panic!("Could not complete write to {}: {:#?}", data_path.display(), err),
-        };
+        let (records, _) = split_records(data, tracks_start, false, version).map_err(corrupt)?;
+        let mut tracks = Vec::new();
+        for raw in records {
+            let record = to_record(raw, version, &LEGACY_TRACK_KEYS).map_err(corrupt)?;
+            match Track::from_record(&record) {
+                Ok(track) => tracks.push(track),
+                Err(Error::CorruptSaveFile { offset, .. }) => return Err(corrupt(offset)),
+                Err(err) => return Err(err),
+            };
+        }
+        Ok(tracks)
     }
 
-    fn process_save_header(data: &str) -> Vec<String> {
-        let mut field_pos = 0;
-        let mut name = String::new();
-        let mut field2 = String::new();
-
-        for c in data.chars() {
-            if c == END_OF_FIELD {
-                field_pos += 1;
-            } else if c == END_OF_HEADER {
-                return vec!(name, field2);
-            } else if field_pos == 0 {
-                name.push(c);
-            } else {
-                field2.push(c);
-            }
-        }
-        panic!("Failed to load header!");
+    // Keys for the positional fields of header records in version 1 files.
+    // The kind is None for the first record, and otherwise is the first field.
+    fn get_legacy_header_keys(kind: Option<&str>) -> &'static [&'static str];
+
+    fn get_header(&self) -> String;
+    fn get_name(&self) -> &str;
+    fn get_extension(&self) -> &str;
+    fn get_tracks(&self) -> Vec<Track>;
+}
+
+// Gives the header records with their keys, and the version and where the
+// tracks start, for the tracks to be read the same way.
+fn read_header<F>(data: &str, data_path: &Path, legacy_keys: F) -> Result<(Vec<Record>, (u32, usize))>
+    where F: Fn(Option<&str>) -> &'static [&'static str]
+{
+    let corrupt = |offset| Error::CorruptSaveFile {
+        path: data_path.to_path_buf(),
+        offset,
+    };
+
+    let (version, header_start) = read_version(data).map_err(corrupt)?;
+    if version > FORMAT_VERSION {
+        return Err(Error::UnsupportedSaveVersion {
+            path: data_path.to_path_buf(),
+            version,
+        });
+    };
+
+    let (raw_records, tracks_start) = split_records(data, header_start, true, version).map_err(corrupt)?;
+    let mut records = Vec::new();
+    for raw in raw_records {
+        let keys = if records.is_empty() {
+            legacy_keys(None)
+        } else {
+            legacy_keys(raw.first().map(|(kind, _)| kind.as_str()))
+        };
+        records.push(to_record(raw, version, keys).map_err(corrupt)?);
     }
+    Ok((records, (version, tracks_start)))
+}
 
-    fn load_tracks(data: &str) -> Vec<Track> {
-        let mut header_read_complete = false;
+// What save does, for types which save more than themselves
+pub(crate) fn write_saved<S: Saveable + ?Sized>(saveable: &S, data_storage_path: String) -> Result<()> {
+    let mut data_path = PathBuf::from(data_storage_path);
+    data_path.push(OsStr::new(&format!("{}.{}", &saveable.get_name(), &saveable.get_extension())));
 
-        let mut track_data = String::new();
+    let mut data = dump_version();
 
-        let mut tracks: Vec<Track> = Vec::new();
+    data.push_str(&saveable.get_header());
 
-        // Save file structure:
-        // header:
-        // <library name><END_OF_FIELD><library path><END_OF_HEADER>
-        // <zero or more tracks (see track for serialisation format)>
+    // Add tracks
+    for track in &saveable.get_tracks() {
+        data.push_str(&track.dump());
+    };
 
-        for c in data.chars() {
-            if c == END_OF_HEADER {
-                header_read_complete = true;
-                continue;
-            }
+    write_atomically(&data_path, data.as_bytes(), |data, path| S::load_tracks(data, path).is_ok())
+}
 
-            if ! header_read_complete {
-                continue;
-            }
+// The save is written to a temporary file next to the real one, which then
+// replaces it, so a crash or a full disk part way through never leaves a
+// half written save behind. What was there before is kept as a backup, as
+// long as it loads, so a corrupt save never replaces a good backup.
+fn write_atomically<F>(data_path: &Path, data: &[u8], loads: F) -> Result<()>
+    where F: Fn(&str, &Path) -> bool
+{
+    let temp_suffix = format!("{}.{}.{}", process::id(), TEMP_COUNTER.fetch_add(1, Ordering::SeqCst), TEMP_SUFFIX);
+    let temp_path = with_suffix(data_path, &temp_suffix);
+    if let Err(err) = write_and_sync(&temp_path, data) {
+        // Best effort, the write failing is what needs reporting
+        remove_file(&temp_path).ok();
+        return Err(Error::io(&temp_path, err));
+    };
+
+    let current_loads = match read_saved(data_path) {
+        Ok(current) => loads(&current, data_path),
+        Err(_) => false,
+    };
+    if current_loads {
+        let backup_path = with_suffix(data_path, BACKUP_SUFFIX);
+        if let Err(err) = copy_and_sync(data_path, &backup_path) {
+            remove_file(&temp_path).ok();
+            return Err(Error::io(&backup_path, err));
+        };
+    };
+
+    match rename(&temp_path, data_path) {
+        Ok(_) => {},
+        Err(err) => {
+            remove_file(&temp_path).ok();
+            return Err(Error::io(data_path, err));
+        },
+    };
+    // The rename itself isn't safe until the directory has been synced
+    match sync_dir(data_path) {
+        Ok(_) => Ok(()),
+        Err(err) => Err(Error::io(data_path, err)),
+    }
+}
 
-            track_data.push(c);
+fn write_and_sync(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+// The backup is what's left if the save is cut short, so it has to be on
+// disk before the save replaces what it was copied from
+fn copy_and_sync(from: &Path, to: &Path) -> io::Result<()> {
+    copy(from, to)?;
+    OpenOptions::new().write(true).open(to)?.sync_all()
+}
+
+#[cfg(unix)]
+fn sync_dir(data_path: &Path) -> io::Result<()> {
+    match data_path.parent() {
+        Some(dir) if dir != Path::new("") => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
 
-            if c == END_OF_RECORD {
-                tracks.push(Track::load(track_data));
-                track_data = String::new()
+// Directories can't be opened to sync them elsewhere
+#[cfg(not(unix))]
+fn sync_dir(_data_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn with_suffix(data_path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(data_path.as_os_str());
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// Reads a save file with parse, falling back to the backup kept by the
+// previous save if the file is corrupt. Errors are for the file itself, as
+// that's the one the caller asked for.
+pub(crate) fn load_saved<T, F>(data_path: &Path, parse: F) -> Result<T>
+    where F: Fn(&str, &Path) -> Result<T>
+{
+    let result = match read_saved(data_path) {
+        Ok(data) => parse(&data, data_path),
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Err(err @ Error::CorruptSaveFile { .. }) => {
+            let backup_path = with_suffix(data_path, BACKUP_SUFFIX);
+            match read_saved(&backup_path) {
+                Ok(data) => parse(&data, &backup_path).map_err(|_| err),
+                Err(_) => Err(err),
             }
-        }
-        tracks
+        },
+        other => other,
     }
+}
 
-    fn get_header(&self) -> String;
-    fn get_name(&self) -> &str;
-    fn get_extension(&self) -> &str;
-    fn get_tracks(&self) -> Vec<Track>;
+fn read_saved(data_path: &Path) -> Result<String> {
+    let data = match read(data_path) {
+        Ok(data) => data,
+        Err(err) => return Err(Error::io(data_path, err)),
+    };
+    match String::from_utf8(data) {
+        Ok(data) => Ok(data),
+        // Saves are always UTF-8, so something has mangled it
+        Err(err) => Err(Error::CorruptSaveFile {
+            path: data_path.to_path_buf(),
+            offset: err.utf8_error().valid_up_to(),
+        }),
+    }
 }
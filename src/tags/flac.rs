@@ -0,0 +1,71 @@
+// FLAC files are "fLaC" followed by metadata blocks, each with a 4 byte
+// header: <last block flag (1 bit)><block type (7 bits)><length (24 bits)>
+// The first block is always STREAMINFO, describing the audio.
+use super::{malformed, read_bytes, samples_to_duration, syncsafe_u32, u32_be, vorbis_comment, TagDetails};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+const STREAMINFO: u8 = 0;
+const VORBIS_COMMENT: u8 = 4;
+
+pub fn read_tags(path: &Path) -> io::Result<TagDetails> {
+    let mut file = BufReader::new(File::open(path)?);
+    skip_to_stream(&mut file)?;
+
+    // Files with no tags at all still get their stream details
+    let mut details = TagDetails::default();
+    let mut stream_info = None;
+    loop {
+        let header = read_bytes(&mut file, 4)?;
+        let is_last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7f;
+        let length = u32_be(&header[1..4]) as usize;
+
+        if block_type == STREAMINFO && length >= 18 {
+            stream_info = Some(read_bytes(&mut file, length)?);
+        } else if block_type == VORBIS_COMMENT {
+            let comments = vorbis_comment::parse(&read_bytes(&mut file, length)?)?;
+            details = vorbis_comment::to_tag_details(&comments);
+        } else {
+            file.seek(SeekFrom::Current(length as i64))?;
+        };
+        if is_last || block_type == VORBIS_COMMENT {
+            break;
+        };
+    }
+
+    // STREAMINFO: <min block size (2)><max block size (2)><min frame size (3)>
+    // <max frame size (3)><sample rate (20 bits)><channels - 1 (3 bits)>
+    // <bits per sample - 1 (5 bits)><total samples (36 bits)><md5 (16)>
+    if let Some(info) = stream_info {
+        let sample_rate = (info[10] as u32) << 12 | (info[11] as u32) << 4 | (info[12] as u32) >> 4;
+        let total_samples = ((info[13] & 0x0f) as u64) << 32 | u32_be(&info[14..18]) as u64;
+        details.sample_rate = Some(sample_rate);
+        details.channels = Some(((info[12] >> 1) & 0x07) as u16 + 1);
+        // A total of 0 means it isn't known
+        if total_samples > 0 {
+            details.duration = samples_to_duration(total_samples, sample_rate);
+        };
+    };
+    Ok(details)
+}
+
+// Skips any id3v2 tag that has been put in front of the stream, then the
+// "fLaC" marker.
+fn skip_to_stream<R: Read + Seek>(file: &mut R) -> io::Result<()> {
+    let mut marker = read_bytes(file, 4)?;
+    if marker.starts_with(b"ID3") {
+        let id3_header = read_bytes(file, 6)?;
+        let mut tag_size = syncsafe_u32(&id3_header[2..6]) as i64;
+        if id3_header[1] & 0x10 != 0 {
+            tag_size += 10;
+        };
+        file.seek(SeekFrom::Current(tag_size))?;
+        marker = read_bytes(file, 4)?;
+    };
+    if marker != b"fLaC" {
+        return Err(malformed("Not a FLAC stream"));
+    };
+    Ok(())
+}
@@ -0,0 +1,31 @@
+use super::TagDetails;
+use crate::error::{Error, Result};
+use id3::{ErrorKind, Tag};
+use std::path::Path;
+
+pub fn read_tags(path: &Path) -> Result<TagDetails> {
+    let tags = match Tag::read_from_path(path) {
+        Ok(res) => res,
+        // Treated like a tag with nothing in it, so details can come from elsewhere
+        Err(ref err) if matches!(err.kind, ErrorKind::NoTag) => return Ok(TagDetails::default()),
+        Err(err) => return Err(Error::Tag {
+            path: path.to_path_buf(),
+            error: err.to_string(),
+        }),
+    };
+
+    Ok(TagDetails {
+        title: tags.get("TIT2").map(|res| res.to_string()),
+        artist: tags.get("TPE1").map(|res| res.to_string()),
+        album: tags.get("TALB").map(|res| res.to_string()),
+        track_number: tags.get("TRCK").map(|res| res.to_string()),
+        // ID3v2.4 replaced the year with a full recording date
+        year: tags.get("TDRC").or_else(|| tags.get("TYER")).map(|res| res.to_string()),
+        genre: tags.get("TCON").map(|res| res.to_string()),
+        // Like the track number, this can be "disc/total"
+        disc_number: tags.get("TPOS").map(|res| res.to_string()),
+        album_artist: tags.get("TPE2").map(|res| res.to_string()),
+        composer: tags.get("TCOM").map(|res| res.to_string()),
+        ..TagDetails::default()
+    })
+}
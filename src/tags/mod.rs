@@ -0,0 +1,240 @@
+// Reading track details from the different audio formats we support.
+// Formats are detected from the file contents rather than trusting the
+// extension, as plenty of files out there are misnamed.
+mod flac;
+mod id3v2;
+mod mp4;
+mod mpeg;
+mod ogg;
+mod riff;
+mod vorbis_comment;
+
+use crate::error::{Error, Result};
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AudioFormat {
+    Mp3,
+    Flac,
+    OggVorbis,
+    Opus,
+    Wav,
+    Mp4,
+}
+
+// The details we care about, whichever format they came from. Despite the
+// name, this includes the properties of the audio itself.
+#[derive(Default)]
+pub struct TagDetails {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<String>,
+    pub year: Option<String>,
+    pub genre: Option<String>,
+    pub disc_number: Option<String>,
+    pub disc_total: Option<String>,
+    pub album_artist: Option<String>,
+    pub composer: Option<String>,
+    pub duration: Option<Duration>,
+    // Average, in bits per second
+    pub bitrate: Option<u32>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+}
+
+impl TagDetails {
+    // Smooth over the different ways formats store the same things
+    fn tidy(&mut self, file_size: u64) {
+        // Dates are often full dates, but we only want the year
+        self.year = self.year.as_ref().and_then(|date| {
+            let year: String = date.trim().chars().take(4).collect();
+            if year.len() == 4 && year.chars().all(|c| c.is_ascii_digit()) {
+                Some(year)
+            } else {
+                None
+            }
+        });
+
+        // Disc numbers are often given as "<disc>/<total>"
+        if let Some(disc) = self.disc_number.take() {
+            let mut parts = disc.splitn(2, '/');
+            self.disc_number = parts.next().map(|number| number.trim().to_string());
+            if let Some(total) = parts.next() {
+                if self.disc_total.is_none() {
+                    self.disc_total = Some(total.trim().to_string());
+                };
+            };
+        };
+
+        for field in &mut [&mut self.year, &mut self.genre, &mut self.disc_number, &mut self.disc_total, &mut self.album_artist, &mut self.composer] {
+            if field.as_ref().map(|value| value.is_empty()).unwrap_or(false) {
+                **field = None;
+            };
+        }
+
+        // Without anything better, the average over the whole file will do
+        if self.bitrate.is_none() {
+            if let Some(duration) = self.duration {
+                if duration.as_millis() > 0 {
+                    self.bitrate = Some((file_size as u128 * 8 * 1000 / duration.as_millis()) as u32);
+                };
+            };
+        };
+    }
+}
+
+// Returns None for files that aren't in a format we know about.
+pub fn detect_format(path: &Path) -> io::Result<Option<AudioFormat>> {
+    let mut file = File::open(path)?;
+    let mut header = [0; 32];
+    let header_len = read_up_to(&mut file, &mut header)?;
+    let header = &header[..header_len];
+
+    if header.starts_with(b"ID3") && header.len() >= 10 {
+        // Something is hiding behind an id3v2 tag, usually an MP3, but
+        // occasionally a FLAC file.
+        let mut tag_size = 10 + syncsafe_u32(&header[6..10]) as u64;
+        if header[5] & 0x10 != 0 {
+            // There is a footer too
+            tag_size += 10;
+        };
+        file.seek(SeekFrom::Start(tag_size))?;
+        let mut after_tag = [0; 4];
+        if read_up_to(&mut file, &mut after_tag)? == 4 && &after_tag == b"fLaC" {
+            return Ok(Some(AudioFormat::Flac));
+        };
+        return Ok(Some(AudioFormat::Mp3));
+    };
+
+    if header.starts_with(b"fLaC") {
+        return Ok(Some(AudioFormat::Flac));
+    };
+
+    if header.starts_with(b"OggS") && header.len() >= 27 {
+        // The first packet says what codec the stream holds
+        let segments = header[26] as u64;
+        file.seek(SeekFrom::Start(27 + segments))?;
+        let mut packet_start = [0; 8];
+        read_up_to(&mut file, &mut packet_start)?;
+        return Ok(ogg_format(&packet_start));
+    };
+
+    if header.len() >= 12 && header.starts_with(b"RIFF") && &header[8..12] == b"WAVE" {
+        return Ok(Some(AudioFormat::Wav));
+    };
+
+    if header.len() >= 8 && &header[4..8] == b"ftyp" {
+        return Ok(Some(AudioFormat::Mp4));
+    };
+
+    if header.len() >= 2 && is_mp3_frame_sync(header[0], header[1]) {
+        return Ok(Some(AudioFormat::Mp3));
+    };
+
+    Ok(None)
+}
+
+fn ogg_format(packet_start: &[u8]) -> Option<AudioFormat> {
+    if packet_start.starts_with(b"\x01vorbis") {
+        Some(AudioFormat::OggVorbis)
+    } else if packet_start.starts_with(b"OpusHead") {
+        Some(AudioFormat::Opus)
+    } else {
+        None
+    }
+}
+
+fn is_mp3_frame_sync(first: u8, second: u8) -> bool {
+    let version = (second >> 3) & 0x03;
+    let layer = (second >> 1) & 0x03;
+    // A version of 1 and layer of 0 are reserved, and AAC uses the same sync
+    // bits with a layer of 0.
+    first == 0xff && second & 0xe0 == 0xe0 && version != 1 && layer != 0
+}
+
+pub fn read_tags(path: &Path, format: AudioFormat) -> Result<TagDetails> {
+    let to_tag_error = |err: io::Error| Error::Tag {
+        path: path.to_path_buf(),
+        error: err.to_string(),
+    };
+    let mut details = match format {
+        AudioFormat::Mp3 => {
+            let mut details = id3v2::read_tags(path)?;
+            mpeg::read_properties(path, &mut details).map_err(to_tag_error)?;
+            details
+        },
+        AudioFormat::Flac => flac::read_tags(path).map_err(to_tag_error)?,
+        AudioFormat::OggVorbis | AudioFormat::Opus => ogg::read_tags(path).map_err(to_tag_error)?,
+        AudioFormat::Wav => riff::read_tags(path).map_err(to_tag_error)?,
+        AudioFormat::Mp4 => mp4::read_tags(path).map_err(to_tag_error)?,
+    };
+    let file_size = match path.metadata() {
+        Ok(metadata) => metadata.len(),
+        Err(err) => return Err(Error::io(path, err)),
+    };
+    details.tidy(file_size);
+    Ok(details)
+}
+
+// Like read_exact, but a short read isn't an error.
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match reader.read(&mut buf[total..])? {
+            0 => break,
+            read => total += read,
+        };
+    }
+    Ok(total)
+}
+
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    reader.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() < len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "File ended unexpectedly"));
+    };
+    Ok(buf)
+}
+
+fn syncsafe_u32(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |acc, byte| (acc << 7) | (*byte as u32 & 0x7f))
+}
+
+fn u32_le(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |acc, byte| (acc << 8) | *byte as u32)
+}
+
+fn u32_be(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |acc, byte| (acc << 8) | *byte as u32)
+}
+
+fn u64_be(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |acc, byte| (acc << 8) | *byte as u64)
+}
+
+fn u64_le(bytes: &[u8]) -> u64 {
+    bytes.iter().rev().fold(0, |acc, byte| (acc << 8) | *byte as u64)
+}
+
+// A number of samples at the given rate as a duration, to the nearest
+// millisecond.
+fn samples_to_duration(samples: u64, sample_rate: u32) -> Option<Duration> {
+    if sample_rate == 0 {
+        return None;
+    };
+    Some(Duration::from_millis((samples as u128 * 1000 / sample_rate as u128) as u64))
+}
+
+fn malformed(description: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, description)
+}
+
+// Tags often come with padding or terminators we don't want.
+fn clean_text(text: &[u8]) -> String {
+    String::from_utf8_lossy(text).trim_end_matches('\0').trim().to_string()
+}
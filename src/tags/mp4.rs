@@ -0,0 +1,258 @@
+// MP4 (M4A/AAC) files are made of nested atoms: <length (32 bit big endian)>
+// <type (4)><data>. A length of 1 means a 64 bit length follows the type, and
+// 0 means the atom runs to the end of the file.
+// Tags are found in moov/udta/meta/ilst, with each tag holding a data atom.
+// The length is in moov/mvhd, and the audio is described in the sample
+// description of its track, moov/trak/mdia/minf/stbl/stsd.
+use super::{clean_text, malformed, read_bytes, samples_to_duration, u32_be, u64_be, TagDetails};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+pub fn read_tags(path: &Path) -> io::Result<TagDetails> {
+    let mut file = BufReader::new(File::open(path)?);
+    let file_end = file.seek(SeekFrom::End(0))?;
+
+    let (moov_start, moov_end) = match find_atom(&mut file, 0, file_end, b"moov")? {
+        Some(moov) => moov,
+        None => return Err(malformed("MP4 file has no moov atom")),
+    };
+    // Everything else we want is in moov, which is small enough to read in
+    // one go.
+    file.seek(SeekFrom::Start(moov_start))?;
+    let moov = read_bytes(&mut file, (moov_end - moov_start) as usize)?;
+
+    let mut details = match find_child(&moov, &[b"udta", b"meta"]) {
+        Some(meta) => parse_meta(meta)?,
+        None => TagDetails::default(),
+    };
+
+    if let Some(mvhd) = find_child(&moov, &[b"mvhd"]) {
+        // <version (1)><flags (3)>, then the times and lengths are 32 bit for
+        // version 0 and 64 bit for version 1:
+        // <created><modified><timescale (always 32 bit)><duration>
+        let (timescale, duration) = if mvhd.first() == Some(&1) && mvhd.len() >= 32 {
+            (u32_be(&mvhd[20..24]), u64_be(&mvhd[24..32]))
+        } else if mvhd.len() >= 20 {
+            (u32_be(&mvhd[12..16]), u32_be(&mvhd[16..20]) as u64)
+        } else {
+            return Err(malformed("MP4 mvhd atom is truncated"));
+        };
+        details.duration = samples_to_duration(duration, timescale);
+    };
+
+    // The first track with audio in it is the one we describe
+    for (atom_type, trak) in parse_atoms(&moov) {
+        if atom_type != b"trak" {
+            continue;
+        };
+        if let Some(stsd) = find_child(trak, &[b"mdia", b"minf", b"stbl", b"stsd"]) {
+            if parse_sample_description(stsd, &mut details) {
+                break;
+            };
+        };
+    }
+
+    Ok(details)
+}
+
+fn parse_meta(meta: &[u8]) -> io::Result<TagDetails> {
+    // meta is usually a "full" atom, with 4 bytes of version and flags
+    // before its children, but not always.
+    let meta_children = if meta.len() >= 8 && &meta[4..8] == b"hdlr" {
+        meta
+    } else if meta.len() >= 4 {
+        &meta[4..]
+    } else {
+        return Err(malformed("MP4 meta atom is truncated"));
+    };
+
+    match find_child(meta_children, &[b"ilst"]) {
+        Some(ilst) => Ok(parse_ilst(ilst)),
+        None => Ok(TagDetails::default()),
+    }
+}
+
+// stsd: <version and flags (4)><entry count (4)><sample entries>
+// Audio sample entries: <reserved (6)><data reference (2)><reserved (8)>
+// <channels (2)><sample size (2)><reserved (4)><sample rate (16.16)>
+// <child atoms>
+// Returns false if the track isn't audio.
+fn parse_sample_description(stsd: &[u8], details: &mut TagDetails) -> bool {
+    if stsd.len() < 8 {
+        return false;
+    };
+    let (entry_type, entry) = match parse_atoms(&stsd[8..]).into_iter().next() {
+        Some(entry) => entry,
+        None => return false,
+    };
+    if ! AUDIO_SAMPLE_ENTRIES.contains(&entry_type) || entry.len() < 28 {
+        return false;
+    };
+
+    details.channels = Some((entry[16] as u16) << 8 | entry[17] as u16);
+    details.sample_rate = Some(u32_be(&entry[24..28]) >> 16);
+    if let Some(esds) = find_child(&entry[28..], &[b"esds"]) {
+        details.bitrate = parse_esds_bitrate(esds);
+    };
+    true
+}
+
+const AUDIO_SAMPLE_ENTRIES: [&[u8]; 4] = [b"mp4a", b"alac", b"ac-3", b"ec-3"];
+
+// esds holds MPEG-4 descriptors, each <tag (1)><length (1-4)><data>. The
+// ES descriptor (tag 3) holds a decoder config descriptor (tag 4), which
+// holds: <object type (1)><stream type (1)><buffer size (3)>
+// <max bitrate (4)><average bitrate (4)>
+fn parse_esds_bitrate(esds: &[u8]) -> Option<u32> {
+    // Skip the version and flags
+    let mut pos = 4;
+    let (tag, _) = read_descriptor_header(esds, &mut pos)?;
+    if tag != 3 {
+        return None;
+    };
+    // <ES id (2)><flags (1)>, where the flags say what optional fields follow
+    let flags = *esds.get(pos + 2)?;
+    pos += 3;
+    if flags & 0x80 != 0 {
+        // Depends on another stream
+        pos += 2;
+    };
+    if flags & 0x40 != 0 {
+        // URL, with a length byte
+        pos += 1 + *esds.get(pos)? as usize;
+    };
+    if flags & 0x20 != 0 {
+        // OCR stream
+        pos += 2;
+    };
+
+    let (tag, _) = read_descriptor_header(esds, &mut pos)?;
+    if tag != 4 || esds.len() < pos + 13 {
+        return None;
+    };
+    let average = u32_be(&esds[pos + 9..pos + 13]);
+    if average > 0 {
+        Some(average)
+    } else {
+        None
+    }
+}
+
+// Lengths are 7 bits per byte, with the top bit set on all but the last.
+fn read_descriptor_header(data: &[u8], pos: &mut usize) -> Option<(u8, usize)> {
+    let tag = *data.get(*pos)?;
+    *pos += 1;
+    let mut length = 0;
+    for _ in 0..4 {
+        let byte = *data.get(*pos)?;
+        *pos += 1;
+        length = (length << 7) | (byte & 0x7f) as usize;
+        if byte & 0x80 == 0 {
+            break;
+        };
+    }
+    Some((tag, length))
+}
+
+// Returns where the data of the first atom of the given type between start
+// and end begins and ends.
+fn find_atom<R: Read + Seek>(file: &mut R, start: u64, end: u64, wanted: &[u8]) -> io::Result<Option<(u64, u64)>> {
+    let mut pos = start;
+    while pos < end && end - pos >= 8 {
+        file.seek(SeekFrom::Start(pos))?;
+        let header = read_bytes(file, 8)?;
+        let (header_length, length) = match u32_be(&header[0..4]) {
+            0 => (8, end - pos),
+            1 => (16, read_bytes(file, 8)?.iter().fold(0, |acc, byte| (acc << 8) | *byte as u64)),
+            length => (8, length as u64),
+        };
+        if length < header_length {
+            return Err(malformed("MP4 atom is too short"));
+        };
+        // Lengths can be 64 bit, so could be anything
+        let atom_end = match pos.checked_add(length) {
+            Some(atom_end) => atom_end,
+            None => return Err(malformed("MP4 atom is too long")),
+        };
+        if &header[4..8] == wanted {
+            return Ok(Some((pos + header_length, atom_end.min(end))));
+        };
+        pos = atom_end;
+    }
+    Ok(None)
+}
+
+// Follows a path of atom types down from atoms that have already been read
+// into memory, giving the data of the last one.
+fn find_child<'a>(data: &'a [u8], path: &[&[u8]]) -> Option<&'a [u8]> {
+    let mut current = data;
+    for wanted in path {
+        current = parse_atoms(current).into_iter()
+            .find(|(atom_type, _)| atom_type == wanted)
+            .map(|(_, child)| child)?;
+    }
+    Some(current)
+}
+
+// Splits up atoms that have already been read into memory.
+fn parse_atoms(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut atoms = Vec::new();
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let length = u32_be(&data[pos..pos + 4]) as usize;
+        if length < 8 || pos + length > data.len() {
+            break;
+        };
+        atoms.push((&data[pos + 4..pos + 8], &data[pos + 8..pos + length]));
+        pos += length;
+    }
+    atoms
+}
+
+fn parse_ilst(ilst: &[u8]) -> TagDetails {
+    let mut details = TagDetails::default();
+    for (item_type, item) in parse_atoms(ilst) {
+        let value = match get_item_data(item) {
+            Some(value) => value,
+            None => continue,
+        };
+        match item_type {
+            b"\xa9nam" => details.title = Some(clean_text(value)),
+            b"\xa9ART" => details.artist = Some(clean_text(value)),
+            b"\xa9alb" => details.album = Some(clean_text(value)),
+            // Track number is binary: <reserved (2)><track (2)><total (2)>
+            b"trkn" if value.len() >= 4 => {
+                let track_number = (value[2] as u16) << 8 | value[3] as u16;
+                details.track_number = Some(track_number.to_string());
+            },
+            b"\xa9day" => details.year = Some(clean_text(value)),
+            b"\xa9gen" => details.genre = Some(clean_text(value)),
+            // Disc number is like track number: <reserved (2)><disc (2)><total (2)>
+            b"disk" if value.len() >= 4 => {
+                let disc_number = (value[2] as u16) << 8 | value[3] as u16;
+                details.disc_number = Some(disc_number.to_string());
+                if value.len() >= 6 {
+                    let disc_total = (value[4] as u16) << 8 | value[5] as u16;
+                    if disc_total > 0 {
+                        details.disc_total = Some(disc_total.to_string());
+                    };
+                };
+            },
+            b"aART" => details.album_artist = Some(clean_text(value)),
+            b"\xa9wrt" => details.composer = Some(clean_text(value)),
+            _ => (),
+        };
+    }
+    details
+}
+
+// Item values are in a data atom: <type (4)><locale (4)><value>
+fn get_item_data(item: &[u8]) -> Option<&[u8]> {
+    for (atom_type, data) in parse_atoms(item) {
+        if atom_type == b"data" && data.len() >= 8 {
+            return Some(&data[8..]);
+        };
+    }
+    None
+}
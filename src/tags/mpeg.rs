@@ -0,0 +1,204 @@
+// MPEG audio (MP3) properties come from the frames themselves. The first
+// frame tells us the sample rate and channels, and for variable bitrate
+// files usually holds a Xing or VBRI header with the total number of frames.
+// Without one, the file is taken to be constant bitrate.
+use super::{read_bytes, read_up_to, syncsafe_u32, u32_be, samples_to_duration, TagDetails};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+// How far past the tags we'll look for the first frame
+const MAX_FRAME_SEARCH: usize = 64 * 1024;
+
+// In kbit/s, indexed by [version 1 or not][layer - 1][bitrate index]
+const BITRATES: [[[u32; 16]; 3]; 2] = [
+    [
+        [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448, 0],
+        [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384, 0],
+        [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 0],
+    ],
+    [
+        [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256, 0],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160, 0],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160, 0],
+    ],
+];
+
+// For MPEG 1, halved for MPEG 2 and quartered for MPEG 2.5
+const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+struct FrameHeader {
+    mpeg1: bool,
+    // 1 to 3
+    layer: u8,
+    bitrate: u32,
+    sample_rate: u32,
+    channels: u16,
+}
+
+impl FrameHeader {
+    fn parse(bytes: &[u8]) -> Option<FrameHeader> {
+        if bytes.len() < 4 || bytes[0] != 0xff || bytes[1] & 0xe0 != 0xe0 {
+            return None;
+        };
+        // 0 is MPEG 2.5, 1 is reserved, 2 is MPEG 2 and 3 is MPEG 1
+        let version = (bytes[1] >> 3) & 0x03;
+        let layer = match (bytes[1] >> 1) & 0x03 {
+            0 => return None,
+            bits => 4 - bits,
+        };
+        let bitrate_index = (bytes[2] >> 4) as usize;
+        let rate_index = ((bytes[2] >> 2) & 0x03) as usize;
+        if version == 1 || bitrate_index == 0x0f || rate_index == 3 {
+            return None;
+        };
+
+        let mpeg1 = version == 3;
+        let sample_rate = match version {
+            3 => SAMPLE_RATES[rate_index],
+            2 => SAMPLE_RATES[rate_index] / 2,
+            _ => SAMPLE_RATES[rate_index] / 4,
+        };
+        // Channel mode 3 is mono, the rest are all kinds of stereo
+        let channels = if bytes[3] >> 6 == 3 { 1 } else { 2 };
+
+        Some(FrameHeader {
+            mpeg1,
+            layer,
+            bitrate: BITRATES[if mpeg1 { 0 } else { 1 }][layer as usize - 1][bitrate_index] * 1000,
+            sample_rate,
+            channels,
+        })
+    }
+
+    fn samples_per_frame(&self) -> u32 {
+        match (self.layer, self.mpeg1) {
+            (1, _) => 384,
+            (3, false) => 576,
+            _ => 1152,
+        }
+    }
+
+    // Where a Xing header would start, after the side information
+    fn xing_offset(&self) -> usize {
+        match (self.mpeg1, self.channels) {
+            (true, 1) => 4 + 17,
+            (true, _) => 4 + 32,
+            (false, 1) => 4 + 9,
+            (false, _) => 4 + 17,
+        }
+    }
+}
+
+pub fn read_properties(path: &Path, details: &mut TagDetails) -> io::Result<()> {
+    let mut file = BufReader::new(File::open(path)?);
+    let file_end = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(0))?;
+
+    // Skip over an id3v2 tag, if there is one
+    let mut audio_start = 0;
+    let mut id3_header = [0; 10];
+    if read_up_to(&mut file, &mut id3_header)? == 10 && id3_header.starts_with(b"ID3") {
+        audio_start = 10 + syncsafe_u32(&id3_header[6..10]) as u64;
+        if id3_header[5] & 0x10 != 0 {
+            // There is a footer too
+            audio_start += 10;
+        };
+    };
+
+    file.seek(SeekFrom::Start(audio_start))?;
+    let mut search = Vec::new();
+    file.by_ref().take(MAX_FRAME_SEARCH as u64).read_to_end(&mut search)?;
+    let (frame_start, header) = match find_first_frame(&search) {
+        Some(frame) => frame,
+        // Not much of an MP3, but the tags might still be useful
+        None => return Ok(()),
+    };
+    details.sample_rate = Some(header.sample_rate);
+    details.channels = Some(header.channels);
+
+    let frame = &search[frame_start..];
+    if let Some(frames) = read_frame_count(frame, &header) {
+        let samples = frames as u64 * header.samples_per_frame() as u64;
+        details.duration = samples_to_duration(samples, header.sample_rate);
+        // Bitrate is left for tidy to work out from the duration
+        return Ok(());
+    };
+
+    // Constant bitrate, so the size of the audio gives us the length
+    let mut audio_end = file_end;
+    if file_end >= 128 {
+        file.seek(SeekFrom::Start(file_end - 128))?;
+        if read_bytes(&mut file, 3)? == b"TAG" {
+            // id3v1 tag
+            audio_end -= 128;
+        };
+    };
+    let audio_len = audio_end.saturating_sub(audio_start + frame_start as u64);
+    if header.bitrate > 0 {
+        details.bitrate = Some(header.bitrate);
+        details.duration = samples_to_duration(audio_len * 8, header.bitrate);
+    };
+    Ok(())
+}
+
+// Returns where the first frame starts and its header. Two frames in a row
+// are wanted where possible, as the sync bits turn up in other data too.
+fn find_first_frame(data: &[u8]) -> Option<(usize, FrameHeader)> {
+    let mut first_guess = None;
+    for start in 0..data.len().saturating_sub(4) {
+        let header = match FrameHeader::parse(&data[start..]) {
+            Some(header) => header,
+            None => continue,
+        };
+        match frame_length(&header, data[start + 2]) {
+            Some(length) if start + length + 4 <= data.len() => {
+                if FrameHeader::parse(&data[start + length..]).is_some() {
+                    return Some((start, header));
+                };
+            },
+            _ => if first_guess.is_none() {
+                // The file may just be too short to hold a second frame
+                first_guess = Some((start, header));
+            },
+        };
+    }
+    first_guess
+}
+
+fn frame_length(header: &FrameHeader, third_byte: u8) -> Option<usize> {
+    if header.bitrate == 0 {
+        // Free format, we can't tell
+        return None;
+    };
+    let padding = ((third_byte >> 1) & 0x01) as u32;
+    let length = if header.layer == 1 {
+        (12 * header.bitrate / header.sample_rate + padding) * 4
+    } else {
+        header.samples_per_frame() / 8 * header.bitrate / header.sample_rate + padding
+    };
+    Some(length as usize)
+}
+
+// Variable bitrate encoders put a Xing (or Info) header after the side
+// information of the first frame, or a VBRI header at a fixed offset.
+// <id (4)><flags (4)><frames if flags & 1 (4)>...
+// VBRI: <id (4)><version (2)><delay (2)><quality (2)><bytes (4)><frames (4)>
+fn read_frame_count(frame: &[u8], header: &FrameHeader) -> Option<u32> {
+    let xing = header.xing_offset();
+    if frame.len() >= xing + 12 {
+        let id = &frame[xing..xing + 4];
+        if id == b"Xing" || id == b"Info" {
+            let flags = u32_be(&frame[xing + 4..xing + 8]);
+            if flags & 0x01 != 0 {
+                return Some(u32_be(&frame[xing + 8..xing + 12]));
+            };
+        };
+    };
+
+    let vbri = 4 + 32;
+    if frame.len() >= vbri + 18 && &frame[vbri..vbri + 4] == b"VBRI" {
+        return Some(u32_be(&frame[vbri + 14..vbri + 18]));
+    };
+    None
+}
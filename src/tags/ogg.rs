@@ -0,0 +1,128 @@
+// Ogg Vorbis and Opus keep their tags in the second packet of the stream,
+// after an identification packet describing the audio. The length comes
+// from the granule position of the last page, which counts samples.
+// Ogg page layout:
+// "OggS"<version><header type><granule position (8)><serial (4)>
+// <sequence number (4)><checksum (4)><segment count><segment table><data>
+use super::{malformed, read_bytes, samples_to_duration, u32_le, u64_le, vorbis_comment, TagDetails};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+pub fn read_tags(path: &Path) -> io::Result<TagDetails> {
+    let mut file = BufReader::new(File::open(path)?);
+    let (serial, packets) = read_packets(&mut file, 2)?;
+
+    let comment_packet = &packets[1];
+    let comments = if comment_packet.starts_with(b"\x03vorbis") {
+        &comment_packet[7..]
+    } else if comment_packet.starts_with(b"OpusTags") {
+        &comment_packet[8..]
+    } else {
+        return Err(malformed("Ogg stream has no comment header"));
+    };
+
+    let mut details = vorbis_comment::to_tag_details(&vorbis_comment::parse(comments)?);
+    let last_granule = find_last_granule(&mut file, serial)?;
+
+    let ident_packet = &packets[0];
+    if ident_packet.starts_with(b"\x01vorbis") && ident_packet.len() >= 28 {
+        // <version (4)><channels (1)><sample rate (4)><max bitrate (4)>
+        // <nominal bitrate (4)><min bitrate (4)>
+        let sample_rate = u32_le(&ident_packet[12..16]);
+        // Signed, so a value of -1 is possible
+        let nominal_bitrate = u32_le(&ident_packet[20..24]) as i32;
+        details.channels = Some(ident_packet[11] as u16);
+        details.sample_rate = Some(sample_rate);
+        // Zero (or -1) means the encoder didn't say
+        if nominal_bitrate > 0 {
+            details.bitrate = Some(nominal_bitrate as u32);
+        };
+        details.duration = last_granule.and_then(|granule| samples_to_duration(granule, sample_rate));
+    } else if ident_packet.starts_with(b"OpusHead") && ident_packet.len() >= 19 {
+        // <version (1)><channels (1)><pre-skip (2)><input sample rate (4)>...
+        // Opus is always decoded at 48kHz, and the first pre-skip samples
+        // are thrown away.
+        let pre_skip = (ident_packet[11] as u64) << 8 | ident_packet[10] as u64;
+        details.channels = Some(ident_packet[9] as u16);
+        details.sample_rate = Some(OPUS_SAMPLE_RATE);
+        details.duration = last_granule
+            .and_then(|granule| samples_to_duration(granule.saturating_sub(pre_skip), OPUS_SAMPLE_RATE));
+    };
+
+    Ok(details)
+}
+
+const OPUS_SAMPLE_RATE: u32 = 48000;
+
+// Pages are at most about 64KB, so the last one will be in this much of the
+// end of the file.
+const LAST_PAGE_SEARCH: u64 = 65536 + 27 + 255;
+
+// Finds the granule position of the last page of the given stream.
+fn find_last_granule<R: Read + Seek>(file: &mut R, serial: u32) -> io::Result<Option<u64>> {
+    let file_end = file.seek(SeekFrom::End(0))?;
+    let search_start = file_end.saturating_sub(LAST_PAGE_SEARCH);
+    file.seek(SeekFrom::Start(search_start))?;
+    let data = read_bytes(file, (file_end - search_start) as usize)?;
+
+    let mut pos = data.len().saturating_sub(27);
+    loop {
+        let page = &data[pos..];
+        if page.len() >= 27 && &page[0..4] == b"OggS" && u32_le(&page[14..18]) == serial {
+            let granule = u64_le(&page[6..14]);
+            // All ones means no packet finished on this page
+            if granule != !0 {
+                return Ok(Some(granule));
+            };
+        };
+        if pos == 0 {
+            return Ok(None);
+        };
+        pos -= 1;
+    }
+}
+
+// Reads the first packets of the first logical stream in the file, giving
+// the stream's serial number too.
+fn read_packets<R: Read + Seek>(file: &mut R, count: usize) -> io::Result<(u32, Vec<Vec<u8>>)> {
+    let mut packets = Vec::new();
+    let mut current_packet = Vec::new();
+    let mut stream_serial = None;
+
+    while packets.len() < count {
+        let header = read_bytes(file, 27)?;
+        if &header[0..4] != b"OggS" {
+            return Err(malformed("Lost track of Ogg pages"));
+        };
+        let serial = u32_le(&header[14..18]);
+        let segments = read_bytes(file, header[26] as usize)?;
+        let page_length: usize = segments.iter().map(|segment| *segment as usize).sum();
+
+        if stream_serial.is_none() {
+            stream_serial = Some(serial);
+        };
+        if stream_serial != Some(serial) {
+            // Part of some other stream in the same file
+            file.seek(SeekFrom::Current(page_length as i64))?;
+            continue;
+        };
+
+        let page = read_bytes(file, page_length)?;
+        let mut pos = 0;
+        for segment in segments {
+            let segment = segment as usize;
+            current_packet.extend_from_slice(&page[pos..pos + segment]);
+            pos += segment;
+            // A segment shorter than the maximum ends the packet
+            if segment < 255 {
+                packets.push(current_packet);
+                current_packet = Vec::new();
+                if packets.len() == count {
+                    break;
+                };
+            };
+        }
+    }
+    Ok((stream_serial.unwrap(), packets))
+}
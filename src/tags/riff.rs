@@ -0,0 +1,80 @@
+// WAV files are RIFF containers, with tags in a LIST chunk of type INFO.
+// Chunks are <id (4)><length (32 bit little endian)><data>, padded to an
+// even length. The audio is described by the fmt chunk, and is in the data
+// chunk.
+use super::{clean_text, malformed, read_bytes, u32_le, TagDetails};
+use std::time::Duration;
+use std::fs::File;
+use std::io::{self, BufReader, Seek, SeekFrom};
+use std::path::Path;
+
+pub fn read_tags(path: &Path) -> io::Result<TagDetails> {
+    let mut file = BufReader::new(File::open(path)?);
+    let header = read_bytes(&mut file, 12)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(malformed("Not a WAV file"));
+    };
+    let riff_end = 8 + u32_le(&header[4..8]) as u64;
+
+    let mut details = TagDetails::default();
+    let mut byte_rate = 0;
+    let mut data_length = None;
+    let mut pos = 12;
+    while pos + 8 <= riff_end {
+        let chunk_header = match read_bytes(&mut file, 8) {
+            Ok(chunk_header) => chunk_header,
+            // Plenty of files claim to be longer than they are
+            Err(_) => break,
+        };
+        let length = u32_le(&chunk_header[4..8]) as u64;
+        let padded_length = length + length % 2;
+
+        if &chunk_header[0..4] == b"LIST" && length >= 4 {
+            let list = read_bytes(&mut file, padded_length as usize)?;
+            if &list[0..4] == b"INFO" {
+                parse_info(&list[4..length as usize], &mut details);
+            };
+        } else if &chunk_header[0..4] == b"fmt " && length >= 16 {
+            // <format (2)><channels (2)><sample rate (4)><bytes per second (4)>
+            // <block align (2)><bits per sample (2)>...
+            let format = read_bytes(&mut file, padded_length as usize)?;
+            details.channels = Some(format[2] as u16 | (format[3] as u16) << 8);
+            details.sample_rate = Some(u32_le(&format[4..8]));
+            byte_rate = u32_le(&format[8..12]);
+            details.bitrate = Some(byte_rate.saturating_mul(8));
+        } else {
+            if &chunk_header[0..4] == b"data" {
+                data_length = Some(length);
+            };
+            file.seek(SeekFrom::Current(padded_length as i64))?;
+        };
+        pos += 8 + padded_length;
+    }
+
+    if let Some(data_length) = data_length {
+        if byte_rate > 0 {
+            details.duration = Some(Duration::from_millis(data_length * 1000 / byte_rate as u64));
+        };
+    };
+    Ok(details)
+}
+
+fn parse_info(data: &[u8], details: &mut TagDetails) {
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let id = &data[pos..pos + 4];
+        let length = u32_le(&data[pos + 4..pos + 8]) as usize;
+        let end = (pos + 8 + length).min(data.len());
+        let value = Some(clean_text(&data[pos + 8..end]));
+        match id {
+            b"INAM" => details.title = value,
+            b"IART" => details.artist = value,
+            b"IPRD" => details.album = value,
+            b"ITRK" | b"IPRT" => details.track_number = value,
+            b"ICRD" => details.year = value,
+            b"IGNR" => details.genre = value,
+            _ => (),
+        };
+        pos += 8 + length + length % 2;
+    }
+}
@@ -0,0 +1,62 @@
+// Vorbis comments are shared by FLAC, Ogg Vorbis and Opus.
+// Layout (all lengths are 32 bit little endian):
+// <vendor length><vendor><comment count>[<comment length><KEY=value>]*
+use super::{malformed, u32_le, TagDetails};
+use std::io;
+
+pub fn parse(data: &[u8]) -> io::Result<Vec<(String, String)>> {
+    let mut pos = 0;
+    let vendor_length = take_u32(data, &mut pos)? as usize;
+    take(data, &mut pos, vendor_length)?;
+    let count = take_u32(data, &mut pos)?;
+
+    let mut comments = Vec::new();
+    for _ in 0..count {
+        let length = take_u32(data, &mut pos)? as usize;
+        let comment = String::from_utf8_lossy(take(data, &mut pos, length)?);
+        let mut parts = comment.splitn(2, '=');
+        let key = parts.next().unwrap_or("");
+        if let Some(value) = parts.next() {
+            // Keys are case insensitive
+            comments.push((key.to_uppercase(), value.trim().to_string()));
+        };
+    }
+    Ok(comments)
+}
+
+pub fn to_tag_details(comments: &[(String, String)]) -> TagDetails {
+    let mut details = TagDetails::default();
+    for (key, value) in comments {
+        // Where a key is repeated, the first one wins
+        let field = match key.as_str() {
+            "TITLE" => &mut details.title,
+            "ARTIST" => &mut details.artist,
+            "ALBUM" => &mut details.album,
+            "TRACKNUMBER" => &mut details.track_number,
+            "DATE" | "YEAR" => &mut details.year,
+            "GENRE" => &mut details.genre,
+            "DISCNUMBER" => &mut details.disc_number,
+            "DISCTOTAL" | "TOTALDISCS" => &mut details.disc_total,
+            "ALBUMARTIST" | "ALBUM ARTIST" => &mut details.album_artist,
+            "COMPOSER" => &mut details.composer,
+            _ => continue,
+        };
+        if field.is_none() {
+            *field = Some(value.clone());
+        };
+    }
+    details
+}
+
+fn take<'a>(data: &'a [u8], pos: &mut usize, len: usize) -> io::Result<&'a [u8]> {
+    if data.len() - *pos < len {
+        return Err(malformed("Vorbis comment is truncated"));
+    };
+    let taken = &data[*pos..*pos + len];
+    *pos += len;
+    Ok(taken)
+}
+
+fn take_u32(data: &[u8], pos: &mut usize) -> io::Result<u32> {
+    Ok(u32_le(take(data, pos, 4)?))
+}
@@ -1,66 +1,168 @@
 use std::cmp::Ordering;
-use crate::delimiters::{END_OF_FIELD, END_OF_RECORD};
+use crate::delimiters::END_OF_RECORD;
+use crate::error::{Error, Result};
+use crate::save_format::{dump_record, split_records, to_record, Record, FORMAT_VERSION};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+#[cfg(feature = "export")]
+use serde::{Deserialize, Serialize};
 
 
-#[derive(Clone)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "export", derive(Serialize, Deserialize), serde(rename_all = "snake_case"))]
+pub enum TrackField {
+    TrackName,
+    Artist,
+    Album,
+    TrackNumber,
+}
+
+impl TrackField {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TrackField::TrackName => "track_name",
+            TrackField::Artist => "artist",
+            TrackField::Album => "album",
+            TrackField::TrackNumber => "track_number",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<TrackField> {
+        match name {
+            "track_name" => Some(TrackField::TrackName),
+            "artist" => Some(TrackField::Artist),
+            "album" => Some(TrackField::Album),
+            "track_number" => Some(TrackField::TrackNumber),
+            _ => None,
+        }
+    }
+}
+
+// The order fields were saved in by version 1 of the save format, before
+// fields had keys.
+pub(crate) const LEGACY_TRACK_KEYS: [&str; 16] = [
+    "title", "artist", "album", "track_number", "path", "inferred", "duration_ms", "year", "genre",
+    "disc_number", "disc_total", "album_artist", "composer", "bitrate", "sample_rate", "channels",
+];
+
+#[derive(Clone, Default)]
+#[cfg_attr(feature = "export", derive(Serialize, Deserialize), serde(default))]
 pub struct Track {
     pub track_name: String,
     pub artist: String,
     pub album: String,
     pub track_number: String,  // Yes, a string, because of hidden tracks on some albums
     pub path: String,
+    // Fields which were worked out from the path rather than read from tags
+    pub inferred: Vec<TrackField>,
+    #[cfg_attr(feature = "export", serde(
+        rename = "duration_ms",
+        serialize_with = "crate::export::serialize_duration_ms",
+        deserialize_with = "crate::export::deserialize_duration_ms",
+    ))]
+    pub duration: Option<Duration>,
+    pub year: String,
+    pub genre: String,
+    pub disc_number: String,
+    pub disc_total: String,
+    pub album_artist: String,
+    pub composer: String,
+    // Average, in bits per second
+    pub bitrate: Option<u32>,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
 }
 
 impl Track {
-    pub fn load(data: String) -> Track {
-        let mut track_details_pos = 0;
-        let mut track_name = String::new();
-        let mut artist = String::new();
-        let mut album = String::new();
-        let mut track_number = String::new();
-        let mut path = String::new();
-
-        for c in data.chars() {
-            if c == END_OF_FIELD {
-                track_details_pos += 1;
-                continue;
-            }
+    // Reads a track saved by dump. Errors will have an empty path and
+    // offsets from the start of this track's data, as we don't know where the
+    // data came from.
+    pub fn load(data: String) -> Result<Track> {
+        let corrupt = |offset| Error::CorruptSaveFile {
+            path: PathBuf::new(),
+            offset,
+        };
+        let (mut records, _) = split_records(&data, 0, false, FORMAT_VERSION).map_err(corrupt)?;
+        if records.is_empty() {
+            // Data had no end marker
+            return Err(corrupt(data.len()));
+        };
+        let record = to_record(records.remove(0), FORMAT_VERSION, &LEGACY_TRACK_KEYS).map_err(corrupt)?;
+        Track::from_record(&record)
+    }
 
-            if c == END_OF_RECORD {
-                return Track {
-                    track_name,
-                    artist,
-                    album,
-                    track_number,
-                    path,
-                };
-            }
+    // Fields that aren't there are left empty, and ones we don't know about
+    // are ignored.
+    pub(crate) fn from_record(record: &Record) -> Result<Track> {
+        let value = |key| record.value(key).to_string();
 
-            match track_details_pos {
-                0 => track_name.push(c),
-                1 => artist.push(c),
-                2 => album.push(c),
-                3 => track_number.push(c),
-                4 => path.push(c),
-                _ => panic!("Found too many fields in track."),
+        let mut inferred = Vec::new();
+        for name in record.value("inferred").split(',').filter(|name| ! name.is_empty()) {
+            match TrackField::from_name(name) {
+                Some(field) => inferred.push(field),
+                None => return Err(Error::CorruptSaveFile {
+                    path: PathBuf::new(),
+                    offset: record.offset("inferred"),
+                }),
             };
-        }
-        panic!("Could not create track, data had no end marker.");
+        };
+
+        Ok(Track {
+            track_name: value("title"),
+            artist: value("artist"),
+            album: value("album"),
+            track_number: value("track_number"),
+            path: value("path"),
+            inferred,
+            duration: parse_number(record, "duration_ms")?.map(Duration::from_millis),
+            year: value("year"),
+            genre: value("genre"),
+            disc_number: value("disc_number"),
+            disc_total: value("disc_total"),
+            album_artist: value("album_artist"),
+            composer: value("composer"),
+            bitrate: parse_number(record, "bitrate")?,
+            sample_rate: parse_number(record, "sample_rate")?,
+            channels: parse_number(record, "channels")?,
+        })
     }
 
     pub fn dump(&self) -> String {
         format!(
-            "{name}{field_end}{artist}{field_end}{album}{field_end}{track}{field_end}{path}{record_end}",
-            name = &self.track_name,
-            artist = &self.artist,
-            album = &self.album,
-            track = &self.track_number,
-            path = &self.path,
-            field_end = END_OF_FIELD,
+            "{fields}{record_end}",
+            fields = dump_record(&self.to_record()),
             record_end = END_OF_RECORD,
         )
     }
 
+    pub(crate) fn to_record(&self) -> Vec<(&'static str, String)> {
+        let inferred: Vec<&str> = self.inferred.iter().map(|field| field.name()).collect();
+        let number = |number: Option<u64>| number.map(|number| number.to_string()).unwrap_or_default();
+        vec!(
+            ("title", self.track_name.clone()),
+            ("artist", self.artist.clone()),
+            ("album", self.album.clone()),
+            ("track_number", self.track_number.clone()),
+            ("path", self.path.clone()),
+            ("inferred", inferred.join(",")),
+            ("duration_ms", number(self.duration.map(|duration| duration.as_millis() as u64))),
+            ("year", self.year.clone()),
+            ("genre", self.genre.clone()),
+            ("disc_number", self.disc_number.clone()),
+            ("disc_total", self.disc_total.clone()),
+            ("album_artist", self.album_artist.clone()),
+            ("composer", self.composer.clone()),
+            ("bitrate", number(self.bitrate.map(u64::from))),
+            ("sample_rate", number(self.sample_rate.map(u64::from))),
+            ("channels", number(self.channels.map(u64::from))),
+        )
+    }
+
+    pub fn is_inferred(&self, field: TrackField) -> bool {
+        self.inferred.contains(&field)
+    }
+
     pub fn order_by_track(&self, other: &Self) -> Ordering {
         if self.track_name > other.track_name {
             Ordering::Greater
@@ -83,30 +185,76 @@ impl Track {
             Ordering::Less
         } else {
             // Same artist
-            if self.album > other.album {
+            self.order_by_album(other)
+        }
+    }
+
+    // Compilations have a different artist on each track, so the album
+    // artist keeps them together.
+    pub fn order_by_album_artist_and_album(&self, other: &Self) -> Ordering {
+        if self.get_album_artist() > other.get_album_artist() {
+            Ordering::Greater
+        } else if self.get_album_artist() < other.get_album_artist() {
+            Ordering::Less
+        } else {
+            // Same album artist
+            self.order_by_album(other)
+        }
+    }
+
+    // Falls back to the track's artist, as most albums don't say
+    pub fn get_album_artist(&self) -> &str {
+        if self.album_artist.is_empty() {
+            &self.artist
+        } else {
+            &self.album_artist
+        }
+    }
+
+    fn order_by_album(&self, other: &Self) -> Ordering {
+        if self.album > other.album {
+            Ordering::Greater
+        } else if self.album < other.album {
+            Ordering::Less
+        } else if self.disc_number > other.disc_number {
+            // Same album, but maybe not the same disc
+            Ordering::Greater
+        } else if self.disc_number < other.disc_number {
+            Ordering::Less
+        } else {
+            // Same disc
+            if self.track_number > other.track_number {
                 Ordering::Greater
-            } else if self.album < other.album {
+            } else if self.track_number < other.track_number {
                 Ordering::Less
             } else {
-                // Same album
-                if self.track_number > other.track_number {
+                // Somebody forgot to put track numbers
+                // We'll break ties on track name here
+                if self.track_name > other.track_name {
                     Ordering::Greater
-                } else if self.track_number < other.track_number {
-                    Ordering::Less
                 } else {
-                    // Somebody forgot to put track numbers
-                    // We'll break ties on track name here
-                    if self.track_name > other.track_name {
-                        Ordering::Greater
-                    } else {
-                        Ordering::Less
-                    }
+                    Ordering::Less
                 }
             }
         }
     }
 }
 
+// Numbers are left empty when they aren't known
+fn parse_number<T: FromStr>(record: &Record, key: &str) -> Result<Option<T>> {
+    let field = record.value(key);
+    if field.is_empty() {
+        return Ok(None);
+    };
+    match field.parse() {
+        Ok(number) => Ok(Some(number)),
+        Err(_) => Err(Error::CorruptSaveFile {
+            path: PathBuf::new(),
+            offset: record.offset(key),
+        }),
+    }
+}
+
 impl PartialEq for Track {
     fn eq(&self, other: &Self) -> bool {
         self.track_name == other.track_name && self.artist == other.artist && self.album == other.album && self.track_number == other.track_number && self.path == other.path
@@ -0,0 +1,195 @@
+use crate::error::{Error, Result};
+use crate::music_library::{MusicLibrary, ScanReport};
+use crate::track::Track;
+use crate::shared::DynamicSource;
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// How long to wait for a file to stop changing before reading it, so that
+// files still being written aren't read half finished.
+const DEFAULT_DEBOUNCE_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Clone, PartialEq)]
+pub enum LibraryEvent {
+    TrackAdded(Track),
+    TrackUpdated(Track),
+    // The path of the track which has gone
+    TrackRemoved(String),
+    // The path of a file which looked like audio but couldn't be read
+    ReadFailed(String),
+    WatchError(String),
+}
+
+pub struct LibraryWatcher {
+    library: Arc<Mutex<MusicLibrary>>,
+    subscribers: Arc<Mutex<Vec<mpsc::Sender<LibraryEvent>>>>,
+    stopped: Arc<AtomicBool>,
+    // Watching stops when this is dropped
+    _watcher: RecommendedWatcher,
+}
+
+impl LibraryWatcher {
+    pub fn new(library: MusicLibrary) -> Result<LibraryWatcher> {
+        LibraryWatcher::with_delay(library, DEFAULT_DEBOUNCE_DELAY)
+    }
+
+    pub fn with_delay(mut library: MusicLibrary, delay: Duration) -> Result<LibraryWatcher> {
+        let library_path = PathBuf::from(library.get_path());
+        // Changes are reported with canonical paths, which may not be how the
+        // library's path was given
+        let watched_path = match library_path.canonicalize() {
+            Ok(path) => path,
+            Err(err) => return Err(Error::io(&library_path, err)),
+        };
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = match notify::watcher(sender, delay) {
+            Ok(watcher) => watcher,
+            Err(err) => return Err(watch_error(&library_path, err)),
+        };
+        if let Err(err) = watcher.watch(&watched_path, RecursiveMode::Recursive) {
+            return Err(watch_error(&library_path, err));
+        };
+
+        // Anything which changed before we started watching is caught up on
+        // here, so nothing is missed.
+        library.rescan()?;
+
+        let watching = LibraryWatcher {
+            library: Arc::new(Mutex::new(library)),
+            subscribers: Arc::new(Mutex::new(Vec::new())),
+            stopped: Arc::new(AtomicBool::new(false)),
+            _watcher: watcher,
+        };
+
+        let library = watching.library.clone();
+        let subscribers = watching.subscribers.clone();
+        let stopped = watching.stopped.clone();
+        thread::spawn(move || {
+            while ! stopped.load(Ordering::Relaxed) {
+                let event = match receiver.recv_timeout(Duration::from_millis(100)) {
+                    Ok(event) => event,
+                    Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                };
+                let events = apply_event(&mut library.lock().unwrap(), &watched_path, event);
+                // Forget about anyone who has stopped listening
+                subscribers.lock().unwrap().retain(|subscriber| {
+                    events.iter().all(|event| subscriber.send(event.clone()).is_ok())
+                });
+            };
+        });
+
+        Ok(watching)
+    }
+
+    // A snapshot of the library as it is now, e.g. to pass on to a playlist
+    // or queue after being told about a change.
+    pub fn get_library(&self) -> MusicLibrary {
+        self.library.lock().unwrap().clone()
+    }
+
+    pub fn subscribe(&self) -> mpsc::Receiver<LibraryEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    // Stop watching, handing back the library as it was last updated
+    pub fn into_library(self) -> MusicLibrary {
+        self.stopped.store(true, Ordering::Relaxed);
+        self.library.lock().unwrap().clone()
+    }
+}
+
+impl Drop for LibraryWatcher {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+}
+
+fn watch_error(path: &Path, err: notify::Error) -> Error {
+    Error::Watch {
+        path: path.to_path_buf(),
+        error: err.to_string(),
+    }
+}
+
+// The path as the library knows it, for a canonical path from a change
+fn library_path_for(library: &MusicLibrary, watched_path: &Path, path: &Path) -> PathBuf {
+    if let Ok(relative) = path.strip_prefix(watched_path) {
+        return Path::new(library.get_path()).join(relative);
+    };
+    // Reached some other way, such as through a link, so it can only be
+    // matched against tracks that are still there
+    let canonical = path.canonicalize();
+    let track = library.get_tracks().iter().find(|track| {
+        match (&canonical, Path::new(&track.path).canonicalize()) {
+            (Ok(canonical), Ok(track_path)) => *canonical == track_path,
+            _ => false,
+        }
+    });
+    match track {
+        Some(track) => PathBuf::from(&track.path),
+        None => path.to_path_buf(),
+    }
+}
+
+fn apply_event(library: &mut MusicLibrary, watched_path: &Path, event: DebouncedEvent) -> Vec<LibraryEvent> {
+    let result = match event {
+        DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Chmod(path)
+            | DebouncedEvent::Remove(path) => {
+            let path = library_path_for(library, watched_path, &path);
+            library.rescan_path(&path)
+        },
+        DebouncedEvent::Rename(from, to) => {
+            let from = library_path_for(library, watched_path, &from);
+            let to = library_path_for(library, watched_path, &to);
+            library.rescan_path(&from).and_then(|mut report| {
+                let to_report = library.rescan_path(&to)?;
+                report.added.extend(to_report.added);
+                report.updated.extend(to_report.updated);
+                report.removed.extend(to_report.removed);
+                report.failed.extend(to_report.failed);
+                Ok(report)
+            })
+        },
+        // Events were lost, so check everything
+        DebouncedEvent::Rescan => library.rescan(),
+        DebouncedEvent::Error(err, _) => return vec!(LibraryEvent::WatchError(err.to_string())),
+        // These are followed by the debounced event once things settle down
+        DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_) => return Vec::new(),
+    };
+
+    match result {
+        Ok(report) => report_to_events(library, report),
+        Err(err) => vec!(LibraryEvent::WatchError(err.to_string())),
+    }
+}
+
+fn report_to_events(library: &MusicLibrary, report: ScanReport) -> Vec<LibraryEvent> {
+    let mut events = Vec::new();
+    for path in report.removed {
+        events.push(LibraryEvent::TrackRemoved(path));
+    };
+    for path in report.added {
+        if let Some(track) = library.get_track(&path) {
+            events.push(LibraryEvent::TrackAdded(track));
+        };
+    };
+    for path in report.updated {
+        if let Some(track) = library.get_track(&path) {
+            events.push(LibraryEvent::TrackUpdated(track));
+        };
+    };
+    for path in report.failed {
+        events.push(LibraryEvent::ReadFailed(path));
+    };
+    events
+}
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use korama;
+
+#[test]
+fn metadata_bias() {
+    // Only a few tracks are from the 90s, but half the picks should be
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test bias playlist"));
+    dyn_playlist.add_dynamic_playlist_source(get_numbered_source("tracks", 200, |number| {
+        if number < 20 { String::from("1995-06-01") } else { String::from("2005") }
+    }), None);
+    dyn_playlist.add_bias(korama::MetadataBias::year_between(1990, 1999, 0.5));
+
+    assert_proportion(&mut dyn_playlist, 2000, |track| track.year.starts_with("199"), 0.5);
+}
+
+#[test]
+fn source_bias() {
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test bias playlist"));
+    dyn_playlist.add_dynamic_playlist_source(get_numbered_source("small", 100, |_| String::new()), None);
+    dyn_playlist.add_dynamic_playlist_source(get_numbered_source("large", 300, |_| String::new()), None);
+    dyn_playlist.add_bias(korama::SourceBias::new("small", 0.6));
+
+    assert_proportion(&mut dyn_playlist, 2000, |track| track.path.starts_with("/small/"), 0.6);
+}
+
+#[test]
+fn rating_bias() {
+    // Half the tracks have 5 stars, so are picked 6 times as often as the
+    // other half with none
+    let source = get_numbered_source("rated", 500, |_| String::new());
+    let ratings: HashMap<String, u8> = korama::Saveable::get_tracks(&source).into_iter()
+        .enumerate()
+        .map(|(number, track)| (track.path, if number % 2 == 0 { 5 } else { 0 }))
+        .collect();
+    let five_stars: Vec<String> = ratings.iter()
+        .filter(|(_, rating)| **rating == 5)
+        .map(|(path, _)| path.clone())
+        .collect();
+
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test bias playlist"));
+    dyn_playlist.add_dynamic_playlist_source(source, None);
+    dyn_playlist.add_bias(korama::RatingBias::new(ratings));
+
+    assert_proportion(&mut dyn_playlist, 2000, |track| five_stars.contains(&track.path), 6.0 / 7.0);
+}
+
+#[test]
+fn recency_bias() {
+    let mut source = korama::Playlist::new(String::from("Artists"));
+    for artist in &["First", "Second", "Third"] {
+        for number in 0..5 {
+            source.add_track(korama::Track {
+                track_name: format!("Track {}", number),
+                artist: artist.to_string(),
+                path: format!("/{}/{}", artist, number),
+                ..Default::default()
+            });
+        }
+    }
+
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test bias playlist"));
+    dyn_playlist.add_dynamic_playlist_source(source, None);
+    dyn_playlist.add_bias(korama::RecencyBias::new(korama::RecentBy::Artist, Duration::from_secs(60 * 60)));
+
+    // Played elsewhere, one recently and one too long ago to count
+    let now = SystemTime::now();
+    let played_by = |artist: &str| korama::Track {
+        artist: artist.to_string(),
+        path: format!("/elsewhere/{}", artist),
+        ..Default::default()
+    };
+    dyn_playlist.record_play(played_by("first"), now - Duration::from_secs(10 * 60));
+    dyn_playlist.record_play(played_by("Second"), now - Duration::from_secs(2 * 60 * 60));
+
+    // Then each pick rules out the next artist, until there are none left
+    let mut artists = vec!(dyn_playlist.next().unwrap().artist, dyn_playlist.next().unwrap().artist);
+    artists.sort();
+    assert_eq!(artists, vec!("Second", "Third"));
+    // Every artist has been played, so the bias is ignored rather than the
+    // playlist stopping
+    assert!(dyn_playlist.next().is_some());
+}
+
+#[test]
+fn biases_combine() {
+    // Half from the 90s, of which none are by artists played recently
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test bias playlist"));
+    let mut source = get_numbered_source("tracks", 200, |number| {
+        if number < 40 { String::from("1999") } else { String::from("2010") }
+    });
+    source.add_track(korama::Track {
+        track_name: String::from("Avoided"),
+        artist: String::from("Avoided artist"),
+        year: String::from("1999"),
+        path: String::from("/avoided"),
+        ..Default::default()
+    });
+    dyn_playlist.add_dynamic_playlist_source(source, None);
+    dyn_playlist.add_bias(korama::MetadataBias::year_between(1990, 1999, 0.5));
+    dyn_playlist.add_bias(korama::RecencyBias::new(korama::RecentBy::Artist, Duration::from_secs(60 * 60)));
+    dyn_playlist.record_play(korama::Track {
+        artist: String::from("Avoided artist"),
+        ..Default::default()
+    }, SystemTime::now());
+
+    // Each pick is by an artist who then can't come up again, so there are
+    // fewer picks than other artists, and the recency bias always applies
+    let avoided = (0..150)
+        .filter(|_| dyn_playlist.next().unwrap().path == "/avoided")
+        .count();
+    assert_eq!(avoided, 0);
+
+    dyn_playlist.clear_biases();
+    assert_proportion(&mut dyn_playlist, 2000, |track| track.year == "1999", 41.0 / 201.0);
+}
+
+#[test]
+fn bias_ruling_out_everything_is_skipped() {
+    // The only artist has been played, so the recency bias would leave
+    // nothing, but the year bias still gets half the picks from the 90s
+    let mut source = korama::Playlist::new(String::from("One artist"));
+    for number in 0..200 {
+        source.add_track(korama::Track {
+            track_name: format!("Track {}", number),
+            artist: String::from("Only artist"),
+            year: String::from(if number < 20 { "1999" } else { "2010" }),
+            path: format!("/one/{}", number),
+            ..Default::default()
+        });
+    }
+
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test bias playlist"));
+    dyn_playlist.add_dynamic_playlist_source(source, None);
+    dyn_playlist.add_bias(korama::MetadataBias::year_between(1990, 1999, 0.5));
+    dyn_playlist.add_bias(korama::RecencyBias::new(korama::RecentBy::Artist, Duration::from_secs(60 * 60)));
+    dyn_playlist.record_play(korama::Track {
+        artist: String::from("Only artist"),
+        ..Default::default()
+    }, SystemTime::now());
+
+    assert_proportion(&mut dyn_playlist, 2000, |track| track.year == "1999", 0.5);
+}
+
+// Checks the part of many picks that match is close to what it should be
+fn assert_proportion<F>(dyn_playlist: &mut korama::Playlist, draws: usize, matches: F, expected: f64)
+    where F: Fn(&korama::Track) -> bool
+{
+    let matching = (0..draws)
+        .filter(|_| matches(&dyn_playlist.next().unwrap()))
+        .count();
+    let proportion = matching as f64 / draws as f64;
+    assert!((proportion - expected).abs() < 0.04, "{} of picks matched rather than {}", proportion, expected);
+}
+
+fn get_numbered_source<F>(name: &str, track_count: usize, year: F) -> korama::Playlist
+    where F: Fn(usize) -> String
+{
+    let mut playlist = korama::Playlist::new(String::from(name));
+    for number in 0..track_count {
+        playlist.add_track(korama::Track {
+            track_name: format!("Track {}", number),
+            artist: format!("Artist {}", number),
+            year: year(number),
+            path: format!("/{}/{}", name, number),
+            ..Default::default()
+        });
+    }
+    playlist
+}
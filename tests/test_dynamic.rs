@@ -1,3 +1,4 @@
+use std::fs::{create_dir_all, remove_dir_all, remove_file};
 use std::path::PathBuf;
 use korama;
 use korama::Saveable;
@@ -9,7 +10,7 @@ fn add_dynamic_source_playlist() {
 
     let source_playlist = get_playlist_source();
 
-    dyn_playlist.add_dynamic_playlist_source(source_playlist.clone());
+    dyn_playlist.add_dynamic_playlist_source(source_playlist.clone(), None);
 
     let dynamic_playlist_sources = dyn_playlist.get_dynamic_playlist_sources();
     let dynamic_library_sources = dyn_playlist.get_dynamic_library_sources();
@@ -29,7 +30,7 @@ fn add_dynamic_source_library() {
 
     let source_library = get_library_source();
 
-    dyn_playlist.add_dynamic_library_source(source_library.clone());
+    dyn_playlist.add_dynamic_library_source(source_library.clone(), None);
 
     let dynamic_playlist_sources = dyn_playlist.get_dynamic_playlist_sources();
     let dynamic_library_sources = dyn_playlist.get_dynamic_library_sources();
@@ -43,6 +44,32 @@ fn add_dynamic_source_library() {
     assert!(get_library_paths().contains(&track.path));
 }
 
+#[test]
+fn update_dynamic_source_library() {
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test dynamic playlist"));
+
+    let source_library = get_library_source();
+
+    dyn_playlist.add_dynamic_library_source(source_library.clone(), None);
+
+    // A library with the same name replaces the old copy
+    let mut formats_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    formats_path.push("resources/test/formats");
+    let mut updated_library = korama::MusicLibrary::new(
+        String::from("Test source library"),
+        formats_path.to_str().unwrap().to_string(),
+    );
+    updated_library.scan().unwrap();
+    assert!(dyn_playlist.update_dynamic_library_source(updated_library));
+
+    let dynamic_library_sources = dyn_playlist.get_dynamic_library_sources();
+    assert_eq!(dynamic_library_sources.len(), 1);
+    assert_eq!(dynamic_library_sources[0].get_path(), formats_path.to_str().unwrap());
+
+    let other_library = korama::MusicLibrary::new(String::from("Not a source"), String::from("."));
+    assert!(! dyn_playlist.update_dynamic_library_source(other_library));
+}
+
 #[test]
 fn add_dynamic_source_library_and_playlist() {
     let mut dyn_playlist = korama::Playlist::new(String::from("Test dynamic playlist"));
@@ -50,8 +77,8 @@ fn add_dynamic_source_library_and_playlist() {
     let source_playlist = get_playlist_source();
     let source_library = get_library_source();
 
-    dyn_playlist.add_dynamic_playlist_source(source_playlist.clone());
-    dyn_playlist.add_dynamic_library_source(source_library.clone());
+    dyn_playlist.add_dynamic_playlist_source(source_playlist.clone(), None);
+    dyn_playlist.add_dynamic_library_source(source_library.clone(), None);
 
     check_dyn_playlist_with_both_sources(dyn_playlist, source_playlist, source_library);
 }
@@ -62,7 +89,7 @@ fn test_dynamic_window_tiny() {
 
     let source_library = get_library_source();
 
-    dyn_playlist.add_dynamic_library_source(source_library.clone());
+    dyn_playlist.add_dynamic_library_source(source_library.clone(), None);
 
     // Generate some tracks
     let mut paths = Vec::new();
@@ -104,7 +131,7 @@ fn test_dynamic_window_small() {
 
     let source_playlist = get_playlist_source();
 
-    dyn_playlist.add_dynamic_playlist_source(source_playlist.clone());
+    dyn_playlist.add_dynamic_playlist_source(source_playlist.clone(), None);
 
     // Generate some tracks
     let mut paths = Vec::new();
@@ -147,8 +174,8 @@ fn test_dynamic_window_smallish() {
     let source_playlist = get_playlist_source();
     let source_library = get_library_source();
 
-    dyn_playlist.add_dynamic_playlist_source(source_playlist.clone());
-    dyn_playlist.add_dynamic_library_source(source_library.clone());
+    dyn_playlist.add_dynamic_playlist_source(source_playlist.clone(), None);
+    dyn_playlist.add_dynamic_library_source(source_library.clone(), None);
 
     // Generate some tracks
     let mut paths = Vec::new();
@@ -210,6 +237,364 @@ fn test_dynamic_window_smallish() {
     };
 }
 
+#[test]
+fn save_and_load_dynamic_sources() {
+    let dir = set_up_dir("dynamic_sources");
+    let other_dir = dir.join("other");
+    create_dir_all(&other_dir).unwrap();
+    let dir_string = dir.to_str().unwrap().to_string();
+    let other_dir_string = other_dir.to_str().unwrap().to_string();
+
+    // One source loaded from elsewhere, and one that's never been loaded so
+    // goes alongside the playlist
+    get_playlist_source().save(other_dir_string.clone()).unwrap();
+    let source_playlist = korama::Playlist::load(other_dir_string.clone(), String::from("Test source playlist")).unwrap();
+    let source_library = get_library_source();
+    source_library.save(dir_string.clone()).unwrap();
+
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test dynamic playlist"));
+    dyn_playlist.add_dynamic_playlist_source(source_playlist.clone(), None);
+    dyn_playlist.add_dynamic_library_source(source_library.clone(), None);
+    for _ in 0..10 {
+        dyn_playlist.next().unwrap();
+    }
+    dyn_playlist.save(dir_string.clone()).unwrap();
+
+    let mut loaded = korama::Playlist::load(dir_string.clone(), String::from("Test dynamic playlist")).unwrap();
+    assert!(loaded.get_tracks() == dyn_playlist.get_tracks());
+    let loaded_playlist_sources = loaded.get_dynamic_playlist_sources();
+    let loaded_library_sources = loaded.get_dynamic_library_sources();
+    assert_eq!(loaded_playlist_sources.len(), 1);
+    assert!(loaded_playlist_sources[0].get_tracks() == source_playlist.get_tracks());
+    assert_eq!(loaded_library_sources.len(), 1);
+    assert!(loaded_library_sources[0].get_tracks() == source_library.get_tracks());
+    // Still dynamic, so carries on past the end
+    assert!(loaded.next().is_some());
+
+    // Saved as a source of itself, which mustn't load forever
+    loaded.add_dynamic_playlist_source(loaded.clone(), None);
+    loaded.save(dir_string.clone()).unwrap();
+    let looped = korama::Playlist::load(dir_string.clone(), String::from("Test dynamic playlist"));
+    remove_dir_all(&dir).unwrap();
+
+    assert_eq!(looped.unwrap().get_dynamic_playlist_sources().len(), 2);
+}
+
+#[test]
+fn save_and_load_in_memory_sources() {
+    let dir = set_up_dir("in_memory_sources");
+    let dir_string = dir.to_str().unwrap().to_string();
+
+    // Neither source has been saved anywhere, so they're saved with the playlist
+    let source_playlist = get_playlist_source();
+    let source_library = get_library_source();
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test in memory playlist"));
+    dyn_playlist.add_dynamic_playlist_source(source_playlist.clone(), None);
+    dyn_playlist.add_dynamic_library_source(source_library.clone(), None);
+    dyn_playlist.save(dir_string.clone()).unwrap();
+
+    let mut loaded = korama::Playlist::load(dir_string, String::from("Test in memory playlist")).unwrap();
+    remove_dir_all(&dir).unwrap();
+    assert!(loaded.get_source_errors().is_empty());
+    assert!(loaded.get_dynamic_playlist_sources()[0].get_tracks() == source_playlist.get_tracks());
+    assert!(loaded.get_dynamic_library_sources()[0].get_tracks() == source_library.get_tracks());
+    assert!(loaded.next().is_some());
+}
+
+#[test]
+fn load_with_missing_source() {
+    let dir = set_up_dir("missing_source");
+    let dir_string = dir.to_str().unwrap().to_string();
+
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test missing playlist"));
+    dyn_playlist.add_dynamic_playlist_source(get_playlist_source(), None);
+    dyn_playlist.add_dynamic_library_source(get_library_source(), Some(korama::SourceWeight::Share(40)));
+    dyn_playlist.add_track(get_library_source().get_tracks()[0].clone());
+    dyn_playlist.save(dir_string.clone()).unwrap();
+    remove_file(dir.join("Test source library.lib")).unwrap();
+
+    // The playlist still loads, without the library
+    let loaded = korama::Playlist::load(dir_string.clone(), String::from("Test missing playlist")).unwrap();
+    assert_eq!(loaded.get_tracks().len(), 1);
+    assert_eq!(loaded.get_dynamic_playlist_sources().len(), 1);
+    assert!(loaded.get_dynamic_library_sources().is_empty());
+    match loaded.get_source_errors().as_slice() {
+        [korama::Error::Io { path, .. }] => assert!(path.ends_with("Test source library.lib")),
+        _ => panic!("Expected the missing library to be reported."),
+    };
+
+    // Once it's back it's loaded again, as it was saved
+    loaded.save(dir_string.clone()).unwrap();
+    get_library_source().save(dir_string.clone()).unwrap();
+    let reloaded = korama::Playlist::load(dir_string, String::from("Test missing playlist")).unwrap();
+    remove_dir_all(&dir).unwrap();
+    assert!(reloaded.get_source_errors().is_empty());
+    assert_eq!(reloaded.get_dynamic_library_sources().len(), 1);
+    assert_eq!(reloaded.get_source_weight("Test source library"), Some(korama::SourceWeight::Share(40)));
+}
+
+#[test]
+fn save_and_load_dynamic_window() {
+    let dir = set_up_dir("dynamic_window");
+    let dir_string = dir.to_str().unwrap().to_string();
+    get_library_source().save(dir_string.clone()).unwrap();
+
+    // 7 tracks give a window of the last 3
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test window playlist"));
+    dyn_playlist.add_dynamic_library_source(get_library_source(), None);
+    for _ in 0..10 {
+        dyn_playlist.next().unwrap();
+    }
+    dyn_playlist.save(dir_string.clone()).unwrap();
+    let tracks = dyn_playlist.get_tracks();
+    let window = &tracks[tracks.len() - 3..];
+
+    // Without the window, one of the last picks would very likely come up
+    // again straight away at some point
+    let mut next_tracks = Vec::new();
+    for _ in 0..30 {
+        let mut loaded = korama::Playlist::load(dir_string.clone(), String::from("Test window playlist")).unwrap();
+        next_tracks.push(loaded.next().unwrap());
+    }
+    remove_dir_all(&dir).unwrap();
+
+    for track in next_tracks {
+        assert!(! window.contains(&track));
+    }
+}
+
+#[test]
+fn default_source_weights_follow_track_counts() {
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test weighted playlist"));
+    dyn_playlist.add_dynamic_playlist_source(get_numbered_source("small", 100), None);
+    dyn_playlist.add_dynamic_playlist_source(get_numbered_source("large", 300), None);
+
+    assert_share(&mut dyn_playlist, "small", 0.25);
+}
+
+#[test]
+fn fixed_source_weights() {
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test weighted playlist"));
+    dyn_playlist.add_dynamic_playlist_source(get_numbered_source("small", 100), Some(korama::SourceWeight::Fixed(300)));
+    dyn_playlist.add_dynamic_playlist_source(get_numbered_source("large", 300), Some(korama::SourceWeight::Fixed(100)));
+
+    assert_share(&mut dyn_playlist, "small", 0.75);
+
+    assert!(dyn_playlist.set_source_weight("small", korama::SourceWeight::Fixed(0)));
+    assert_share(&mut dyn_playlist, "small", 0.0);
+}
+
+#[test]
+fn share_source_weights() {
+    // A few favourites shouldn't be drowned out by a whole library
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test weighted playlist"));
+    dyn_playlist.add_dynamic_playlist_source(get_numbered_source("favourites", 20), Some(korama::SourceWeight::Share(50)));
+    dyn_playlist.add_dynamic_playlist_source(get_numbered_source("everything", 500), None);
+
+    assert_share(&mut dyn_playlist, "favourites", 0.5);
+
+    assert!(dyn_playlist.set_source_weight("favourites", korama::SourceWeight::Share(10)));
+    assert_eq!(dyn_playlist.get_source_weight("favourites"), Some(korama::SourceWeight::Share(10)));
+    assert_share(&mut dyn_playlist, "favourites", 0.1);
+
+    // Shares adding up to more than everything are scaled down
+    assert!(dyn_playlist.set_source_weight("favourites", korama::SourceWeight::Share(90)));
+    assert!(dyn_playlist.set_source_weight("everything", korama::SourceWeight::Share(90)));
+    assert_share(&mut dyn_playlist, "favourites", 0.5);
+
+    assert!(! dyn_playlist.set_source_weight("Not a source", korama::SourceWeight::Share(10)));
+    assert_eq!(dyn_playlist.get_source_weight("Not a source"), None);
+}
+
+#[test]
+fn save_and_load_source_weights() {
+    let dir = set_up_dir("source_weights");
+    let dir_string = dir.to_str().unwrap().to_string();
+    get_numbered_source("shared", 10).save(dir_string.clone()).unwrap();
+    get_numbered_source("fixed", 10).save(dir_string.clone()).unwrap();
+    get_library_source().save(dir_string.clone()).unwrap();
+
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test weighted playlist"));
+    dyn_playlist.add_dynamic_playlist_source(get_numbered_source("shared", 10), Some(korama::SourceWeight::Share(35)));
+    dyn_playlist.add_dynamic_playlist_source(get_numbered_source("fixed", 10), Some(korama::SourceWeight::Fixed(250)));
+    dyn_playlist.add_dynamic_library_source(get_library_source(), None);
+    dyn_playlist.save(dir_string.clone()).unwrap();
+
+    let loaded = korama::Playlist::load(dir_string, String::from("Test weighted playlist")).unwrap();
+    remove_dir_all(&dir).unwrap();
+
+    assert_eq!(loaded.get_source_weight("shared"), Some(korama::SourceWeight::Share(35)));
+    assert_eq!(loaded.get_source_weight("fixed"), Some(korama::SourceWeight::Fixed(250)));
+    assert_eq!(loaded.get_source_weight("Test source library"), Some(korama::SourceWeight::Tracks));
+}
+
+#[test]
+fn artist_separation() {
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test separated playlist"));
+    dyn_playlist.add_dynamic_playlist_source(get_grouped_source(4, 5), None);
+    dyn_playlist.set_artist_separation(3);
+
+    let artists: Vec<String> = (0..200).map(|_| dyn_playlist.next().unwrap().artist).collect();
+    for (index, artist) in artists.iter().enumerate().skip(3) {
+        assert!(! artists[index - 3..index].contains(artist), "{} came up again too soon", artist);
+    }
+}
+
+#[test]
+fn album_separation() {
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test separated playlist"));
+    let mut source = get_grouped_source(4, 20);
+    // A different album by a different artist, despite the name
+    for number in 0..20 {
+        source.add_track(korama::Track {
+            track_name: format!("Lookalike {}", number),
+            artist: String::from("Someone else"),
+            album: String::from("Album 0"),
+            path: format!("/lookalike/{}", number),
+            ..Default::default()
+        });
+    }
+    dyn_playlist.add_dynamic_playlist_source(source, None);
+    dyn_playlist.set_album_separation(3);
+
+    let albums: Vec<(String, String)> = (0..200)
+        .map(|_| dyn_playlist.next().unwrap())
+        .map(|track| (track.album, track.artist))
+        .collect();
+    for (index, album) in albums.iter().enumerate().skip(3) {
+        assert!(! albums[index - 3..index].contains(album), "{:?} came up again too soon", album);
+    }
+}
+
+#[test]
+fn separation_relaxed_for_small_sources() {
+    // Only two artists, so they can't be kept three tracks apart, but they
+    // can still take turns
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test separated playlist"));
+    dyn_playlist.add_dynamic_playlist_source(get_grouped_source(2, 5), None);
+    dyn_playlist.set_artist_separation(3);
+    dyn_playlist.set_album_separation(3);
+
+    let artists: Vec<String> = (0..100).map(|_| dyn_playlist.next().unwrap().artist).collect();
+    for pair in artists.windows(2) {
+        assert!(pair[0] != pair[1]);
+    }
+}
+
+#[test]
+fn save_and_load_separation() {
+    let dir = set_up_dir("separation");
+    let dir_string = dir.to_str().unwrap().to_string();
+
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test separated playlist"));
+    dyn_playlist.set_artist_separation(4);
+    dyn_playlist.set_album_separation(10);
+    dyn_playlist.save(dir_string.clone()).unwrap();
+    let loaded = korama::Playlist::load(dir_string, String::from("Test separated playlist")).unwrap();
+    remove_dir_all(&dir).unwrap();
+
+    assert_eq!(loaded.get_artist_separation(), 4);
+    assert_eq!(loaded.get_album_separation(), 10);
+}
+
+#[test]
+fn overlapping_sources() {
+    // The same files in both sources, with the playlist's details out of date
+    let source_library = get_library_source();
+    let mut source_playlist = korama::Playlist::new(String::from("Copies"));
+    for track in source_library.get_tracks() {
+        source_playlist.add_track(korama::Track {
+            track_name: format!("Old {}", track.track_name),
+            ..track
+        });
+    }
+
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test overlapping playlist"));
+    dyn_playlist.add_dynamic_playlist_source(source_playlist, Some(korama::SourceWeight::Fixed(1000)));
+    dyn_playlist.add_dynamic_library_source(source_library, None);
+
+    // 7 different tracks give a window of 3, however many sources they're in
+    let paths: Vec<String> = (0..500).map(|_| dyn_playlist.next().unwrap().path).collect();
+    for (index, path) in paths.iter().enumerate().skip(3) {
+        assert!(! paths[index - 3..index].contains(path), "{} came up again too soon", path);
+    }
+}
+
+#[test]
+fn window_covering_every_track() {
+    let dir = set_up_dir("covering_window");
+    let dir_string = dir.to_str().unwrap().to_string();
+
+    let mut dyn_playlist = korama::Playlist::new(String::from("Test covering playlist"));
+    dyn_playlist.add_dynamic_playlist_source(get_numbered_source("source", 7), None);
+    for _ in 0..10 {
+        dyn_playlist.next().unwrap();
+    }
+    dyn_playlist.save(dir_string.clone()).unwrap();
+
+    // The source now only has what's in the window
+    let tracks = dyn_playlist.get_tracks();
+    let mut shrunk_source = korama::Playlist::new(String::from("source"));
+    for track in &tracks[tracks.len() - 3..] {
+        shrunk_source.add_track(track.clone());
+    }
+    shrunk_source.save(dir_string.clone()).unwrap();
+
+    let mut loaded = korama::Playlist::load(dir_string, String::from("Test covering playlist")).unwrap();
+    remove_dir_all(&dir).unwrap();
+    for _ in 0..20 {
+        assert!(shrunk_source.get_tracks().contains(&loaded.next().unwrap()));
+    }
+}
+
+// Artists with an album each
+fn get_grouped_source(artist_count: usize, tracks_each: usize) -> korama::Playlist {
+    let mut playlist = korama::Playlist::new(String::from("Grouped"));
+    for artist in 0..artist_count {
+        for number in 0..tracks_each {
+            playlist.add_track(korama::Track {
+                track_name: format!("Track {}", number),
+                artist: format!("Artist {}", artist),
+                album: format!("Album {}", artist),
+                path: format!("/{}/{}", artist, number),
+                ..Default::default()
+            });
+        }
+    }
+    playlist
+}
+
+// Checks the part of many picks that came from the source is close to what
+// it should be
+fn assert_share(dyn_playlist: &mut korama::Playlist, source_name: &str, expected: f64) {
+    let draws = 4000;
+    let prefix = format!("/{}/", source_name);
+    let from_source = (0..draws)
+        .filter(|_| dyn_playlist.next().unwrap().path.starts_with(&prefix))
+        .count();
+    let share = from_source as f64 / draws as f64;
+    assert!((share - expected).abs() < 0.04, "{} was picked {} of the time rather than {}", source_name, share, expected);
+}
+
+fn get_numbered_source(name: &str, track_count: usize) -> korama::Playlist {
+    let mut playlist = korama::Playlist::new(String::from(name));
+    for number in 0..track_count {
+        playlist.add_track(korama::Track {
+            track_name: format!("Track {}", number),
+            path: format!("/{}/{}", name, number),
+            ..Default::default()
+        });
+    }
+    playlist
+}
+
+fn set_up_dir(name: &str) -> PathBuf {
+    let mut dir = std::env::temp_dir();
+    dir.push(format!("korama_{}_{}", name, std::process::id()));
+    create_dir_all(&dir).unwrap();
+    dir
+}
+
 fn get_playlist_paths() -> Vec<String> {
     vec!(
         String::from("/some/path"),
@@ -244,6 +629,7 @@ fn get_playlist_source() -> korama::Playlist {
         album: String::from("Example album"),
         track_number: String::from(""),
         path: String::from("/some/path"),
+        ..Default::default()
     };
     let example_track_2 = korama::Track {
         track_name: String::from("Second track"),
@@ -251,6 +637,7 @@ fn get_playlist_source() -> korama::Playlist {
         album: String::from("Extrample album"),
         track_number: String::from("4"),
         path: String::from("/some/other/path"),
+        ..Default::default()
     };
     let example_track_3 = korama::Track {
         track_name: String::from("Third track"),
@@ -258,6 +645,7 @@ fn get_playlist_source() -> korama::Playlist {
         album: String::from("Nextrample album"),
         track_number: String::from("4.2"),
         path: String::from("/some/other/path/again"),
+        ..Default::default()
     };
 
     let mut playlist = korama::Playlist::new(String::from("Test source playlist"));
@@ -280,7 +668,7 @@ fn get_library_source() -> korama::MusicLibrary {
         test_library_path.to_str().unwrap().to_string(),
     );
 
-    library.scan();
+    library.scan().unwrap();
 
     library
 }
@@ -0,0 +1,107 @@
+#![cfg(feature = "export")]
+use std::path::PathBuf;
+use std::time::Duration;
+use korama;
+use korama::Saveable;
+
+#[test]
+fn export_and_import_library() {
+    let mut library = set_up_formats_library();
+    library.set_path_patterns(vec!(korama::PathPattern::new("{artist}/{title}.{ext}").unwrap()));
+    library.scan().unwrap();
+
+    let check = |imported: korama::MusicLibrary| {
+        assert_eq!(imported.get_name(), library.get_name());
+        assert_eq!(imported.get_path(), library.get_path());
+        assert!(imported.get_tracks() == library.get_tracks());
+        assert_eq!(imported.get_path_patterns(), library.get_path_patterns());
+        assert_eq!(imported.get_diagnostics(), library.get_diagnostics());
+
+        let flac = imported.get_tracks_by_title().into_iter().find(|track| track.track_name == "Flac title").unwrap();
+        assert_eq!(flac.duration, Some(Duration::from_secs(3)));
+        assert_eq!(flac.genre, "Rock");
+    };
+
+    let json = korama::export_json(&library).unwrap();
+    check(korama::import_json(&json).unwrap());
+    let toml = korama::export_toml(&library).unwrap();
+    check(korama::import_toml(&toml).unwrap());
+}
+
+#[test]
+fn export_and_import_playlist() {
+    let mut source = korama::Playlist::new(String::from("Source playlist"));
+    source.add_track(example_track("Source track", "/music/source.mp3"));
+
+    let mut playlist = korama::Playlist::new(String::from("Exported playlist"));
+    for (name, path) in vec!(("One", "/music/1.mp3"), ("Two", "/music/2.mp3"), ("Three", "/music/3.mp3")) {
+        playlist.add_track(example_track(name, path));
+    }
+    playlist.add_dynamic_playlist_source(source.clone(), None);
+    playlist.add_dynamic_library_source(set_up_formats_library(), None);
+    playlist.next();
+    playlist.next();
+
+    for imported in vec!(
+        korama::import_json::<korama::Playlist>(&korama::export_json(&playlist).unwrap()).unwrap(),
+        korama::import_toml::<korama::Playlist>(&korama::export_toml(&playlist).unwrap()).unwrap(),
+    ) {
+        let mut imported = imported;
+        assert_eq!(imported.get_name(), playlist.get_name());
+        assert!(imported.get_tracks() == playlist.get_tracks());
+        assert_eq!(imported.get_dynamic_playlist_sources().len(), 1);
+        assert!(imported.get_dynamic_playlist_sources()[0].get_tracks() == source.get_tracks());
+        assert_eq!(imported.get_dynamic_library_sources()[0].get_name(), "Test formats library");
+        // Carries on from where it was
+        assert_eq!(imported.next().unwrap().track_name, "Three");
+    }
+}
+
+#[test]
+fn import_track_written_by_hand() {
+    let track: korama::Track = korama::import_json(r#"{
+        "track_name": "Hand made",
+        "artist": "Script",
+        "path": "/music/hand_made.flac",
+        "duration_ms": 61500,
+        "inferred": ["album"]
+    }"#).unwrap();
+
+    assert_eq!(track.track_name, "Hand made");
+    assert_eq!(track.artist, "Script");
+    assert_eq!(track.album, "");
+    assert_eq!(track.duration, Some(Duration::from_millis(61500)));
+    assert!(track.is_inferred(korama::TrackField::Album));
+}
+
+#[test]
+fn import_invalid_data() {
+    match korama::import_json::<korama::Track>("{\"track_name\": 3}") {
+        Err(korama::Error::Import { format, .. }) => assert_eq!(format, "JSON"),
+        _ => panic!("Expected an import error."),
+    };
+    match korama::import_toml::<korama::MusicLibrary>("name = \"No path\"\npath_patterns = [\"{nonsense}\"]") {
+        Err(korama::Error::Import { format, .. }) => assert_eq!(format, "TOML"),
+        _ => panic!("Expected an import error."),
+    };
+}
+
+fn example_track(name: &str, path: &str) -> korama::Track {
+    korama::Track{
+        track_name: String::from(name),
+        artist: String::from("Artist"),
+        album: String::from("Album"),
+        path: String::from(path),
+        ..Default::default()
+    }
+}
+
+fn set_up_formats_library() -> korama::MusicLibrary {
+    let mut test_library_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    test_library_path.push("resources/test/formats");
+
+    korama::MusicLibrary::new(
+        String::from("Test formats library"),
+        test_library_path.to_str().unwrap().to_string(),
+    )
+}
@@ -1,5 +1,6 @@
-use std::fs::remove_file;
+use std::fs::{copy, create_dir_all, metadata, read, read_dir, remove_dir_all, remove_file, write, File};
 use std::path::PathBuf;
+use std::time::Duration;
 use korama;
 use korama::Saveable;
 
@@ -22,22 +23,266 @@ fn test_save_and_load_library() {
     
     {
         let mut library = set_up_test_library();
-        library.scan();
+        library.scan().unwrap();
         // Ensure we have the expected contents before we save
         check_tracks_in_library_by_artist_and_album(&library);
-        library.save(saved_library_path.clone());
+        library.save(saved_library_path.clone()).unwrap();
     }
 
-    let library = korama::MusicLibrary::load(saved_library_path.clone(), String::from("Test library"));
+    let library = korama::MusicLibrary::load(saved_library_path.clone(), String::from("Test library")).unwrap();
     check_tracks_in_library_by_artist_and_album(&library);
     remove_file(format!("{}/{}", &saved_library_path, String::from("Test library.lib"))).unwrap();
 }
 
+#[test]
+fn save_library_with_format_version() {
+    let mut saved_library_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    saved_library_path.push("resources/test/library/saved_libraries");
+    let saved_library_path = saved_library_path.to_str().unwrap().to_string();
+    let saved_file = format!("{}/{}", &saved_library_path, String::from("Versioned library.lib"));
+
+    let library = korama::MusicLibrary::new(String::from("Versioned library"), String::from("/music"));
+    library.save(saved_library_path.clone()).unwrap();
+    let saved = read(&saved_file).unwrap();
+    remove_file(&saved_file).unwrap();
+
+    assert_eq!(String::from_utf8(saved).unwrap(), "\u{1c}3\u{1e}name=Versioned library\u{1f}path=/music\u{1d}");
+}
+
+#[test]
+fn load_version_1_library() {
+    let mut saved_library_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    saved_library_path.push("resources/test/library/saved_libraries");
+    let saved_library_path = saved_library_path.to_str().unwrap().to_string();
+    let saved_file = format!("{}/{}", &saved_library_path, String::from("Old library.lib"));
+
+    // Positional fields and no version, as saved before versions were added
+    write(
+        &saved_file,
+        concat!(
+            "Old library\u{1f}/music",
+            "\u{1e}stat\u{1f}/music/a.mp3\u{1f}1000\u{1f}2494",
+            "\u{1e}pattern\u{1f}{artist}/{title}.{ext}",
+            "\u{1e}diag\u{1f}/music/b.mp3\u{1f}missing_tag\u{1f}artist",
+            "\u{1d}Name\u{1f}Artist\u{1f}Album\u{1f}1\u{1f}/music/a.mp3\u{1e}",
+        ),
+    ).unwrap();
+
+    let library = korama::MusicLibrary::load(saved_library_path.clone(), String::from("Old library")).unwrap();
+    assert_eq!(library.get_name(), "Old library");
+    assert_eq!(library.get_path(), "/music");
+    assert_eq!(library.get_path_patterns(), vec!(korama::PathPattern::new("{artist}/{title}.{ext}").unwrap()));
+    assert_eq!(
+        library.get_diagnostics().get("/music/b.mp3").map(|diagnostic| diagnostic.reason),
+        Some(korama::SkipReason::MissingTag(korama::RequiredTag::Artist)),
+    );
+    let expected = vec!(korama::Track{
+        track_name: String::from("Name"),
+        artist: String::from("Artist"),
+        album: String::from("Album"),
+        track_number: String::from("1"),
+        path: String::from("/music/a.mp3"),
+        ..Default::default()
+    });
+    assert!(library.get_tracks() == expected);
+
+    // Saving upgrades it to the current version
+    library.save(saved_library_path.clone()).unwrap();
+    let saved = String::from_utf8(read(&saved_file).unwrap()).unwrap();
+    assert!(saved.starts_with("\u{1c}3\u{1e}name=Old library\u{1f}path=/music\u{1e}kind=stat\u{1f}path=/music/a.mp3"));
+    let reloaded = korama::MusicLibrary::load(saved_library_path.clone(), String::from("Old library")).unwrap();
+    remove_file(&saved_file).unwrap();
+    remove_file(format!("{}.bak", &saved_file)).unwrap();
+    assert!(reloaded.get_tracks() == expected);
+    assert_eq!(reloaded.get_path_patterns(), library.get_path_patterns());
+    assert_eq!(reloaded.get_diagnostics(), library.get_diagnostics());
+}
+
+#[test]
+fn load_library_from_newer_version() {
+    let mut saved_library_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    saved_library_path.push("resources/test/library/saved_libraries");
+    let saved_library_path = saved_library_path.to_str().unwrap().to_string();
+    let saved_file = format!("{}/{}", &saved_library_path, String::from("Future library.lib"));
+
+    write(&saved_file, "\u{1c}99\u{1e}name=Future library\u{1f}path=/music\u{1d}").unwrap();
+    let result = korama::MusicLibrary::load(saved_library_path.clone(), String::from("Future library"));
+    remove_file(&saved_file).unwrap();
+
+    match result {
+        Err(korama::Error::UnsupportedSaveVersion { version, .. }) => assert_eq!(version, 99),
+        _ => panic!("Expected an unsupported save version error."),
+    };
+}
+
+#[test]
+fn load_library_ignores_unknown_fields() {
+    let mut saved_library_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    saved_library_path.push("resources/test/library/saved_libraries");
+    let saved_library_path = saved_library_path.to_str().unwrap().to_string();
+    let saved_file = format!("{}/{}", &saved_library_path, String::from("Extra fields library.lib"));
+
+    write(
+        &saved_file,
+        concat!(
+            "\u{1c}2\u{1e}name=Extra fields library\u{1f}path=/music\u{1f}colour=blue",
+            "\u{1e}kind=something_new\u{1f}value=1",
+            "\u{1d}path=/music/a.mp3\u{1f}mood=happy\u{1f}artist=Artist\u{1f}title=Name\u{1e}",
+        ),
+    ).unwrap();
+    let library = korama::MusicLibrary::load(saved_library_path.clone(), String::from("Extra fields library")).unwrap();
+    remove_file(&saved_file).unwrap();
+
+    assert_eq!(library.get_path(), "/music");
+    let expected = vec!(korama::Track{
+        track_name: String::from("Name"),
+        artist: String::from("Artist"),
+        path: String::from("/music/a.mp3"),
+        ..Default::default()
+    });
+    assert!(library.get_tracks() == expected);
+}
+
+#[test]
+fn load_library_backup_when_corrupt() {
+    let mut saved_library_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    saved_library_path.push("resources/test/library/saved_libraries");
+    let saved_library_path = saved_library_path.to_str().unwrap().to_string();
+    let saved_file = format!("{}/{}", &saved_library_path, String::from("Backup library.lib"));
+    let backup_file = format!("{}.bak", &saved_file);
+
+    korama::MusicLibrary::new(String::from("Backup library"), String::from("/first")).save(saved_library_path.clone()).unwrap();
+    korama::MusicLibrary::new(String::from("Backup library"), String::from("/second")).save(saved_library_path.clone()).unwrap();
+    // Nothing is left over from writing the save
+    let left_over = read_dir(&saved_library_path).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("Backup library.lib.") && name.ends_with(".tmp"))
+        .count();
+    assert_eq!(left_over, 0);
+
+    let load = || korama::MusicLibrary::load(saved_library_path.clone(), String::from("Backup library"));
+    assert_eq!(load().unwrap().get_path(), "/second");
+
+    // As if the last save was cut short, so the one before is used
+    write(&saved_file, "\u{1c}3\u{1e}name=Backup library\u{1f}path=/sec").unwrap();
+    assert_eq!(load().unwrap().get_path(), "/first");
+    write(&saved_file, b"\xff\xfe").unwrap();
+    assert_eq!(load().unwrap().get_path(), "/first");
+
+    // Without a backup, the error is for the file asked for
+    remove_file(&backup_file).unwrap();
+    let result = load();
+    remove_file(&saved_file).unwrap();
+    match result {
+        Err(korama::Error::CorruptSaveFile { path, offset }) => {
+            assert_eq!(path, PathBuf::from(&saved_file));
+            assert_eq!(offset, 0);
+        },
+        _ => panic!("Expected a corrupt save file error."),
+    };
+}
+
+#[test]
+fn save_keeps_backup_when_corrupt() {
+    let mut saved_library_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    saved_library_path.push("resources/test/library/saved_libraries");
+    let saved_library_path = saved_library_path.to_str().unwrap().to_string();
+    let saved_file = format!("{}/{}", &saved_library_path, String::from("Corrupt backup library.lib"));
+    let backup_file = format!("{}.bak", &saved_file);
+
+    korama::MusicLibrary::new(String::from("Corrupt backup library"), String::from("/first")).save(saved_library_path.clone()).unwrap();
+    korama::MusicLibrary::new(String::from("Corrupt backup library"), String::from("/second")).save(saved_library_path.clone()).unwrap();
+
+    // The corrupt save shouldn't replace the backup of the first
+    write(&saved_file, "\u{1c}3\u{1e}name=Corrupt backup library\u{1f}path=/sec").unwrap();
+    korama::MusicLibrary::new(String::from("Corrupt backup library"), String::from("/third")).save(saved_library_path.clone()).unwrap();
+
+    let load = || korama::MusicLibrary::load(saved_library_path.clone(), String::from("Corrupt backup library"));
+    assert_eq!(load().unwrap().get_path(), "/third");
+
+    write(&saved_file, b"\xff\xfe").unwrap();
+    let result = load();
+    remove_file(&saved_file).unwrap();
+    remove_file(&backup_file).unwrap();
+    assert_eq!(result.unwrap().get_path(), "/first");
+}
+
+#[test]
+fn failed_save_leaves_nothing_behind() {
+    let mut saved_library_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    saved_library_path.push("resources/test/library/saved_libraries");
+    let saved_library_path = saved_library_path.to_str().unwrap().to_string();
+    // The save can't replace a directory
+    let saved_file = format!("{}/{}", &saved_library_path, String::from("Blocked library.lib"));
+    create_dir_all(format!("{}/inside", &saved_file)).unwrap();
+
+    let result = korama::MusicLibrary::new(String::from("Blocked library"), String::from("/music")).save(saved_library_path.clone());
+    let left_over = read_dir(&saved_library_path).unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("Blocked library.lib."))
+        .count();
+    remove_dir_all(&saved_file).unwrap();
+
+    assert!(result.is_err());
+    assert_eq!(left_over, 0);
+}
+
+#[test]
+fn load_missing_library() {
+    let mut saved_library_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    saved_library_path.push("resources/test/library/saved_libraries");
+
+    let result = korama::MusicLibrary::load(
+        saved_library_path.to_str().unwrap().to_string(),
+        String::from("No such library"),
+    );
+
+    match result {
+        Err(korama::Error::Io { .. }) => (),
+        _ => panic!("Expected an IO error loading a missing library."),
+    };
+}
+
+#[test]
+fn load_corrupt_library() {
+    let mut saved_library_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    saved_library_path.push("resources/test/library/saved_libraries");
+    let saved_library_path = saved_library_path.to_str().unwrap().to_string();
+    let corrupt_file = format!("{}/{}", &saved_library_path, String::from("Corrupt library.lib"));
+
+    // Header, then a track with too many fields
+    write(
+        &corrupt_file,
+        "Corrupt library\u{1f}/music\u{1d}Name\u{1f}Artist\u{1f}Album\u{1f}1\u{1f}/music/a.mp3\u{1f}extra\u{1e}",
+    ).unwrap();
+
+    let result = korama::MusicLibrary::load(saved_library_path.clone(), String::from("Corrupt library"));
+    remove_file(&corrupt_file).unwrap();
+
+    match result {
+        Err(korama::Error::CorruptSaveFile { offset, .. }) => assert_eq!(offset, 56),
+        _ => panic!("Expected a corrupt save file error."),
+    };
+}
+
+#[test]
+fn scan_missing_directory() {
+    let mut library = korama::MusicLibrary::new(
+        String::from("Missing library"),
+        String::from("/not/real/at/all"),
+    );
+
+    match library.scan() {
+        Err(korama::Error::Io { .. }) => (),
+        _ => panic!("Expected an IO error scanning a missing directory."),
+    };
+}
+
 #[test]
 fn get_tracks_by_artist_and_album() {
     let mut library = set_up_test_library();
 
-    library.scan();
+    library.scan().unwrap();
 
     check_tracks_in_library_by_artist_and_album(&library);
 }
@@ -46,7 +291,7 @@ fn get_tracks_by_artist_and_album() {
 fn get_tracks_by_track_name() {
     let mut library = set_up_test_library();
 
-    library.scan();
+    library.scan().unwrap();
 
     let expected = vec![
         korama::Track{
@@ -55,6 +300,7 @@ fn get_tracks_by_track_name() {
             album: String::from("The Ignored And the Found"),
             track_number: String::from("2.1"),
             path: get_full_track_path(String::from("another_artist/good_album/hidden_track.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("First steps"),
@@ -62,6 +308,7 @@ fn get_tracks_by_track_name() {
             album: String::from("Ignored"),
             track_number: String::from("1"),
             path: get_full_track_path(String::from("artist2/live_cover.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("First steps"),
@@ -69,6 +316,7 @@ fn get_tracks_by_track_name() {
             album: String::from("The Ignored And the Found"),
             track_number: String::from("2"),
             path: get_full_track_path(String::from("another_artist/good_album/another_track.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("Ignored"),
@@ -76,6 +324,7 @@ fn get_tracks_by_track_name() {
             album: String::from("Ignored"),
             track_number: String::from("1"),
             path: get_full_track_path(String::from("ignored.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("Not much to write home about"),
@@ -83,6 +332,7 @@ fn get_tracks_by_track_name() {
             album: String::from("The Greatest Album of Negligible MP3s"),
             track_number: String::from("1"),
             path: get_full_track_path(String::from("artist2/album/ignored.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("Scream into the mic"),
@@ -90,6 +340,7 @@ fn get_tracks_by_track_name() {
             album: String::from("Live Bootleg"),
             track_number: String::from(""),
             path: get_full_track_path(String::from("artist1/test.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("The Second Step"),
@@ -97,6 +348,7 @@ fn get_tracks_by_track_name() {
             album: String::from("The Ignored And the Found"),
             track_number: String::from("1"),
             path: get_full_track_path(String::from("another_artist/good_album/first_track.mp3")),
+            ..Default::default()
         },
     ];
 
@@ -117,6 +369,7 @@ fn check_tracks_in_library_by_artist_and_album(library: &korama::MusicLibrary) {
             album: String::from("Ignored"),
             track_number: String::from("1"),
             path: get_full_track_path(String::from("artist2/live_cover.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("Not much to write home about"),
@@ -124,6 +377,7 @@ fn check_tracks_in_library_by_artist_and_album(library: &korama::MusicLibrary) {
             album: String::from("The Greatest Album of Negligible MP3s"),
             track_number: String::from("1"),
             path: get_full_track_path(String::from("artist2/album/ignored.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("The Second Step"),
@@ -131,6 +385,7 @@ fn check_tracks_in_library_by_artist_and_album(library: &korama::MusicLibrary) {
             album: String::from("The Ignored And the Found"),
             track_number: String::from("1"),
             path: get_full_track_path(String::from("another_artist/good_album/first_track.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("First steps"),
@@ -138,6 +393,7 @@ fn check_tracks_in_library_by_artist_and_album(library: &korama::MusicLibrary) {
             album: String::from("The Ignored And the Found"),
             track_number: String::from("2"),
             path: get_full_track_path(String::from("another_artist/good_album/another_track.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("Falling over"),
@@ -145,6 +401,7 @@ fn check_tracks_in_library_by_artist_and_album(library: &korama::MusicLibrary) {
             album: String::from("The Ignored And the Found"),
             track_number: String::from("2.1"),
             path: get_full_track_path(String::from("another_artist/good_album/hidden_track.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("Ignored"),
@@ -152,6 +409,7 @@ fn check_tracks_in_library_by_artist_and_album(library: &korama::MusicLibrary) {
             album: String::from("Ignored"),
             track_number: String::from("1"),
             path: get_full_track_path(String::from("ignored.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("Scream into the mic"),
@@ -159,6 +417,7 @@ fn check_tracks_in_library_by_artist_and_album(library: &korama::MusicLibrary) {
             album: String::from("Live Bootleg"),
             track_number: String::from(""),
             path: get_full_track_path(String::from("artist1/test.mp3")),
+            ..Default::default()
         },
     ];
 
@@ -171,6 +430,532 @@ fn check_tracks_in_library_by_artist_and_album(library: &korama::MusicLibrary) {
             );
 }
 
+#[test]
+fn scan_other_formats() {
+    let mut test_library_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    test_library_path.push("resources/test/formats");
+
+    let mut library = korama::MusicLibrary::new(
+        String::from("Test formats library"),
+        test_library_path.to_str().unwrap().to_string(),
+    );
+
+    library.scan().unwrap();
+
+    let expected = vec![
+        korama::Track{
+            track_name: String::from("Flac title"),
+            artist: String::from("Flac artist"),
+            album: String::from("Flac album"),
+            track_number: String::from("3"),
+            path: get_format_track_path(String::from("flac_track.flac")),
+            ..Default::default()
+        },
+        korama::Track{
+            track_name: String::from("M4a title"),
+            artist: String::from("M4a artist"),
+            album: String::from("M4a album"),
+            track_number: String::from("9"),
+            path: get_format_track_path(String::from("m4a_track.m4a")),
+            ..Default::default()
+        },
+        korama::Track{
+            track_name: String::from("Opus title"),
+            artist: String::from("Opus artist"),
+            album: String::from("Opus album"),
+            track_number: String::from("2"),
+            path: get_format_track_path(String::from("opus_track.opus")),
+            ..Default::default()
+        },
+        korama::Track{
+            track_name: String::from("Scream into the mic"),
+            artist: String::from("Somebody"),
+            album: String::from("Live Bootleg"),
+            track_number: String::from(""),
+            path: get_format_track_path(String::from("upper_case.MP3")),
+            ..Default::default()
+        },
+        korama::Track{
+            track_name: String::from("Vorbis title"),
+            artist: String::from("Vorbis artist"),
+            album: String::from("Vorbis album"),
+            track_number: String::from("7"),
+            path: get_format_track_path(String::from("vorbis_track.ogg")),
+            ..Default::default()
+        },
+        korama::Track{
+            track_name: String::from("Wav title"),
+            artist: String::from("Wav artist"),
+            album: String::from("Wav album"),
+            track_number: String::from("5"),
+            path: get_format_track_path(String::from("wav_track.wav")),
+            ..Default::default()
+        },
+    ];
+
+    let result = library.get_tracks_by_artist_and_album();
+
+    assert!(result == expected,
+            "Results not as expected.\nResults were:\n{}\nExpected:\n{}",
+            generate_track_output(result),
+            generate_track_output(expected),
+            );
+}
+
+#[test]
+fn scan_track_metadata() {
+    let mut test_library_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    test_library_path.push("resources/test/formats");
+
+    let mut library = korama::MusicLibrary::new(
+        String::from("Test formats library"),
+        test_library_path.to_str().unwrap().to_string(),
+    );
+    library.scan().unwrap();
+    let get_track = |name: &str| library.get_track(&get_format_track_path(String::from(name))).unwrap();
+
+    let flac = get_track("flac_track.flac");
+    assert_eq!(flac.duration, Some(Duration::from_secs(3)));
+    assert_eq!(flac.year, "1994");
+    assert_eq!(flac.genre, "Rock");
+    assert_eq!(flac.disc_number, "1");
+    assert_eq!(flac.disc_total, "2");
+    assert_eq!(flac.album_artist, "Various artists");
+    assert_eq!(flac.composer, "Flac composer");
+    assert_eq!(flac.sample_rate, Some(44100));
+    assert_eq!(flac.channels, Some(2));
+    // FLAC doesn't say, so it's the average over the whole file
+    let flac_size = read(get_format_track_path(String::from("flac_track.flac"))).unwrap().len() as u32;
+    assert_eq!(flac.bitrate, Some(flac_size * 8 / 3));
+
+    let m4a = get_track("m4a_track.m4a");
+    assert_eq!(m4a.duration, Some(Duration::from_secs(5)));
+    assert_eq!(m4a.year, "2010");
+    assert_eq!(m4a.genre, "Electronic");
+    assert_eq!(m4a.disc_number, "2");
+    assert_eq!(m4a.disc_total, "3");
+    assert_eq!(m4a.album_artist, "M4a album artist");
+    assert_eq!(m4a.composer, "M4a composer");
+    assert_eq!(m4a.bitrate, Some(128000));
+    assert_eq!(m4a.sample_rate, Some(44100));
+    assert_eq!(m4a.channels, Some(2));
+
+    let vorbis = get_track("vorbis_track.ogg");
+    assert_eq!(vorbis.duration, Some(Duration::from_secs(2)));
+    assert_eq!(vorbis.year, "2001");
+    assert_eq!(vorbis.genre, "Jazz");
+    assert_eq!(vorbis.bitrate, Some(128000));
+    assert_eq!(vorbis.sample_rate, Some(44100));
+    assert_eq!(vorbis.channels, Some(2));
+
+    let opus = get_track("opus_track.opus");
+    assert_eq!(opus.duration, Some(Duration::from_secs(4)));
+    assert_eq!(opus.sample_rate, Some(48000));
+    assert_eq!(opus.channels, Some(2));
+
+    let wav = get_track("wav_track.wav");
+    assert_eq!(wav.duration, Some(Duration::from_secs(1)));
+    assert_eq!(wav.year, "1987");
+    assert_eq!(wav.genre, "Pop");
+    assert_eq!(wav.bitrate, Some(128000));
+    assert_eq!(wav.sample_rate, Some(8000));
+    assert_eq!(wav.channels, Some(1));
+
+    let mp3 = get_track("upper_case.MP3");
+    // Its frames are "free format", which don't say their bitrate
+    assert_eq!(mp3.duration, None);
+    assert_eq!(mp3.sample_rate, Some(44100));
+    assert_eq!(mp3.channels, Some(1));
+    assert!(mp3.album_artist.is_empty());
+}
+
+#[test]
+fn save_and_load_track_metadata() {
+    let track = korama::Track{
+        track_name: String::from("Title"),
+        artist: String::from("Artist"),
+        album: String::from("Album"),
+        track_number: String::from("1"),
+        path: String::from("/music/track.flac"),
+        duration: Some(Duration::from_millis(123456)),
+        year: String::from("1999"),
+        genre: String::from("Rock"),
+        disc_number: String::from("2"),
+        disc_total: String::from("2"),
+        album_artist: String::from("Various artists"),
+        composer: String::from("Composer"),
+        bitrate: Some(320000),
+        sample_rate: Some(44100),
+        channels: Some(2),
+        ..Default::default()
+    };
+
+    let loaded = korama::Track::load(track.dump()).unwrap();
+    assert!(loaded == track);
+    assert_eq!(loaded.duration, track.duration);
+    assert_eq!(loaded.year, track.year);
+    assert_eq!(loaded.genre, track.genre);
+    assert_eq!(loaded.disc_number, track.disc_number);
+    assert_eq!(loaded.disc_total, track.disc_total);
+    assert_eq!(loaded.album_artist, track.album_artist);
+    assert_eq!(loaded.composer, track.composer);
+    assert_eq!(loaded.bitrate, track.bitrate);
+    assert_eq!(loaded.sample_rate, track.sample_rate);
+    assert_eq!(loaded.channels, track.channels);
+
+    // Tracks saved before any of this was known still load
+    let old = korama::Track::load(String::from(
+        "title=Title\u{1f}artist=Artist\u{1f}album=Album\u{1f}track_number=1\u{1f}path=/music/track.flac\u{1e}",
+    )).unwrap();
+    assert!(old == track);
+    assert_eq!(old.duration, None);
+    assert!(old.year.is_empty());
+    assert_eq!(old.channels, None);
+}
+
+#[test]
+fn scan_twice_does_not_duplicate_tracks() {
+    let mut library = set_up_test_library();
+
+    library.scan().unwrap();
+    library.scan().unwrap();
+
+    check_tracks_in_library_by_artist_and_album(&library);
+}
+
+#[test]
+fn scan_with_progress() {
+    let mut library = set_up_test_library();
+    library.set_scan_threads(3);
+
+    let mut updates = Vec::new();
+    library.scan_with_progress(|progress| updates.push(progress.clone())).unwrap();
+
+    check_tracks_in_library_by_artist_and_album(&library);
+
+    // Every file is seen, then every file is processed
+    let last = updates.last().unwrap();
+    assert!(last.files_seen > 0);
+    assert_eq!(last.files_processed, last.files_seen);
+    assert_eq!(updates.len(), last.files_seen * 2);
+    for (before, after) in updates.iter().zip(updates.iter().skip(1)) {
+        assert!(after.files_seen >= before.files_seen);
+        assert!(after.files_processed >= before.files_processed);
+    }
+}
+
+#[test]
+fn saved_library_does_not_depend_on_scan_threads() {
+    let mut saved_library_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    saved_library_path.push("resources/test/library/saved_libraries");
+    let saved_library_path = saved_library_path.to_str().unwrap().to_string();
+    let saved_file = format!("{}/{}", &saved_library_path, String::from("Threads library.lib"));
+
+    let mut saved = Vec::new();
+    for threads in vec!(1, 8) {
+        let mut library = korama::MusicLibrary::new(String::from("Threads library"), get_full_track_path(String::new()));
+        library.set_scan_threads(threads);
+        library.scan().unwrap();
+        library.save(saved_library_path.clone()).unwrap();
+        saved.push(read(&saved_file).unwrap());
+    }
+    remove_file(&saved_file).unwrap();
+    remove_file(format!("{}.bak", &saved_file)).unwrap();
+
+    assert!(saved[0] == saved[1], "Saved libraries differ with different thread counts.");
+}
+
+#[test]
+fn rescan_library() {
+    let mut library_path = std::env::temp_dir();
+    library_path.push(format!("korama_rescan_{}", std::process::id()));
+    create_dir_all(&library_path).unwrap();
+    let library_dir = library_path.to_str().unwrap().to_string();
+    let in_library = |name: &str| format!("{}/{}", &library_dir, name);
+
+    copy(get_format_track_path(String::from("flac_track.flac")), in_library("flac_track.flac")).unwrap();
+    copy(get_format_track_path(String::from("wav_track.wav")), in_library("wav_track.wav")).unwrap();
+
+    let mut library = korama::MusicLibrary::new(String::from("Rescan library"), library_dir.clone());
+    let mut report = library.rescan().unwrap();
+    report.added.sort();
+    assert_eq!(report, korama::ScanReport {
+        added: vec!(in_library("flac_track.flac"), in_library("wav_track.wav")),
+        ..Default::default()
+    });
+
+    // The file details are saved, so nothing needs reading again after loading
+    library.save(library_dir.clone()).unwrap();
+    let mut library = korama::MusicLibrary::load(library_dir.clone(), String::from("Rescan library")).unwrap();
+    assert_eq!(library.rescan().unwrap(), korama::ScanReport::default());
+
+    remove_file(in_library("flac_track.flac")).unwrap();
+    copy(get_format_track_path(String::from("vorbis_track.ogg")), in_library("wav_track.wav")).unwrap();
+    copy(get_format_track_path(String::from("opus_track.opus")), in_library("opus_track.opus")).unwrap();
+    write(in_library("broken.flac"), "fLaC but nothing more").unwrap();
+
+    let report = library.rescan().unwrap();
+    remove_dir_all(&library_path).unwrap();
+
+    assert_eq!(report, korama::ScanReport {
+        added: vec!(in_library("opus_track.opus")),
+        updated: vec!(in_library("wav_track.wav")),
+        removed: vec!(in_library("flac_track.flac")),
+        failed: vec!(in_library("broken.flac")),
+    });
+    let titles: Vec<String> = library.get_tracks_by_title().into_iter()
+        .map(|track| track.track_name)
+        .collect();
+    assert_eq!(titles, vec!(String::from("Opus title"), String::from("Vorbis title")));
+}
+
+#[test]
+fn rescan_keeps_path_order() {
+    let mut library_path = std::env::temp_dir();
+    library_path.push(format!("korama_rescan_order_{}", std::process::id()));
+    create_dir_all(&library_path).unwrap();
+    let library_dir = library_path.to_str().unwrap().to_string();
+    let in_library = |name: &str| format!("{}/{}", &library_dir, name);
+
+    copy(get_format_track_path(String::from("flac_track.flac")), in_library("a.flac")).unwrap();
+    copy(get_format_track_path(String::from("wav_track.wav")), in_library("b.wav")).unwrap();
+    write(in_library("notes.txt"), "Not audio").unwrap();
+
+    let mut library = korama::MusicLibrary::new(String::from("Rescan order library"), library_dir.clone());
+    library.scan().unwrap();
+    // The first track changes, and is read again
+    copy(get_format_track_path(String::from("vorbis_track.ogg")), in_library("a.flac")).unwrap();
+    let report = library.rescan().unwrap();
+    assert_eq!(report.updated, vec!(in_library("a.flac")));
+    let paths: Vec<String> = library.get_tracks().into_iter().map(|track| track.path).collect();
+    assert_eq!(paths, vec!(in_library("a.flac"), in_library("b.wav")));
+
+    // Files which aren't audio are remembered too, so they aren't looked at
+    // again until they change. This one becomes audio without seeming to.
+    let modified = metadata(in_library("notes.txt")).unwrap().modified().unwrap();
+    let audio = read(get_format_track_path(String::from("flac_track.flac"))).unwrap();
+    write(in_library("notes.txt"), &audio[..9]).unwrap();
+    File::options().write(true).open(in_library("notes.txt")).unwrap().set_modified(modified).unwrap();
+    let report = library.rescan().unwrap();
+    remove_dir_all(&library_path).unwrap();
+    assert_eq!(report, korama::ScanReport::default());
+}
+
+#[test]
+fn rescan_skips_unchanged_failed_files() {
+    let mut library_path = std::env::temp_dir();
+    library_path.push(format!("korama_rescan_failed_{}", std::process::id()));
+    create_dir_all(&library_path).unwrap();
+    let library_dir = library_path.to_str().unwrap().to_string();
+    let in_library = |name: &str| format!("{}/{}", &library_dir, name);
+
+    write(in_library("broken.flac"), "fLaC but nothing more").unwrap();
+
+    let mut library = korama::MusicLibrary::new(String::from("Failed files library"), library_dir.clone());
+    assert_eq!(library.rescan().unwrap().failed, vec!(in_library("broken.flac")));
+    // Not read again until it changes, even after loading
+    assert_eq!(library.rescan().unwrap(), korama::ScanReport::default());
+    library.save(library_dir.clone()).unwrap();
+    let mut library = korama::MusicLibrary::load(library_dir.clone(), String::from("Failed files library")).unwrap();
+    assert_eq!(library.rescan().unwrap(), korama::ScanReport::default());
+    assert_eq!(library.get_diagnostics().len(), 1);
+
+    write(in_library("broken.flac"), "fLaC and still nothing more").unwrap();
+    let report = library.rescan().unwrap();
+    remove_dir_all(&library_path).unwrap();
+    assert_eq!(report.failed, vec!(in_library("broken.flac")));
+}
+
+#[test]
+fn scan_diagnostics() {
+    let mut library_path = std::env::temp_dir();
+    library_path.push(format!("korama_diagnostics_{}", std::process::id()));
+    create_dir_all(&library_path).unwrap();
+    let library_dir = library_path.to_str().unwrap().to_string();
+    let in_library = |name: &str| format!("{}/{}", &library_dir, name);
+
+    copy(get_format_track_path(String::from("flac_track.flac")), in_library("flac_track.flac")).unwrap();
+    write(in_library("broken.flac"), "fLaC but nothing more").unwrap();
+    write(in_library("notes.txt"), "Not audio, so not a problem").unwrap();
+    write(in_library("untagged.wav"), get_untagged_wav()).unwrap();
+
+    let mut library = korama::MusicLibrary::new(String::from("Diagnostics library"), library_dir.clone());
+    let diagnostics = library.scan().unwrap();
+
+    assert_eq!(library.get_tracks_by_title().len(), 1);
+    assert_eq!(diagnostics.len(), 2);
+    match diagnostics.get(&in_library("broken.flac")).map(|diagnostic| diagnostic.reason) {
+        Some(korama::SkipReason::BadTags(_)) => (),
+        _ => panic!("Expected bad tags for broken.flac."),
+    };
+    assert_eq!(diagnostics.get_missing_tag(korama::RequiredTag::Title), vec!(korama::ScanDiagnostic {
+        path: in_library("untagged.wav"),
+        reason: korama::SkipReason::MissingTag(korama::RequiredTag::Title),
+    }));
+
+    // Diagnostics are kept with the library
+    library.save(library_dir.clone()).unwrap();
+    let mut library = korama::MusicLibrary::load(library_dir.clone(), String::from("Diagnostics library")).unwrap();
+    assert!(library.get_diagnostics() == diagnostics);
+
+    // Until the files are dealt with
+    remove_file(in_library("broken.flac")).unwrap();
+    library.rescan().unwrap();
+    remove_dir_all(&library_path).unwrap();
+
+    assert_eq!(library.get_diagnostics().get_all(), vec!(korama::ScanDiagnostic {
+        path: in_library("untagged.wav"),
+        reason: korama::SkipReason::MissingTag(korama::RequiredTag::Title),
+    }));
+}
+
+#[test]
+fn infer_missing_tags_from_path() {
+    let mut library_path = std::env::temp_dir();
+    library_path.push(format!("korama_infer_{}", std::process::id()));
+    let library_dir = library_path.to_str().unwrap().to_string();
+    let in_library = |name: &str| format!("{}/{}", &library_dir, name);
+    create_dir_all(in_library("Some Artist/Some Album")).unwrap();
+
+    write(in_library("Some Artist/Some Album/04 - Some Song - Live.wav"), get_untagged_wav()).unwrap();
+    write(in_library("untagged.wav"), get_untagged_wav()).unwrap();
+    copy(get_format_track_path(String::from("flac_track.flac")), in_library("Some Artist/Some Album/01 - Flac.flac")).unwrap();
+
+    let mut library = korama::MusicLibrary::new(String::from("Inferred library"), library_dir.clone());
+    library.set_path_patterns(vec!(
+        korama::PathPattern::new("{artist}/{album}/{track_number} - {title}.{ext}").unwrap(),
+    ));
+    let diagnostics = library.scan().unwrap();
+
+    // Files outside the pattern still need attention
+    assert_eq!(diagnostics.get_all().len(), 1);
+    assert!(diagnostics.get(&in_library("untagged.wav")).is_some());
+
+    let expected = vec!(
+        korama::Track {
+            track_name: String::from("Flac title"),
+            artist: String::from("Flac artist"),
+            album: String::from("Flac album"),
+            track_number: String::from("3"),
+            path: in_library("Some Artist/Some Album/01 - Flac.flac"),
+            ..Default::default()
+        },
+        korama::Track {
+            track_name: String::from("Some Song - Live"),
+            artist: String::from("Some Artist"),
+            album: String::from("Some Album"),
+            track_number: String::from("04"),
+            path: in_library("Some Artist/Some Album/04 - Some Song - Live.wav"),
+            ..Default::default()
+        },
+    );
+    let result = library.get_tracks_by_artist_and_album();
+    assert!(result == expected,
+            "Results not as expected.\nResults were:\n{}\nExpected:\n{}",
+            generate_track_output(result),
+            generate_track_output(expected),
+            );
+    // Tags always win over the path
+    assert!(library.get_tracks_by_artist_and_album()[0].inferred.is_empty());
+
+    // Which fields were inferred is saved with the library, as are the patterns
+    library.save(library_dir.clone()).unwrap();
+    let library = korama::MusicLibrary::load(library_dir.clone(), String::from("Inferred library")).unwrap();
+    remove_dir_all(&library_path).unwrap();
+
+    let inferred_track = &library.get_tracks_by_artist_and_album()[1];
+    assert_eq!(inferred_track.inferred, vec!(
+        korama::TrackField::TrackName,
+        korama::TrackField::Artist,
+        korama::TrackField::Album,
+        korama::TrackField::TrackNumber,
+    ));
+    assert!(inferred_track.is_inferred(korama::TrackField::Artist));
+    assert_eq!(library.get_path_patterns()[0].get_pattern(), "{artist}/{album}/{track_number} - {title}.{ext}");
+}
+
+#[test]
+fn invalid_path_patterns() {
+    for pattern in vec!("{artist}{title}", "{artist}/{unknown}", "{artist", "artist}", "{artist}//{title}") {
+        match korama::PathPattern::new(pattern) {
+            Err(korama::Error::InvalidPathPattern { .. }) => (),
+            _ => panic!("Expected {} to be an invalid pattern.", pattern),
+        };
+    }
+}
+
+#[test]
+fn scan_mp4_with_overflowing_atom() {
+    let mut library_path = std::env::temp_dir();
+    library_path.push(format!("korama_mp4_overflow_{}", std::process::id()));
+    create_dir_all(&library_path).unwrap();
+    let library_dir = library_path.to_str().unwrap().to_string();
+    let file = format!("{}/{}", &library_dir, "overflow.m4a");
+
+    // A 64 bit atom length running past the end of what can be addressed
+    let mut data = Vec::new();
+    data.extend_from_slice(&16u32.to_be_bytes());
+    data.extend_from_slice(b"ftypM4A \0\0\0\0");
+    data.extend_from_slice(&1u32.to_be_bytes());
+    data.extend_from_slice(b"free");
+    data.extend_from_slice(&u64::MAX.to_be_bytes());
+    write(&file, data).unwrap();
+
+    let mut library = korama::MusicLibrary::new(String::from("Overflow library"), library_dir.clone());
+    let diagnostics = library.scan().unwrap();
+    remove_dir_all(&library_path).unwrap();
+
+    match diagnostics.get(&file).map(|diagnostic| diagnostic.reason) {
+        Some(korama::SkipReason::BadTags(_)) => (),
+        _ => panic!("Expected bad tags for the file."),
+    };
+}
+
+#[test]
+#[cfg(unix)]
+fn scan_path_that_is_not_utf8() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut library_path = std::env::temp_dir();
+    library_path.push(format!("korama_not_utf8_{}", std::process::id()));
+    create_dir_all(&library_path).unwrap();
+    let library_dir = library_path.to_str().unwrap().to_string();
+    copy(get_format_track_path(String::from("flac_track.flac")), library_path.join("flac_track.flac")).unwrap();
+    let bad_path = library_path.join(std::ffi::OsStr::from_bytes(b"bad \xff name.flac"));
+    copy(get_format_track_path(String::from("flac_track.flac")), &bad_path).unwrap();
+
+    // The rest of the library is still scanned, with the file reported
+    let mut library = korama::MusicLibrary::new(String::from("Not UTF-8 library"), library_dir.clone());
+    let diagnostics = library.scan().unwrap();
+    let report = library.rescan().unwrap();
+    remove_dir_all(&library_path).unwrap();
+
+    assert_eq!(library.get_tracks_by_title().len(), 1);
+    match diagnostics.get(&bad_path.to_string_lossy()).map(|diagnostic| diagnostic.reason) {
+        Some(korama::SkipReason::Unreadable(_)) => (),
+        _ => panic!("Expected the file to be reported as unreadable."),
+    };
+    // It hasn't changed, so it isn't read and reported again
+    assert_eq!(report, korama::ScanReport::default());
+    assert!(library.get_diagnostics().get(&bad_path.to_string_lossy()).is_some());
+}
+
+fn get_untagged_wav() -> Vec<u8> {
+    // A wav file with no tags at all
+    let mut untagged = Vec::new();
+    untagged.extend_from_slice(b"RIFF");
+    untagged.extend_from_slice(&36u32.to_le_bytes());
+    untagged.extend_from_slice(b"WAVEfmt ");
+    untagged.extend_from_slice(&16u32.to_le_bytes());
+    untagged.extend_from_slice(&[1, 0, 1, 0]);
+    untagged.extend_from_slice(&8000u32.to_le_bytes());
+    untagged.extend_from_slice(&16000u32.to_le_bytes());
+    untagged.extend_from_slice(&[2, 0, 16, 0]);
+    untagged.extend_from_slice(b"data");
+    untagged.extend_from_slice(&0u32.to_le_bytes());
+    untagged
+}
+
 fn generate_track_output(tracks: Vec<korama::Track>) -> String {
     let mut output = String::from("");
     output.push_str("Found ");
@@ -199,6 +984,13 @@ fn get_full_track_path(rel_path: String) -> String {
     file_path.to_str().unwrap().to_string()
 }
 
+fn get_format_track_path(rel_path: String) -> String {
+    let mut file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("resources/test/formats");
+    file_path.push(rel_path);
+    file_path.to_str().unwrap().to_string()
+}
+
 fn set_up_test_library() -> korama::MusicLibrary {
     let mut test_library_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     test_library_path.push("resources/test/library");
@@ -1,4 +1,4 @@
-use std::fs::remove_file;
+use std::fs::{read_to_string, remove_file, write};
 use std::path::PathBuf;
 use korama;
 use korama::Saveable;
@@ -48,10 +48,10 @@ fn test_save_and_load_playlist() {
         playlist.add_track(example_tracks[2].clone());
         // Ensure we have the expected contents before we save
         check_example_tracks_in_playlist(&mut playlist);
-        playlist.save(saved_playlist_path.clone());
+        playlist.save(saved_playlist_path.clone()).unwrap();
     }
 
-    let mut playlist = korama::Playlist::load(saved_playlist_path.clone(), String::from("Test playlist"));
+    let mut playlist = korama::Playlist::load(saved_playlist_path.clone(), String::from("Test playlist")).unwrap();
     playlist.reset_position();
     check_example_tracks_in_playlist(&mut playlist);
     remove_file(format!("{}/{}", &saved_playlist_path, String::from("Test playlist.playlist"))).unwrap();
@@ -73,16 +73,34 @@ fn test_save_and_load_not_started_playlist() {
         playlist.add_track(example_tracks[1].clone());
         playlist.add_track(example_tracks[2].clone());
 
-        playlist.save(saved_playlist_path.clone());
+        playlist.save(saved_playlist_path.clone()).unwrap();
     }
 
-    let mut playlist = korama::Playlist::load(saved_playlist_path.clone(), String::from("Test playlist ns"));
+    let mut playlist = korama::Playlist::load(saved_playlist_path.clone(), String::from("Test playlist ns")).unwrap();
 
     check_example_tracks_in_playlist(&mut playlist);
 
     remove_file(format!("{}/{}", &saved_playlist_path, String::from("Test playlist ns.playlist"))).unwrap();
 }
 
+#[test]
+fn load_playlist_with_bad_position() {
+    let mut saved_playlist_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    saved_playlist_path.push("resources/test/playlist/saved_playlists");
+    let saved_playlist_path = saved_playlist_path.to_str().unwrap().to_string();
+    let corrupt_file = format!("{}/{}", &saved_playlist_path, String::from("Test playlist bad.playlist"));
+
+    write(&corrupt_file, "Test playlist bad\u{1f}not a number\u{1d}").unwrap();
+
+    let result = korama::Playlist::load(saved_playlist_path.clone(), String::from("Test playlist bad"));
+    remove_file(&corrupt_file).unwrap();
+
+    match result {
+        Err(korama::Error::CorruptSaveFile { offset, .. }) => assert_eq!(offset, 18),
+        _ => panic!("Expected a corrupt save file error."),
+    };
+}
+
 #[test]
 fn reset_playlist() {
     let mut playlist = korama::Playlist::new(String::from("Test playlist"));
@@ -228,6 +246,219 @@ fn step_back_through_playlist() {
     };
 }
 
+#[test]
+fn repeat_all_playlist() {
+    let mut playlist = get_numbered_playlist("Test playlist", 3);
+    playlist.set_play_mode(korama::PlayMode::RepeatAll);
+
+    let numbers: Vec<String> = playlist.by_ref().take(7).map(|track| track.track_name).collect();
+    assert_eq!(numbers, vec!("0", "1", "2", "0", "1", "2", "0"));
+    // Nothing was picked to carry on with
+    assert_eq!(playlist.get_tracks().len(), 3);
+}
+
+#[test]
+fn repeat_one_playlist() {
+    let mut playlist = get_numbered_playlist("Test playlist", 3);
+    playlist.next();
+    playlist.next();
+    playlist.set_play_mode(korama::PlayMode::RepeatOne);
+
+    let numbers: Vec<String> = playlist.by_ref().take(3).map(|track| track.track_name).collect();
+    assert_eq!(numbers, vec!("1", "1", "1"));
+
+    playlist.set_play_mode(korama::PlayMode::Sequential);
+    assert_eq!(playlist.next().unwrap().track_name, "2");
+}
+
+#[test]
+fn shuffle_playlist() {
+    let mut playlist = get_numbered_playlist("Test playlist", 10);
+    playlist.set_play_mode(korama::PlayMode::Shuffle);
+
+    let mut cycles = Vec::new();
+    let mut last: Option<String> = None;
+    for _ in 0..20 {
+        let cycle: Vec<String> = playlist.by_ref().take(10).map(|track| track.track_name).collect();
+        // Every track once a time round, and none twice in a row when
+        // starting the next time round
+        let mut sorted: Vec<usize> = cycle.iter().map(|number| number.parse().unwrap()).collect();
+        sorted.sort();
+        assert_eq!(sorted, (0..10).collect::<Vec<usize>>());
+        assert!(last.as_ref() != cycle.first(), "{} was played twice in a row", cycle[0]);
+        last = cycle.last().cloned();
+        cycles.push(cycle);
+    }
+    cycles.dedup();
+    assert!(cycles.len() > 1, "The order was the same every time");
+}
+
+#[test]
+fn album_shuffle_playlist() {
+    // Tracks from three albums mixed together, and one without an album
+    let mut playlist = korama::Playlist::new(String::from("Test playlist"));
+    for number in 0..4 {
+        for album in &["First", "Second", "Third"] {
+            playlist.add_track(korama::Track {
+                track_name: format!("{} {}", album, number),
+                artist: String::from("Artist"),
+                album: album.to_string(),
+                path: format!("/{}/{}", album, number),
+                ..Default::default()
+            });
+        }
+    }
+    playlist.add_track(korama::Track {
+        track_name: String::from("Single"),
+        path: String::from("/single"),
+        ..Default::default()
+    });
+    playlist.set_play_mode(korama::PlayMode::AlbumShuffle);
+
+    for _ in 0..10 {
+        let mut cycle: Vec<korama::Track> = playlist.by_ref().take(13).collect();
+        let single = cycle.iter().position(|track| track.track_name == "Single").unwrap();
+        cycle.remove(single);
+        assert!(single % 4 == 0, "The single track was played in the middle of an album");
+        for album in cycle.chunks(4) {
+            let names: Vec<String> = album.iter().map(|track| track.track_name.clone()).collect();
+            let expected: Vec<String> = (0..4).map(|number| format!("{} {}", album[0].album, number)).collect();
+            assert_eq!(names, expected);
+        }
+    }
+}
+
+#[test]
+fn shuffle_carries_on_from_current_track() {
+    let mut playlist = get_numbered_playlist("Test playlist", 10);
+    playlist.next();
+    playlist.next();
+    playlist.set_play_mode(korama::PlayMode::Shuffle);
+
+    // The rest of the time round is every other track
+    let rest: Vec<String> = playlist.by_ref().take(9).map(|track| track.track_name).collect();
+    let mut sorted = rest.clone();
+    sorted.sort();
+    assert_eq!(sorted, vec!("0", "2", "3", "4", "5", "6", "7", "8", "9"));
+
+    // Stepping back follows the shuffled order
+    assert_eq!(playlist.prev().unwrap().track_name, rest[7]);
+    assert_eq!(playlist.prev().unwrap().track_name, rest[6]);
+}
+
+#[test]
+fn add_and_remove_tracks_while_shuffling() {
+    let mut playlist = get_numbered_playlist("Test playlist", 5);
+    playlist.set_play_mode(korama::PlayMode::Shuffle);
+    let played = vec!(playlist.next().unwrap(), playlist.next().unwrap());
+
+    let removed = playlist.get_tracks().iter().position(|track| track == &played[0]).unwrap();
+    playlist.remove_track(removed);
+    playlist.add_track(korama::Track {
+        track_name: String::from("Added"),
+        path: String::from("/added"),
+        ..Default::default()
+    });
+
+    // The rest of the tracks, with the new one last
+    let mut rest: Vec<String> = playlist.by_ref().take(3).map(|track| track.track_name).collect();
+    assert_eq!(playlist.next().unwrap().track_name, "Added");
+    rest.push(played[1].track_name.clone());
+    rest.sort();
+    let mut expected: Vec<String> = (0..5).map(|number| number.to_string()).collect();
+    expected.retain(|number| *number != played[0].track_name);
+    assert_eq!(rest, expected);
+}
+
+#[test]
+fn remove_tracks_around_current_track() {
+    let modes = vec!(
+        korama::PlayMode::Sequential,
+        korama::PlayMode::RepeatAll,
+        korama::PlayMode::RepeatOne,
+        korama::PlayMode::Shuffle,
+        korama::PlayMode::AlbumShuffle,
+    );
+    for mode in modes {
+        for removing in &["before", "at", "after"] {
+            // Three tracks in, however they're being played. Repeating one
+            // track from the start would never get that far.
+            let mut playlist = get_numbered_playlist("Test playlist", 6);
+            if mode != korama::PlayMode::RepeatOne {
+                playlist.set_play_mode(mode);
+            };
+            let played: Vec<korama::Track> = playlist.by_ref().take(3).collect();
+            if mode == korama::PlayMode::RepeatOne {
+                playlist.set_play_mode(mode);
+            };
+            let upcoming: Vec<korama::Track> = playlist.clone().take(2).collect();
+            // Repeating one track, the one after is only after it in the list
+            let after = if mode == korama::PlayMode::RepeatOne { playlist.get_tracks()[3].clone() } else { upcoming[0].clone() };
+
+            let removed = match *removing {
+                "before" => &played[1],
+                "at" => &played[2],
+                _ => &after,
+            };
+            let index = playlist.get_tracks().iter().position(|track| track == removed).unwrap();
+            playlist.remove_track(index);
+
+            let expected = match (mode, *removing) {
+                (korama::PlayMode::RepeatOne, "at") => String::from("3"),
+                (korama::PlayMode::RepeatOne, _) => String::from("2"),
+                (_, "after") => upcoming[1].track_name.clone(),
+                _ => upcoming[0].track_name.clone(),
+            };
+            assert_eq!(playlist.next().unwrap().track_name, expected, "Removing the track {} the current one in {:?}", removing, mode);
+        }
+    }
+}
+
+#[test]
+fn save_and_load_shuffled_playlist() {
+    let mut saved_playlist_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    saved_playlist_path.push("resources/test/playlist/saved_playlists");
+    let saved_playlist_path = saved_playlist_path.to_str().unwrap().to_string();
+
+    let mut playlist = get_numbered_playlist("Test playlist shuffled", 10);
+    playlist.set_play_mode(korama::PlayMode::Shuffle);
+    for _ in 0..4 {
+        playlist.next();
+    }
+    playlist.save(saved_playlist_path.clone()).unwrap();
+
+    let mut loaded = korama::Playlist::load(saved_playlist_path.clone(), String::from("Test playlist shuffled")).unwrap();
+    remove_file(format!("{}/{}", &saved_playlist_path, String::from("Test playlist shuffled.playlist"))).unwrap();
+    assert_eq!(loaded.get_play_mode(), korama::PlayMode::Shuffle);
+    let expected: Vec<korama::Track> = playlist.take(6).collect();
+    let rest: Vec<korama::Track> = loaded.by_ref().take(6).collect();
+    assert!(rest == expected, "The loaded playlist carried on in a different order");
+}
+
+#[test]
+fn load_playlist_with_bad_order() {
+    let mut saved_playlist_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    saved_playlist_path.push("resources/test/playlist/saved_playlists");
+    let saved_playlist_path = saved_playlist_path.to_str().unwrap().to_string();
+    let corrupt_file = format!("{}/{}", &saved_playlist_path, String::from("Test playlist bad order.playlist"));
+
+    let mut playlist = get_numbered_playlist("Test playlist bad order", 3);
+    playlist.set_play_mode(korama::PlayMode::Shuffle);
+    playlist.save(saved_playlist_path.clone()).unwrap();
+    // The same track twice, and another not at all
+    let saved = read_to_string(&corrupt_file).unwrap();
+    let order = saved.find("order=").unwrap() + "order=".len();
+    write(&corrupt_file, format!("{}0,0,1{}", &saved[..order], &saved[order + "0,1,2".len()..])).unwrap();
+
+    let result = korama::Playlist::load(saved_playlist_path.clone(), String::from("Test playlist bad order"));
+    remove_file(&corrupt_file).unwrap();
+
+    match result {
+        Err(korama::Error::CorruptSaveFile { offset, .. }) => assert_eq!(offset, order),
+        _ => panic!("Expected a corrupt save file error."),
+    };
+}
+
 fn check_example_tracks_in_playlist(playlist: &mut korama::Playlist) {
     let example_tracks = get_example_tracks();
 
@@ -263,6 +494,7 @@ fn get_example_tracks() -> Vec<korama::Track> {
         album: String::from("Example album"),
         track_number: String::from(""),
         path: String::from("/some/path"),
+        ..Default::default()
     };
     let example_track_2 = korama::Track {
         track_name: String::from("Second track"),
@@ -270,6 +502,7 @@ fn get_example_tracks() -> Vec<korama::Track> {
         album: String::from("Extrample album"),
         track_number: String::from("4"),
         path: String::from("/some/other/path"),
+        ..Default::default()
     };
     let example_track_3 = korama::Track {
         track_name: String::from("Third track"),
@@ -277,7 +510,21 @@ fn get_example_tracks() -> Vec<korama::Track> {
         album: String::from("Nextrample album"),
         track_number: String::from("4.2"),
         path: String::from("/some/other/path/again"),
+        ..Default::default()
     };
 
     vec!(example_track_1, example_track_2, example_track_3)
 }
+
+fn get_numbered_playlist(name: &str, track_count: usize) -> korama::Playlist {
+    let mut playlist = korama::Playlist::new(String::from(name));
+    for number in 0..track_count {
+        playlist.add_track(korama::Track {
+            track_name: number.to_string(),
+            artist: format!("Artist {}", number),
+            path: format!("/{}", number),
+            ..Default::default()
+        });
+    }
+    playlist
+}
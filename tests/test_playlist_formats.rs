@@ -0,0 +1,343 @@
+use std::fs::{create_dir_all, read_to_string, remove_dir_all, write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use korama;
+use korama::Saveable;
+
+#[test]
+fn import_m3u() {
+    let dir = set_up_dir("import_m3u");
+    let library = set_up_formats_library();
+    let m3u_path = dir.join("Imported.m3u8");
+    write(&m3u_path, format!(
+        concat!(
+            "#EXTM3U\n",
+            "#EXTINF:3,Flac artist - Flac title\n",
+            "{flac}\n",
+            "\n",
+            "# Found by its URL\n",
+            "file://{wav}\n",
+            "#EXTINF:2,vorbis ARTIST - Vorbis title\n",
+            "/somewhere/else/vorbis_track.ogg\n",
+            "#EXTINF:125.5,Unknown - Not in library\n",
+            "relative/./song.mp3\n",
+            "http://radio.example/stream\n",
+        ),
+        flac = get_format_track_path("flac_track.flac"),
+        wav = get_format_track_path("wav_track.wav"),
+    )).unwrap();
+
+    let playlist = korama::Playlist::import_m3u(&m3u_path, &library).unwrap();
+    let tracks = playlist.get_tracks();
+    remove_dir_all(&dir).unwrap();
+
+    assert_eq!(playlist.get_name(), "Imported");
+    assert_eq!(tracks.len(), 5);
+    assert!(tracks[0] == library.get_track(&get_format_track_path("flac_track.flac")).unwrap());
+    assert!(tracks[1] == library.get_track(&get_format_track_path("wav_track.wav")).unwrap());
+    // Matched by artist and title, as the path is different
+    assert!(tracks[2] == library.get_track(&get_format_track_path("vorbis_track.ogg")).unwrap());
+
+    assert_eq!(tracks[3].path, dir.join("relative/song.mp3").to_str().unwrap());
+    assert_eq!(tracks[3].artist, "Unknown");
+    assert_eq!(tracks[3].track_name, "Not in library");
+    assert_eq!(tracks[3].duration, Some(Duration::from_millis(125500)));
+
+    assert_eq!(tracks[4].path, "http://radio.example/stream");
+    assert_eq!(tracks[4].track_name, "");
+}
+
+#[test]
+fn import_latin_1_m3u() {
+    let dir = set_up_dir("latin_1_m3u");
+    let m3u_path = dir.join("Old.m3u");
+    write(&m3u_path, b"#EXTINF:-1,Caf\xe9\n/music/caf\xe9.mp3\n".to_vec()).unwrap();
+
+    let playlist = korama::Playlist::import_m3u(&m3u_path, &set_up_formats_library()).unwrap();
+    remove_dir_all(&dir).unwrap();
+
+    let tracks = playlist.get_tracks();
+    assert_eq!(tracks.len(), 1);
+    assert_eq!(tracks[0].track_name, "Caf\u{e9}");
+    assert_eq!(tracks[0].artist, "");
+    assert_eq!(tracks[0].path, "/music/caf\u{e9}.mp3");
+    assert_eq!(tracks[0].duration, None);
+}
+
+#[test]
+fn export_m3u() {
+    let dir = set_up_dir("export_m3u");
+    let library = set_up_formats_library();
+    let mut playlist = korama::Playlist::new(String::from("Exported"));
+    playlist.add_track(library.get_track(&get_format_track_path("flac_track.flac")).unwrap());
+    playlist.add_track(korama::Track{
+        track_name: String::from("No artist"),
+        path: String::from("/music/no_artist.mp3"),
+        ..Default::default()
+    });
+
+    let formats_dir = PathBuf::from(get_format_track_path(""));
+    let m3u_path = dir.join("Exported.m3u8");
+    playlist.export_m3u(&m3u_path, Some(&formats_dir)).unwrap();
+    let relative_to_formats = read_to_string(&m3u_path).unwrap();
+    playlist.export_m3u(&m3u_path, Some(&formats_dir.join("other"))).unwrap();
+    let relative_to_other = read_to_string(&m3u_path).unwrap();
+    playlist.export_m3u(&m3u_path, None).unwrap();
+    let absolute = read_to_string(&m3u_path).unwrap();
+    let imported = korama::Playlist::import_m3u(&m3u_path, &library).unwrap();
+    remove_dir_all(&dir).unwrap();
+
+    assert_eq!(relative_to_formats, format!(
+        "#EXTM3U\n#EXTINF:3,Flac artist - Flac title\nflac_track.flac\n#EXTINF:-1,No artist\n{}\n",
+        relative_path(&formats_dir, "/music/no_artist.mp3"),
+    ));
+    assert!(relative_to_other.contains("\n../flac_track.flac\n"));
+    assert!(absolute.contains(&format!("\n{}\n", get_format_track_path("flac_track.flac"))));
+    assert!(imported.get_tracks() == playlist.get_tracks());
+}
+
+#[test]
+fn import_xspf() {
+    let dir = set_up_dir("import_xspf");
+    let library = set_up_formats_library();
+    let xspf_path = dir.join("imported.xspf");
+    write(&xspf_path, format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<!-- Written by hand -->\n",
+            "<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n",
+            "  <title>Imported &amp; matched</title>\n",
+            "  <trackList>\n",
+            "    <track><location>file://{flac}</location></track>\n",
+            "    <track>\n",
+            "      <location>file:///somewhere/else/vorbis_track.ogg</location>\n",
+            "      <creator>Vorbis artist</creator>\n",
+            "      <title><![CDATA[Vorbis title]]></title>\n",
+            "    </track>\n",
+            "    <track>\n",
+            "      <location>relative/not%20in%20library.mp3</location>\n",
+            "      <location>file:///second/choice.mp3</location>\n",
+            "      <title>Not in library</title>\n",
+            "      <creator>Unknown</creator>\n",
+            "      <album>Nowhere</album>\n",
+            "      <duration>125500</duration>\n",
+            "      <extension application=\"http://example.com\"><title>Ignored</title></extension>\n",
+            "    </track>\n",
+            "  </trackList>\n",
+            "</playlist>\n",
+        ),
+        flac = get_format_track_path("flac_track.flac"),
+    )).unwrap();
+
+    let playlist = korama::Playlist::import_xspf(&xspf_path, &library).unwrap();
+    let tracks = playlist.get_tracks();
+    remove_dir_all(&dir).unwrap();
+
+    assert_eq!(playlist.get_name(), "Imported & matched");
+    assert_eq!(tracks.len(), 3);
+    assert!(tracks[0] == library.get_track(&get_format_track_path("flac_track.flac")).unwrap());
+    // Matched by creator and title, as the path is different
+    assert!(tracks[1] == library.get_track(&get_format_track_path("vorbis_track.ogg")).unwrap());
+
+    assert_eq!(tracks[2].path, dir.join("relative/not in library.mp3").to_str().unwrap());
+    assert_eq!(tracks[2].track_name, "Not in library");
+    assert_eq!(tracks[2].artist, "Unknown");
+    assert_eq!(tracks[2].album, "Nowhere");
+    assert_eq!(tracks[2].duration, Some(Duration::from_millis(125500)));
+}
+
+#[test]
+fn import_xspf_without_locations() {
+    let dir = set_up_dir("xspf_without_locations");
+    let library = set_up_formats_library();
+    let xspf_path = dir.join("unlocated.xspf");
+    write(&xspf_path, concat!(
+        "<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n",
+        "  <trackList>\n",
+        "    <track><title>Nowhere</title><creator>Unknown</creator></track>\n",
+        "    <track><location> </location><title>Also nowhere</title></track>\n",
+        "    <track><creator>Vorbis artist</creator><title>Vorbis title</title></track>\n",
+        "  </trackList>\n",
+        "</playlist>\n",
+    )).unwrap();
+
+    let playlist = korama::Playlist::import_xspf(&xspf_path, &library).unwrap();
+    let tracks = playlist.get_tracks();
+    remove_dir_all(&dir).unwrap();
+
+    // Only the track the library has is kept, as the others can't be played
+    assert_eq!(tracks.len(), 1);
+    assert!(tracks[0] == library.get_track(&get_format_track_path("vorbis_track.ogg")).unwrap());
+}
+
+#[test]
+fn import_invalid_xspf() {
+    let dir = set_up_dir("invalid_xspf");
+    let library = set_up_formats_library();
+    let results: Vec<_> = vec!(
+        "<playlist><trackList><track></trackList></playlist>",
+        "<playlist><trackList>",
+        "<html></html>",
+        "<playlist><title>Unfinished",
+    ).into_iter().enumerate().map(|(index, text)| {
+        let xspf_path = dir.join(format!("{}.xspf", index));
+        write(&xspf_path, text).unwrap();
+        korama::Playlist::import_xspf(&xspf_path, &library)
+    }).collect();
+    remove_dir_all(&dir).unwrap();
+
+    for result in results {
+        match result {
+            Err(korama::Error::InvalidPlaylist { .. }) => {},
+            _ => panic!("Expected an invalid playlist error."),
+        };
+    }
+}
+
+#[test]
+fn export_xspf() {
+    let dir = set_up_dir("export_xspf");
+    let library = set_up_formats_library();
+    let mut playlist = korama::Playlist::new(String::from("Rock & <roll>"));
+    playlist.add_track(library.get_track(&get_format_track_path("flac_track.flac")).unwrap());
+    playlist.add_track(korama::Track{
+        track_name: String::from("Space & time"),
+        path: String::from("/music/with space.mp3"),
+        ..Default::default()
+    });
+
+    let formats_dir = PathBuf::from(get_format_track_path(""));
+    let xspf_path = dir.join("Exported.xspf");
+    playlist.export_xspf(&xspf_path, Some(&formats_dir)).unwrap();
+    let relative = read_to_string(&xspf_path).unwrap();
+    let imported_relative = korama::Playlist::import_xspf(&xspf_path, &library).unwrap();
+    playlist.export_xspf(&xspf_path, None).unwrap();
+    let absolute = read_to_string(&xspf_path).unwrap();
+    let imported = korama::Playlist::import_xspf(&xspf_path, &library).unwrap();
+    remove_dir_all(&dir).unwrap();
+
+    assert!(relative.contains("<title>Rock &amp; &lt;roll&gt;</title>"));
+    assert!(relative.contains(concat!(
+        "      <location>flac_track.flac</location>\n",
+        "      <title>Flac title</title>\n",
+        "      <creator>Flac artist</creator>\n",
+    )));
+    assert!(relative.contains("<title>Space &amp; time</title>"));
+    assert!(relative.contains("with%20space.mp3</location>"));
+    assert!(absolute.contains("<location>file:///music/with%20space.mp3</location>"));
+
+    assert_eq!(imported.get_name(), "Rock & <roll>");
+    assert!(imported.get_tracks() == playlist.get_tracks());
+    // Relative to somewhere other than the playlist, so only matched by
+    // artist and title
+    assert!(imported_relative.get_tracks()[0] == playlist.get_tracks()[0]);
+}
+
+#[test]
+fn import_pls() {
+    let dir = set_up_dir("import_pls");
+    let library = set_up_formats_library();
+    let pls_path = dir.join("Imported.pls");
+    write(&pls_path, format!(
+        concat!(
+            "[playlist]\n",
+            "; Numbered out of order\n",
+            "File2=/somewhere/else/vorbis_track.ogg\n",
+            "Title2=Vorbis artist - Vorbis title\n",
+            "Length2=2\n",
+            "File1={flac}\n",
+            "File3=stream.mp3\n",
+            "Title3=Only a title\n",
+            "Length3=-1\n",
+            "Title4=No file\n",
+            "NumberOfEntries=3\n",
+            "Version=2\n",
+        ),
+        flac = get_format_track_path("flac_track.flac"),
+    )).unwrap();
+    let not_pls_path = dir.join("Not.pls");
+    write(&not_pls_path, "File1=/music/track.mp3\n").unwrap();
+
+    let playlist = korama::Playlist::import_pls(&pls_path, &library).unwrap();
+    let not_pls = korama::Playlist::import_pls(&not_pls_path, &library);
+    remove_dir_all(&dir).unwrap();
+
+    let tracks = playlist.get_tracks();
+    assert_eq!(playlist.get_name(), "Imported");
+    assert_eq!(tracks.len(), 3);
+    assert!(tracks[0] == library.get_track(&get_format_track_path("flac_track.flac")).unwrap());
+    assert!(tracks[1] == library.get_track(&get_format_track_path("vorbis_track.ogg")).unwrap());
+    assert_eq!(tracks[2].path, dir.join("stream.mp3").to_str().unwrap());
+    assert_eq!(tracks[2].track_name, "Only a title");
+    assert_eq!(tracks[2].duration, None);
+    match not_pls {
+        Err(korama::Error::InvalidPlaylist { .. }) => {},
+        _ => panic!("Expected an invalid playlist error."),
+    };
+}
+
+#[test]
+fn export_pls() {
+    let dir = set_up_dir("export_pls");
+    let library = set_up_formats_library();
+    let mut playlist = korama::Playlist::new(String::from("Exported"));
+    playlist.add_track(library.get_track(&get_format_track_path("flac_track.flac")).unwrap());
+    playlist.add_track(korama::Track{
+        track_name: String::from("No artist"),
+        path: String::from("/music/no_artist.mp3"),
+        ..Default::default()
+    });
+
+    let formats_dir = PathBuf::from(get_format_track_path(""));
+    let pls_path = dir.join("Exported.pls");
+    playlist.export_pls(&pls_path, Some(&formats_dir)).unwrap();
+    let relative = read_to_string(&pls_path).unwrap();
+    playlist.export_pls(&pls_path, None).unwrap();
+    let imported = korama::Playlist::import_pls(&pls_path, &library).unwrap();
+    remove_dir_all(&dir).unwrap();
+
+    assert_eq!(relative, format!(
+        concat!(
+            "[playlist]\n",
+            "File1=flac_track.flac\nTitle1=Flac artist - Flac title\nLength1=3\n",
+            "File2={}\nTitle2=No artist\nLength2=-1\n",
+            "NumberOfEntries=2\nVersion=2\n",
+        ),
+        relative_path(&formats_dir, "/music/no_artist.mp3"),
+    ));
+    assert!(imported.get_tracks() == playlist.get_tracks());
+}
+
+// Where /music/... ends up relative to the test resources, as they can be
+// anywhere
+fn relative_path(from: &Path, to: &str) -> String {
+    let mut relative = PathBuf::new();
+    for _ in from.components().skip(1) {
+        relative.push("..");
+    }
+    relative.push(&to[1..]);
+    relative.to_str().unwrap().to_string()
+}
+
+fn set_up_dir(name: &str) -> PathBuf {
+    let mut dir = std::env::temp_dir();
+    dir.push(format!("korama_{}_{}", name, std::process::id()));
+    create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn set_up_formats_library() -> korama::MusicLibrary {
+    let mut library = korama::MusicLibrary::new(
+        String::from("Test formats library"),
+        get_format_track_path(""),
+    );
+    library.scan().unwrap();
+    library
+}
+
+fn get_format_track_path(rel_path: &str) -> String {
+    let mut file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("resources/test/formats");
+    file_path.push(rel_path);
+    file_path.to_str().unwrap().to_string()
+}
@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 use korama;
 use korama::Saveable;
+use std::sync::mpsc;
 use std::{thread,time};
 
 
@@ -15,6 +16,17 @@ fn set_queue_playlist() {
     assert_eq!(queue.get_playlist().unwrap().get_name(), String::from("Test playlist for queue"));
 }
 
+#[test]
+fn play_without_playlist() {
+    let mut queue = korama::Queue::with_backend(korama::NullBackend::new());
+
+    match queue.play() {
+        Err(korama::Error::NoPlaylist) => (),
+        _ => panic!("Expected an error with no playlist to play."),
+    };
+    assert!(! queue.is_playing());
+}
+
 #[test]
 fn set_history() {
     let queue = korama::Queue::new();
@@ -35,6 +47,7 @@ fn set_history() {
             album: String::from("It doesn't exist"),
             track_number: String::from("e"),
             path: String::from("/not/real/at/all"),
+            ..Default::default()
         },
     ];
 
@@ -52,17 +65,17 @@ fn set_history() {
 #[test]
 fn play_tracks() {
     let mut library = set_up_test_library(String::from("library"));
-    library.scan();
+    library.scan().unwrap();
 
     let mut playlist = korama::Playlist::new(String::from("Test playlist for queue"));
     for track in library.get_tracks_by_title() {
         playlist.add_track(track.clone());
     };
 
-    let mut queue = korama::Queue::new();
+    let mut queue = korama::Queue::with_backend(korama::NullBackend::new());
     queue.use_playlist(playlist);
 
-    queue.play();
+    queue.play().unwrap();
 
     while queue.is_playing() {
       thread::sleep(time::Duration::from_millis(50));
@@ -77,6 +90,7 @@ fn play_tracks() {
             album: String::from("The Ignored And the Found"),
             track_number: String::from("2.1"),
             path: get_full_track_path(String::from("another_artist/good_album/hidden_track.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("First steps"),
@@ -84,6 +98,7 @@ fn play_tracks() {
             album: String::from("Ignored"),
             track_number: String::from("1"),
             path: get_full_track_path(String::from("artist2/live_cover.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("First steps"),
@@ -91,6 +106,7 @@ fn play_tracks() {
             album: String::from("The Ignored And the Found"),
             track_number: String::from("2"),
             path: get_full_track_path(String::from("another_artist/good_album/another_track.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("Ignored"),
@@ -98,6 +114,7 @@ fn play_tracks() {
             album: String::from("Ignored"),
             track_number: String::from("1"),
             path: get_full_track_path(String::from("ignored.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("Not much to write home about"),
@@ -105,6 +122,7 @@ fn play_tracks() {
             album: String::from("The Greatest Album of Negligible MP3s"),
             track_number: String::from("1"),
             path: get_full_track_path(String::from("artist2/album/ignored.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("Scream into the mic"),
@@ -112,6 +130,7 @@ fn play_tracks() {
             album: String::from("Live Bootleg"),
             track_number: String::from(""),
             path: get_full_track_path(String::from("artist1/test.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("The Second Step"),
@@ -119,6 +138,7 @@ fn play_tracks() {
             album: String::from("The Ignored And the Found"),
             track_number: String::from("1"),
             path: get_full_track_path(String::from("another_artist/good_album/first_track.mp3")),
+            ..Default::default()
         },
     ];
 
@@ -132,17 +152,17 @@ fn play_tracks() {
 #[test]
 fn test_skipping() {
     let mut library = set_up_test_library(String::from("longer"));
-    library.scan();
+    library.scan().unwrap();
 
     let mut playlist = korama::Playlist::new(String::from("Test playlist for queue"));
     for track in library.get_tracks_by_title() {
         playlist.add_track(track.clone());
     };
 
-    let mut queue = korama::Queue::new();
+    let mut queue = korama::Queue::with_backend(korama::NullBackend::new());
     queue.use_playlist(playlist);
 
-    queue.play();
+    queue.play().unwrap();
     queue.skip_forward();
     queue.skip_back();
     queue.skip_forward();
@@ -160,6 +180,7 @@ fn test_skipping() {
             album: String::from("Test"),
             track_number: String::from("1"),
             path: get_longer_track_path(String::from("2.6_second.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("Test2"),
@@ -167,6 +188,7 @@ fn test_skipping() {
             album: String::from("Test2"),
             track_number: String::from("2"),
             path: get_longer_track_path(String::from("2.6_second_2.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("Test"),
@@ -174,6 +196,7 @@ fn test_skipping() {
             album: String::from("Test"),
             track_number: String::from("1"),
             path: get_longer_track_path(String::from("2.6_second.mp3")),
+            ..Default::default()
         },
         korama::Track{
             track_name: String::from("Test2"),
@@ -181,6 +204,7 @@ fn test_skipping() {
             album: String::from("Test2"),
             track_number: String::from("2"),
             path: get_longer_track_path(String::from("2.6_second_2.mp3")),
+            ..Default::default()
         },
     ];
 
@@ -191,6 +215,446 @@ fn test_skipping() {
             );
 }
 
+#[test]
+fn backend_records_playback() {
+    let mut library = set_up_test_library(String::from("longer"));
+    library.scan().unwrap();
+
+    let mut playlist = korama::Playlist::new(String::from("Test playlist for queue"));
+    for track in library.get_tracks_by_title() {
+        playlist.add_track(track.clone());
+    };
+
+    let backend = korama::NullBackend::new();
+    let mut queue = korama::Queue::with_backend(backend.clone());
+    queue.use_playlist(playlist);
+
+    queue.play().unwrap();
+
+    while queue.is_playing() {
+      thread::sleep(time::Duration::from_millis(50));
+    };
+
+    let played = backend.get_played();
+    let result: Vec<korama::Track> = played.iter().map(|played| played.track.clone()).collect();
+
+    assert!(result == queue.get_history(),
+            "Results not as expected.\nResults were:\n{}\nExpected:\n{}",
+            generate_track_output(result),
+            generate_track_output(queue.get_history()),
+            );
+
+    // The second track shouldn't start until the first has finished
+    let gap = played[1].started.duration_since(played[0].started);
+    assert!(gap >= time::Duration::from_millis(2500));
+}
+
+#[test]
+fn pause_and_resume() {
+    let mut queue = set_up_longer_queue(korama::NullBackend::new());
+    let events = queue.subscribe();
+
+    queue.play().unwrap();
+    assert_eq!(next_event(&events), "TrackStarted: Test");
+    queue.pause();
+    assert_eq!(next_event(&events), "Paused");
+
+    assert!(queue.is_paused());
+    assert!(!queue.is_playing());
+
+    // Nothing happens for longer than the track, as it doesn't finish while paused
+    assert!(events.recv_timeout(time::Duration::from_millis(3000)).is_err());
+    assert_eq!(queue.get_history().len(), 1);
+    assert!(queue.is_paused());
+
+    queue.resume();
+    assert_eq!(next_event(&events), "Resumed");
+
+    assert!(queue.is_playing());
+    assert!(!queue.is_paused());
+
+    wait_for_stop(&events);
+
+    assert_eq!(queue.get_history().len(), 2);
+}
+
+#[test]
+fn toggle_pause() {
+    let mut queue = set_up_longer_queue(korama::NullBackend::new());
+    let events = queue.subscribe();
+
+    queue.play().unwrap();
+    assert_eq!(next_event(&events), "TrackStarted: Test");
+    queue.toggle_pause();
+    assert_eq!(next_event(&events), "Paused");
+
+    assert!(queue.is_paused());
+
+    queue.toggle_pause();
+    assert_eq!(next_event(&events), "Resumed");
+
+    assert!(queue.is_playing());
+}
+
+#[test]
+fn pause_when_stopped_does_nothing() {
+    let mut queue = set_up_longer_queue(korama::NullBackend::new());
+
+    // There's no player to pause yet
+    queue.pause();
+
+    assert!(!queue.is_paused());
+    assert!(!queue.is_playing());
+    assert_eq!(queue.get_history().len(), 0);
+}
+
+#[test]
+fn skip_back_from_first_track() {
+    let mut queue = set_up_longer_queue(korama::NullBackend::new());
+    let events = queue.subscribe();
+
+    queue.play().unwrap();
+    assert_eq!(next_event(&events), "TrackStarted: Test");
+    wait_for_position(&events, time::Duration::from_millis(500));
+    queue.skip_back();
+
+    // The first track starts again, and is then followed by the second
+    let received: Vec<String> = wait_for_stop(&events).into_iter()
+        .filter(|event| event != "Progress")
+        .collect();
+    assert_eq!(received, vec!(
+        String::from("TrackFinished"),
+        String::from("TrackStarted: Test2"),
+        String::from("TrackFinished"),
+        String::from("PlaylistExhausted"),
+        String::from("Stopped"),
+    ));
+    let history: Vec<String> = queue.get_history().into_iter()
+        .map(|track| track.track_name)
+        .collect();
+    assert_eq!(history, vec!(String::from("Test"), String::from("Test2")));
+}
+
+#[test]
+fn restart_track() {
+    let backend = korama::NullBackend::new();
+    let mut queue = set_up_longer_queue(backend.clone());
+    let events = queue.subscribe();
+
+    queue.play().unwrap();
+    wait_for_position(&events, time::Duration::from_millis(1000));
+    queue.restart_track();
+
+    wait_for_stop(&events);
+
+    // Restarting shouldn't count as playing another track
+    assert_eq!(queue.get_history().len(), 2);
+
+    let played = backend.get_played();
+    assert_eq!(played.len(), 3);
+    assert!(played[0].track == played[1].track);
+    let gap = played[1].started.duration_since(played[0].started);
+    assert!(gap >= time::Duration::from_millis(1000));
+    assert!(gap < time::Duration::from_millis(2000));
+}
+
+#[test]
+fn position_and_duration() {
+    let mut queue = set_up_longer_queue(korama::NullBackend::new());
+    let events = queue.subscribe();
+
+    assert_eq!(queue.position(), time::Duration::from_secs(0));
+    assert_eq!(queue.duration_of_current(), None);
+
+    queue.play().unwrap();
+    let reported = wait_for_position(&events, time::Duration::from_millis(1000));
+
+    let position = queue.position();
+    assert!(position >= reported);
+    assert!(position <= reported + time::Duration::from_millis(300));
+
+    let duration = queue.duration_of_current().unwrap();
+    assert!(duration >= time::Duration::from_millis(2500));
+    assert!(duration <= time::Duration::from_millis(2700));
+}
+
+#[test]
+fn seek() {
+    let mut queue = set_up_longer_queue(korama::NullBackend::new());
+    let events = queue.subscribe();
+
+    queue.play().unwrap();
+    assert_eq!(next_event(&events), "TrackStarted: Test");
+    let sought = time::Instant::now();
+    queue.seek(time::Duration::from_millis(2000));
+    wait_for_position(&events, time::Duration::from_millis(2000));
+
+    assert!(queue.position() >= time::Duration::from_millis(2000));
+
+    // The rest of the first track should be over quickly
+    assert_eq!(next_event(&events), "TrackFinished");
+    assert_eq!(next_event(&events), "TrackStarted: Test2");
+    assert!(sought.elapsed() < time::Duration::from_millis(1500));
+    assert_eq!(queue.get_history().len(), 2);
+}
+
+#[test]
+fn seek_relative() {
+    let mut queue = set_up_longer_queue(korama::NullBackend::new());
+    let events = queue.subscribe();
+
+    queue.play().unwrap();
+    wait_for_position(&events, time::Duration::from_millis(1000));
+    queue.seek_relative(-5000);
+    wait_until(|| queue.position() <= time::Duration::from_millis(500));
+
+    queue.seek_relative(1500);
+    wait_for_position(&events, time::Duration::from_millis(1500));
+
+    assert!(queue.position() >= time::Duration::from_millis(1500));
+    assert_eq!(queue.get_history().len(), 1);
+}
+
+#[test]
+fn seek_relative_by_extreme_offsets() {
+    let mut queue = set_up_longer_queue(korama::NullBackend::new());
+    let events = queue.subscribe();
+
+    queue.play().unwrap();
+    assert_eq!(next_event(&events), "TrackStarted: Test");
+    queue.seek_relative(i64::MIN);
+    wait_until(|| queue.position() == time::Duration::from_secs(0));
+    queue.seek_relative(i64::MAX);
+
+    // The player is still there to finish the track and move on
+    assert_eq!(next_event(&events), "TrackFinished");
+    assert_eq!(next_event(&events), "TrackStarted: Test2");
+}
+
+#[test]
+fn seek_while_paused() {
+    let mut queue = set_up_longer_queue(korama::NullBackend::new());
+    let events = queue.subscribe();
+
+    queue.play().unwrap();
+    assert_eq!(next_event(&events), "TrackStarted: Test");
+    queue.pause();
+    queue.seek(time::Duration::from_millis(1500));
+    assert_eq!(next_event(&events), "Paused");
+    // Seeking doesn't say when it's done
+    wait_until(|| queue.position() == time::Duration::from_millis(1500));
+
+    assert!(queue.is_paused());
+    assert!(events.recv_timeout(time::Duration::from_millis(300)).is_err());
+    assert_eq!(queue.position(), time::Duration::from_millis(1500));
+}
+
+#[test]
+fn events_while_playing() {
+    let mut queue = set_up_longer_queue(korama::NullBackend::new());
+    let events = queue.subscribe();
+
+    queue.play().unwrap();
+
+    let received = wait_for_stop(&events);
+
+    let expected = vec![
+        String::from("TrackStarted: Test"),
+        String::from("TrackFinished"),
+        String::from("TrackStarted: Test2"),
+        String::from("TrackFinished"),
+        String::from("PlaylistExhausted"),
+        String::from("Stopped"),
+    ];
+    let without_progress: Vec<String> = received.iter()
+        .filter(|event| event.as_str() != "Progress")
+        .cloned()
+        .collect();
+
+    assert_eq!(without_progress, expected);
+    assert!(received.contains(&String::from("Progress")));
+}
+
+#[test]
+fn events_for_pause_and_resume() {
+    let mut queue = set_up_longer_queue(korama::NullBackend::new());
+    let events = queue.subscribe();
+
+    queue.play().unwrap();
+    assert_eq!(next_event(&events), "TrackStarted: Test");
+    queue.pause();
+    assert_eq!(next_event(&events), "Paused");
+    queue.resume();
+    assert_eq!(next_event(&events), "Resumed");
+
+    let received = wait_for_stop(&events);
+    assert_eq!(received.last().unwrap(), "Stopped");
+}
+
+#[test]
+fn events_for_undecodable_track() {
+    let mut playlist = korama::Playlist::new(String::from("Test playlist for queue"));
+    playlist.add_track(get_missing_track());
+
+    let mut queue = korama::Queue::with_backend(korama::NullBackend::new());
+    queue.use_playlist(playlist);
+    let events = queue.subscribe();
+
+    queue.play().unwrap();
+
+    let received = wait_for_stop(&events);
+
+    let expected = vec![
+        String::from("DecodeError: Not a real track"),
+        String::from("PlaylistExhausted"),
+        String::from("Stopped"),
+    ];
+    assert_eq!(received, expected);
+    assert!(!queue.is_playing());
+}
+
+#[test]
+fn skip_undecodable_tracks() {
+    let mut library = set_up_test_library(String::from("longer"));
+    library.scan().unwrap();
+
+    let mut playlist = korama::Playlist::new(String::from("Test playlist for queue"));
+    for track in library.get_tracks_by_title() {
+        playlist.add_track(get_missing_track());
+        playlist.add_track(track.clone());
+    };
+
+    let mut queue = korama::Queue::with_backend(korama::NullBackend::new());
+    queue.use_playlist(playlist);
+
+    queue.play().unwrap();
+
+    while queue.is_playing() {
+      thread::sleep(time::Duration::from_millis(50));
+    };
+
+    let result: Vec<(String, bool)> = queue.get_history_entries().iter()
+        .map(|entry| (entry.track.track_name.clone(), entry.failed))
+        .collect();
+    let expected = vec![
+        (String::from("Not a real track"), true),
+        (String::from("Test"), false),
+        (String::from("Not a real track"), true),
+        (String::from("Test2"), false),
+    ];
+
+    assert_eq!(result, expected);
+}
+
+#[test]
+fn stop_after_too_many_undecodable_tracks() {
+    let mut library = set_up_test_library(String::from("longer"));
+    library.scan().unwrap();
+
+    let mut playlist = korama::Playlist::new(String::from("Test playlist for queue"));
+    playlist.add_track(get_missing_track());
+    playlist.add_track(get_missing_track());
+    playlist.add_track(get_missing_track());
+    for track in library.get_tracks_by_title() {
+        playlist.add_track(track.clone());
+    };
+
+    let mut queue = korama::Queue::with_backend(korama::NullBackend::new());
+    queue.use_playlist(playlist);
+    queue.set_max_consecutive_failures(2);
+
+    queue.play().unwrap();
+
+    while queue.is_playing() {
+      thread::sleep(time::Duration::from_millis(50));
+    };
+
+    let history = queue.get_history_entries();
+    assert_eq!(history.len(), 2);
+    assert!(history.iter().all(|entry| entry.failed));
+}
+
+fn get_missing_track() -> korama::Track {
+    korama::Track{
+        track_name: String::from("Not a real track"),
+        artist: String::from("Really not"),
+        album: String::from("It doesn't exist"),
+        track_number: String::from("e"),
+        path: String::from("/not/real/at/all"),
+        ..Default::default()
+    }
+}
+
+fn describe(event: &korama::QueueEvent) -> String {
+    match event {
+        korama::QueueEvent::TrackStarted(track) => format!("TrackStarted: {}", track.track_name),
+        korama::QueueEvent::TrackFinished => String::from("TrackFinished"),
+        korama::QueueEvent::Paused => String::from("Paused"),
+        korama::QueueEvent::Resumed => String::from("Resumed"),
+        korama::QueueEvent::Stopped => String::from("Stopped"),
+        korama::QueueEvent::PlaylistExhausted => String::from("PlaylistExhausted"),
+        korama::QueueEvent::DecodeError { track, .. } => format!("DecodeError: {}", track.track_name),
+        korama::QueueEvent::Progress { .. } => String::from("Progress"),
+    }
+}
+
+fn wait_for_stop(events: &mpsc::Receiver<korama::QueueEvent>) -> Vec<String> {
+    let mut received = Vec::new();
+    loop {
+        let event = events.recv_timeout(time::Duration::from_secs(10)).unwrap();
+        received.push(describe(&event));
+        if event == korama::QueueEvent::Stopped {
+            return received;
+        };
+    };
+}
+
+// The next event that isn't just progress
+fn next_event(events: &mpsc::Receiver<korama::QueueEvent>) -> String {
+    loop {
+        match events.recv_timeout(time::Duration::from_secs(10)).unwrap() {
+            korama::QueueEvent::Progress { .. } => (),
+            event => return describe(&event),
+        };
+    };
+}
+
+// Waits for the player to say it has got at least this far through a track,
+// giving how far it said
+fn wait_for_position(events: &mpsc::Receiver<korama::QueueEvent>, wanted: time::Duration) -> time::Duration {
+    loop {
+        if let korama::QueueEvent::Progress { position, .. } = events.recv_timeout(time::Duration::from_secs(10)).unwrap() {
+            if position >= wanted {
+                return position;
+            };
+        };
+    };
+}
+
+// For changes the player doesn't send events for
+fn wait_until<F: Fn() -> bool>(condition: F) {
+    let started = time::Instant::now();
+    while ! condition() {
+        assert!(started.elapsed() < time::Duration::from_secs(10), "Gave up waiting");
+        thread::sleep(time::Duration::from_millis(10));
+    };
+}
+
+fn set_up_longer_queue(backend: korama::NullBackend) -> korama::Queue {
+    let mut library = set_up_test_library(String::from("longer"));
+    library.scan().unwrap();
+
+    let mut playlist = korama::Playlist::new(String::from("Test playlist for queue"));
+    for track in library.get_tracks_by_title() {
+        playlist.add_track(track.clone());
+    };
+
+    let mut queue = korama::Queue::with_backend(backend);
+    queue.use_playlist(playlist);
+
+    queue
+}
+
 fn generate_track_output(tracks: Vec<korama::Track>) -> String {
     let mut output = String::from("");
     output.push_str("Found ");
@@ -0,0 +1,110 @@
+use quickcheck::{Arbitrary, Gen};
+use quickcheck_macros::quickcheck;
+use rand::Rng;
+use std::fs::remove_file;
+use std::path::PathBuf;
+use std::time::Duration;
+use korama;
+use korama::Saveable;
+
+// Text that is often made up of the characters save files are split on
+#[derive(Clone, Debug)]
+struct SaveText(String);
+
+impl Arbitrary for SaveText {
+    fn arbitrary<G: Gen>(g: &mut G) -> SaveText {
+        let awkward = ['\u{1b}', '\u{1c}', '\u{1d}', '\u{1e}', '\u{1f}', '=', ',', '\\'];
+        let len = g.gen_range(0, g.size() + 1);
+        let text = (0..len).map(|_| if g.gen() {
+            awkward[g.gen_range(0, awkward.len())]
+        } else {
+            char::arbitrary(g)
+        }).collect();
+        SaveText(text)
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = SaveText>> {
+        Box::new(self.0.shrink().map(SaveText))
+    }
+}
+
+#[quickcheck]
+fn track_round_trips(text: Vec<SaveText>, numbers: (Option<u32>, Option<u32>, Option<u32>, Option<u16>)) -> bool {
+    let mut text = text.into_iter().map(|text| text.0).chain(std::iter::repeat(String::new()));
+    let mut next_text = || text.next().unwrap();
+    let (duration_ms, bitrate, sample_rate, channels) = numbers;
+    let track = korama::Track{
+        track_name: next_text(),
+        artist: next_text(),
+        album: next_text(),
+        track_number: next_text(),
+        path: next_text(),
+        inferred: vec!(korama::TrackField::Artist, korama::TrackField::TrackNumber),
+        duration: duration_ms.map(|duration_ms| Duration::from_millis(duration_ms as u64)),
+        year: next_text(),
+        genre: next_text(),
+        disc_number: next_text(),
+        disc_total: next_text(),
+        album_artist: next_text(),
+        composer: next_text(),
+        bitrate,
+        sample_rate,
+        channels,
+    };
+
+    match korama::Track::load(track.dump()) {
+        // Equality only covers some fields, so check the rest by saving again
+        Ok(loaded) => loaded == track && loaded.dump() == track.dump() && loaded.inferred == track.inferred,
+        Err(_) => false,
+    }
+}
+
+#[quickcheck]
+fn playlist_round_trips(artist: SaveText, track_names: Vec<SaveText>) -> bool {
+    let mut saved_playlist_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    saved_playlist_path.push("resources/test/playlist/saved_playlists");
+    let saved_playlist_path = saved_playlist_path.to_str().unwrap().to_string();
+
+    // The name is used for the file name, so only the tracks can be awkward
+    let mut playlist = korama::Playlist::new(String::from("Round trip"));
+    for track_name in &track_names {
+        playlist.add_track(korama::Track{
+            track_name: track_name.0.clone(),
+            artist: artist.0.clone(),
+            ..Default::default()
+        });
+    }
+    playlist.save(saved_playlist_path.clone()).unwrap();
+    let loaded = korama::Playlist::load(saved_playlist_path.clone(), playlist.get_name().to_string());
+    remove_file(format!("{}/{}.playlist", &saved_playlist_path, playlist.get_name())).unwrap();
+    // Left by the save before
+    remove_file(format!("{}/{}.playlist.bak", &saved_playlist_path, playlist.get_name())).ok();
+
+    match loaded {
+        Ok(loaded) => loaded.get_tracks() == playlist.get_tracks(),
+        Err(_) => false,
+    }
+}
+
+#[test]
+fn load_track_with_delimiters() {
+    let track = korama::Track{
+        track_name: String::from("Split\u{1f}by\u{1e}every\u{1d}delimiter\u{1b}"),
+        artist: String::from("Artist"),
+        ..Default::default()
+    };
+    let dumped = track.dump();
+    assert_eq!(dumped, "title=Split\u{1b}\u{1f}by\u{1b}\u{1e}every\u{1b}\u{1d}delimiter\u{1b}\u{1b}\u{1f}artist=Artist\u{1e}");
+
+    let loaded = korama::Track::load(dumped).unwrap();
+    assert_eq!(loaded.track_name, track.track_name);
+    assert_eq!(loaded.artist, track.artist);
+}
+
+#[test]
+fn load_track_cut_short_after_escape() {
+    match korama::Track::load(String::from("title=Cut short\u{1b}")) {
+        Err(korama::Error::CorruptSaveFile { offset, .. }) => assert_eq!(offset, 16),
+        _ => panic!("Expected a corrupt save file error."),
+    };
+}
@@ -0,0 +1,99 @@
+#![cfg(feature = "watch")]
+use std::fs::{copy, create_dir_all, remove_dir_all, remove_file};
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::time::Duration;
+use korama;
+
+#[test]
+fn watch_library_for_changes() {
+    let mut library_path = std::env::temp_dir();
+    library_path.push(format!("korama_watch_{}", std::process::id()));
+    create_dir_all(&library_path).unwrap();
+    let library_dir = library_path.to_str().unwrap().to_string();
+    let in_library = |name: &str| format!("{}/{}", &library_dir, name);
+
+    copy(get_format_track_path("flac_track.flac"), in_library("flac_track.flac")).unwrap();
+
+    let library = korama::MusicLibrary::new(String::from("Watched library"), library_dir.clone());
+    let watcher = korama::LibraryWatcher::with_delay(library, Duration::from_millis(200)).unwrap();
+    let events = watcher.subscribe();
+
+    // Files already there are picked up when watching starts
+    assert_eq!(watcher.get_library().get_tracks_by_title().len(), 1);
+
+    copy(get_format_track_path("wav_track.wav"), in_library("wav_track.wav")).unwrap();
+    match wait_for_event(&events) {
+        korama::LibraryEvent::TrackAdded(track) => assert_eq!(track.track_name, "Wav title"),
+        _ => panic!("Expected a track to be added."),
+    };
+
+    remove_file(in_library("flac_track.flac")).unwrap();
+    match wait_for_event(&events) {
+        korama::LibraryEvent::TrackRemoved(path) => assert_eq!(path, in_library("flac_track.flac")),
+        _ => panic!("Expected a track to be removed."),
+    };
+
+    let library = watcher.into_library();
+    remove_dir_all(&library_path).unwrap();
+
+    let titles: Vec<String> = library.get_tracks_by_title().into_iter()
+        .map(|track| track.track_name)
+        .collect();
+    assert_eq!(titles, vec!(String::from("Wav title")));
+}
+
+// Changes are reported with the real path, which the library's tracks don't use
+#[cfg(unix)]
+#[test]
+fn watch_library_through_link() {
+    let mut real_path = std::env::temp_dir();
+    real_path.push(format!("korama_watch_real_{}", std::process::id()));
+    create_dir_all(&real_path).unwrap();
+    let mut library_path = std::env::temp_dir();
+    library_path.push(format!("korama_watch_link_{}", std::process::id()));
+    std::os::unix::fs::symlink(&real_path, &library_path).unwrap();
+    let library_dir = library_path.to_str().unwrap().to_string();
+    let in_library = |name: &str| format!("{}/{}", &library_dir, name);
+
+    copy(get_format_track_path("flac_track.flac"), in_library("flac_track.flac")).unwrap();
+
+    let library = korama::MusicLibrary::new(String::from("Linked library"), library_dir.clone());
+    let watcher = korama::LibraryWatcher::with_delay(library, Duration::from_millis(200)).unwrap();
+    let events = watcher.subscribe();
+
+    copy(get_format_track_path("wav_track.wav"), in_library("wav_track.wav")).unwrap();
+    match wait_for_event(&events) {
+        korama::LibraryEvent::TrackAdded(track) => assert_eq!(track.path, in_library("wav_track.wav")),
+        _ => panic!("Expected a track to be added."),
+    };
+
+    remove_file(in_library("flac_track.flac")).unwrap();
+    match wait_for_event(&events) {
+        korama::LibraryEvent::TrackRemoved(path) => assert_eq!(path, in_library("flac_track.flac")),
+        _ => panic!("Expected a track to be removed."),
+    };
+
+    let library = watcher.into_library();
+    remove_file(&library_path).unwrap();
+    remove_dir_all(&real_path).unwrap();
+
+    let paths: Vec<String> = library.get_tracks_by_title().into_iter()
+        .map(|track| track.path)
+        .collect();
+    assert_eq!(paths, vec!(in_library("wav_track.wav")));
+}
+
+fn wait_for_event(events: &mpsc::Receiver<korama::LibraryEvent>) -> korama::LibraryEvent {
+    match events.recv_timeout(Duration::from_secs(10)) {
+        Ok(event) => event,
+        Err(_) => panic!("No library event was sent."),
+    }
+}
+
+fn get_format_track_path(rel_path: &str) -> String {
+    let mut file_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    file_path.push("resources/test/formats");
+    file_path.push(rel_path);
+    file_path.to_str().unwrap().to_string()
+}